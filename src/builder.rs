@@ -33,14 +33,55 @@
 //! # Ok(())}
 //! ```
 
-use std::path::PathBuf;
+use std::{collections::HashMap, fmt::Debug, path::PathBuf, sync::Arc, time::Duration};
 
 use crate::{
     error::{BevyPNError, Result},
     ChatPlugin,
 };
-use bevy::prelude::{Color, Transform};
+use bevy::{
+    prelude::{Color, KeyCode, Rect, Transform, Vec2},
+    render::view::RenderLayers,
+    text::Font,
+};
 use derive_builder::Builder;
+use pubnub::core::{PubNubError, Transport, TransportRequest, TransportResponse};
+
+/// Default capacity of the sets tracking users and messages.
+pub(crate) const DEFAULT_TRACKING_CAPACITY: usize = 1000;
+
+/// Default color of the messages mentioning the local user.
+pub(crate) const DEFAULT_MENTION_COLOR: Color = Color::rgb(1.0, 0.6, 0.2);
+
+/// Default color of the links in the messages.
+pub(crate) const DEFAULT_LINK_COLOR: Color = Color::rgb(0.4, 0.7, 1.0);
+
+/// Default format of the `show_timestamps` column.
+pub(crate) const DEFAULT_TIMESTAMP_FORMAT: &str = "%H:%M";
+
+/// Default number of sent messages recalled with the Up and Down keys.
+pub(crate) const DEFAULT_HISTORY_SIZE: usize = 50;
+
+/// Default time after which PubNub considers a silent user offline.
+pub(crate) const DEFAULT_PRESENCE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Shortest presence timeout accepted by PubNub.
+const MIN_PRESENCE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Default delay between subscribe requests returning immediately without messages.
+pub(crate) const DEFAULT_MIN_POLL_DELAY: Duration = Duration::from_millis(250);
+
+/// Default PubNub origin.
+pub(crate) const DEFAULT_ORIGIN: &str = "ps.pndsn.com";
+
+/// Default timeout of the requests sent to PubNub, subscribe waits longer.
+pub(crate) const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default line height relative to the message font size.
+pub(crate) const DEFAULT_LINE_SPACING: f32 = 1.2;
+
+/// Default limit of the published message length, PubNub rejects larger messages.
+pub(crate) const DEFAULT_MAX_MESSAGE_LENGTH: usize = 32 * 1024;
 
 /// This struct is a config for [`ChatPlugin`].
 ///
@@ -77,9 +118,31 @@ pub struct ChatPluginConfig {
     #[builder(setter(custom))]
     pub(crate) keyset: Keyset<String>,
 
-    /// The channel to use.
-    #[builder(setter(into), default = "\"bevy-pn-chat\".into()")]
-    pub(crate) channel: String,
+    /// The channels to use.
+    /// Defaults to `["bevy-pn-chat"]`, or no channels if channel groups are set.
+    ///
+    /// Messages are received from all of them and published to the first one.
+    /// Without channels, e.g. when only groups are subscribed, messages cannot be published.
+    #[builder(setter(custom), default = "vec![\"bevy-pn-chat\".into()]")]
+    pub(crate) channels: Vec<String>,
+
+    /// The channel groups to subscribe to.
+    /// Defaults to an empty list.
+    ///
+    /// Messages from all channels in the groups are received,
+    /// but they are still published to the first channel.
+    /// Subscribing only to groups leaves out the default channel.
+    #[builder(setter(custom), default)]
+    pub(crate) channel_groups: Vec<String>,
+
+    /// Server-side filter of the received messages, like `"context == 'lobby'"`.
+    /// Defaults to `None` which receives all messages.
+    ///
+    /// Only messages whose metadata matches the expression are delivered.
+    /// See the PubNub stream filter documentation for the syntax.
+    /// It can be replaced at runtime with the `SetFilterExpression` event.
+    #[builder(setter(into, strip_option), default)]
+    pub(crate) filter_expression: Option<String>,
 
     /// The username to use.
     #[builder(setter(into), default = "\"anonymous\".into()")]
@@ -96,6 +159,19 @@ pub struct ChatPluginConfig {
     #[builder(setter(strip_option), default)]
     pub(crate) max_messages: Option<usize>,
 
+    /// Height of a message row relative to the message font size.
+    /// Defaults to `1.2`.
+    #[builder(default = "DEFAULT_LINE_SPACING")]
+    pub(crate) line_spacing: f32,
+
+    /// Maximum number of message rows shown at once.
+    /// Defaults to `None` which fills the chat bounds.
+    ///
+    /// The older rows are hidden, not despawned, so they can be scrolled back to.
+    /// With [`RenderMode::BevyUi`] the messages panel is clipped to the chat bounds instead.
+    #[builder(setter(strip_option), default)]
+    pub(crate) max_visible_lines: Option<usize>,
+
     /// Text style for the input box.
     /// Defaults to `TextStyle::default()`.
     ///
@@ -116,6 +192,28 @@ pub struct ChatPluginConfig {
     #[builder(default)]
     pub(crate) message_style: TextStyle,
 
+    /// Text style for the messages sent by the local user.
+    /// Defaults to `None` which renders them with the `message_style`.
+    ///
+    /// It overrides the `color_by_user` color, so the own messages stand out.
+    #[builder(setter(into, strip_option), default)]
+    pub(crate) own_message_style: Option<TextStyle>,
+
+    /// Text style for the system messages sent with the `SystemMessage` event.
+    /// Defaults to `None` which renders them with the dimmed `message_style`.
+    ///
+    /// Bevy has no italic flag, set the `font_path` to an italic font to get one.
+    #[builder(setter(into, strip_option), default)]
+    pub(crate) system_message_style: Option<TextStyle>,
+
+    /// Font data used for all chat text instead of the `font_path` files.
+    /// Defaults to `None` which loads the fonts from the `font_path` of the styles.
+    ///
+    /// Embed the font, e.g. with `include_bytes!`, to ship the game as a single binary.
+    /// The embedded font wins over the font paths.
+    #[builder(setter(into, strip_option), default)]
+    pub(crate) font_bytes: Option<Vec<u8>>,
+
     /// Message format.
     /// Defaults to `"{username}: {message}"`.
     /// The following placeholders are available:
@@ -126,12 +224,19 @@ pub struct ChatPluginConfig {
     /// - `{datetime}`: the date and time the message was sent
     /// - `{timestamp}`: the timestamp the message was sent
     /// - `{channel}`: the channel the message was sent to
+    /// - `{group}`: the channel group the message was received through, or the channel
+    /// - `{relative}`: how long ago the message was sent, e.g. `5m ago`
+    ///
+    /// The `{relative}` placeholder is refreshed periodically
+    /// and its strings can be changed with [`ChatStrings`].
     #[builder(setter(into), default = "\"{username}: {message}\".into()")]
     pub(crate) message_format: String,
 
     /// Transform of the chat window.
     /// Defaults to `Transform::from_xyz(0.0, 0.0, 0.0)`.
     ///
+    /// The input box and the message stack are placed relative to its translation.
+    ///
     /// See bevy [`Transform`] for more information.
     ///
     /// [`Transform`]: https://docs.rs/bevy/0.5.0/bevy/prelude/struct.Transform.html
@@ -148,6 +253,427 @@ pub struct ChatPluginConfig {
     /// If the width or height is set to `0`, the width or height will be set to `1`.
     #[builder(default = "(500.0, 500.0)")]
     pub(crate) bounds: (f32, f32),
+
+    /// Region of the messages relative to the chat `transform`.
+    /// Defaults to `None` which uses the `bounds` above the input box.
+    ///
+    /// Messages are stacked from the bottom of the region, wrapped to its width
+    /// and hidden once they scroll out of it.
+    #[builder(setter(strip_option), default)]
+    pub(crate) chat_bounds: Option<Rect>,
+
+    /// Position of the input box relative to the chat `transform`.
+    /// Defaults to `Vec2::new(30.0, 30.0)`.
+    ///
+    /// With [`RenderMode::BevyUi`] it is the offset from the bottom left corner of the window.
+    #[builder(default = "Vec2::new(30.0, 30.0)")]
+    pub(crate) input_position: Vec2,
+
+    /// Track the users present in the chat channel.
+    /// Defaults to `false`.
+    ///
+    /// The plugin fetches the current users with `here_now` at startup
+    /// and subscribes to the presence channels to follow joins and leaves.
+    /// Presence has to be enabled for the keyset in the PubNub dashboard.
+    ///
+    /// The users are available in the `OnlineUsers` resource
+    /// and every change is announced with the `PresenceChanged` event.
+    #[builder(default)]
+    pub(crate) presence: bool,
+
+    /// Presence state attached to the local user, like `json!({ "status": "in-match" })`.
+    /// Defaults to `None`.
+    ///
+    /// It is only sent with `presence` enabled, at startup and with every subscribe,
+    /// so it is restored after a presence timeout.
+    /// The states of the other users are available in the `OnlineUsers` resource.
+    #[builder(setter(strip_option), default)]
+    pub(crate) presence_state: Option<serde_json::Value>,
+
+    /// How long PubNub waits for a heartbeat before it reports the user as timed out.
+    /// Defaults to `300` seconds, the shortest accepted value is `20` seconds.
+    #[builder(default = "DEFAULT_PRESENCE_TIMEOUT")]
+    pub(crate) presence_timeout: Duration,
+
+    /// How often a presence heartbeat is sent with `presence` enabled.
+    /// Defaults to `None` which sends it a second before half of the `presence_timeout`.
+    ///
+    /// Heartbeats keep an idle user online, the subscribe long-poll alone
+    /// does not when the connection is held by a proxy.
+    #[builder(setter(strip_option), default)]
+    pub(crate) heartbeat_interval: Option<Duration>,
+
+    /// Publish the messages as JSON objects with `text` and `meta` fields.
+    /// Defaults to `false` which publishes plain strings.
+    ///
+    /// The received `meta` is forwarded with the `MessageReceived` event,
+    /// so apps can attach data like the client version or a reply-to id.
+    /// Plain string messages from other clients are still accepted and have no `meta`.
+    #[builder(default)]
+    pub(crate) structured_messages: bool,
+
+    /// Send typing signals and track the users who are typing.
+    /// Defaults to `false`.
+    ///
+    /// Typing into the input box sends a PubNub signal at most every few seconds.
+    /// The users who are typing are available in the `TypingUsers` resource
+    /// and every change is announced with the `TypingChanged` event.
+    #[builder(default)]
+    pub(crate) typing_indicators: bool,
+
+    /// How presence occupancy is reported by PubNub.
+    /// Defaults to [`PresenceMode::Events`].
+    ///
+    /// See [`PresenceMode`] for more information.
+    #[builder(default)]
+    pub(crate) presence_mode: PresenceMode,
+
+    /// Strings displayed by the chat.
+    /// Defaults to `ChatStrings::default()`.
+    ///
+    /// See [`ChatStrings`] for more information.
+    #[builder(default)]
+    pub(crate) strings: ChatStrings,
+
+    /// Predicate deciding if a message can be published.
+    /// Defaults to `None` which allows every message.
+    ///
+    /// Rejected messages are not sent and a local notice is rendered instead.
+    #[builder(setter(custom), default)]
+    pub(crate) can_publish: Option<PublishPredicate>,
+
+    /// The longest message in bytes that can be published.
+    /// Defaults to `32768`, the PubNub message size limit.
+    ///
+    /// Longer messages are not published and a `ChatError` is sent instead.
+    /// A message typed into the input box stays there, so the user can shorten it.
+    #[builder(default = "DEFAULT_MAX_MESSAGE_LENGTH")]
+    pub(crate) max_message_length: usize,
+
+    /// Number of sent messages recalled with the Up and Down keys in the input box.
+    /// Defaults to `50`.
+    ///
+    /// `0` disables the input history.
+    #[builder(default = "DEFAULT_HISTORY_SIZE")]
+    pub(crate) history_size: usize,
+
+    /// Words masked with asterisks in the sent and received messages.
+    /// Defaults to an empty list which disables the filter.
+    ///
+    /// Words are matched whole and ignoring the case.
+    /// See [`word_filter_mode`](Self::word_filter_mode) for the outgoing messages.
+    #[builder(default)]
+    pub(crate) word_filter: Vec<String>,
+
+    /// What happens to outgoing messages containing a filtered word.
+    /// Defaults to [`FilterMode::Mask`].
+    ///
+    /// See [`FilterMode`] for more information.
+    #[builder(default)]
+    pub(crate) word_filter_mode: FilterMode,
+
+    /// Debounce window for rendering the received messages.
+    /// Defaults to `None` which renders messages immediately.
+    ///
+    /// Messages arriving within the window are rendered together in one batch,
+    /// which smooths bursty traffic like history catch-up.
+    #[builder(setter(strip_option), default)]
+    pub(crate) render_debounce: Option<Duration>,
+
+    /// How long to wait for any subscribe response before reconnecting.
+    /// Defaults to `None` which disables the watchdog.
+    ///
+    /// PubNub long-poll requests return at least every few minutes,
+    /// so the timeout should be longer than that (e.g. 320 seconds).
+    #[builder(setter(strip_option), default)]
+    pub(crate) watchdog_timeout: Option<Duration>,
+
+    /// Host name of the PubNub origin, like `"chat.example.pubnub.com"`.
+    /// Defaults to `"ps.pndsn.com"`.
+    ///
+    /// Custom origins are available to enterprise PubNub customers.
+    /// It is a bare host name, requests are always sent over HTTPS.
+    #[builder(setter(into), default = "DEFAULT_ORIGIN.into()")]
+    pub(crate) origin: String,
+
+    /// URL of the HTTP or SOCKS proxy, like `"http://proxy.example.com:8080"`.
+    /// Defaults to `None` which connects directly.
+    ///
+    /// All requests to PubNub go through the proxy, credentials can be given in the URL.
+    /// It is ignored in the browser, which uses the system proxy.
+    #[builder(setter(into, strip_option), default)]
+    pub(crate) proxy: Option<String>,
+
+    /// Transport sending all requests to PubNub instead of the built-in `reqwest` one.
+    /// Defaults to `None` which uses `reqwest`.
+    ///
+    /// The requests are relative to the origin, with the auth token already attached.
+    /// It is meant for tests and for integrating with the HTTP client of the app,
+    /// so the `origin`, `proxy` and `request_timeout` options are up to it.
+    #[builder(setter(custom), default)]
+    pub(crate) custom_transport: Option<CustomTransport>,
+
+    /// How long to wait for a response to a request sent to PubNub, like a publish.
+    /// Defaults to `10` seconds.
+    ///
+    /// Subscribe requests are held open by PubNub for a few minutes when there are no messages,
+    /// so they wait that long on top of this timeout.
+    /// A timed out request fails like any other network error.
+    /// It is not applied in the browser, which has its own timeouts.
+    #[builder(default = "DEFAULT_REQUEST_TIMEOUT")]
+    pub(crate) request_timeout: Duration,
+
+    /// The shortest delay between subscribe requests that keep returning immediately without messages.
+    /// Defaults to `250` milliseconds.
+    ///
+    /// The delay doubles, with jitter, while the requests keep returning immediately,
+    /// so a misbehaving proxy or server doesn't make the chat poll in a tight loop.
+    /// Responses with messages are followed by the next request right away.
+    #[builder(default = "DEFAULT_MIN_POLL_DELAY")]
+    pub(crate) min_poll_delay: Duration,
+
+    /// Publish every message with POST and a JSON body.
+    /// Defaults to `false`.
+    ///
+    /// Even when disabled, long messages and messages with characters
+    /// awkward to encode in a URL are published with POST.
+    #[builder(default)]
+    pub(crate) publish_via_post: bool,
+
+    /// Normalize the received messages before rendering them.
+    /// Defaults to `false`.
+    ///
+    /// Line endings are converted to `\n`, trailing whitespace and leading
+    /// empty lines are removed. Only the rendered text is affected,
+    /// the received payload stays untouched.
+    #[builder(default)]
+    pub(crate) normalize_incoming: bool,
+
+    /// Keep the unsent input when the input box is despawned.
+    /// Defaults to `false`.
+    ///
+    /// The draft is restored as soon as a new input box is spawned,
+    /// e.g. when the chat UI is rebuilt after a scene change.
+    #[builder(default)]
+    pub(crate) preserve_draft: bool,
+
+    /// Publish messages as `{ "sender": "<username>", "text": "<message>" }` objects.
+    /// Defaults to `false`.
+    ///
+    /// Received messages always prefer the embedded `sender` over the publisher uuid,
+    /// which helps when other clients or backends don't propagate the uuid.
+    ///
+    /// # Warning
+    ///
+    /// Clients expecting plain string payloads will display the raw object.
+    /// Enable it only if every client on the channel understands this format.
+    #[builder(default)]
+    pub(crate) embed_sender_in_payload: bool,
+
+    /// Show the raw received envelope under every message.
+    /// Defaults to `false`.
+    ///
+    /// The envelope is also logged with the `trace` level.
+    /// Useful to diagnose messages coming from custom publishers.
+    #[builder(default)]
+    pub(crate) debug_raw_payloads: bool,
+
+    /// How the chat is rendered.
+    /// Defaults to [`RenderMode::Text2d`].
+    ///
+    /// See [`RenderMode`] for more information.
+    #[builder(default)]
+    pub(crate) render_mode: RenderMode,
+
+    /// How many entries each of the user, message and reaction tracking sets can hold.
+    /// Defaults to `1000`.
+    ///
+    /// The sets keep the most recently active entries and evict the oldest ones,
+    /// so memory stays bounded in long sessions with many transient users.
+    /// An evicted entry is forgotten, e.g. an evicted message showing up again
+    /// can be rendered again.
+    #[builder(default = "DEFAULT_TRACKING_CAPACITY")]
+    pub(crate) tracking_capacity: usize,
+
+    /// Skip the messages published by the local user when they come back from the subscription.
+    /// Defaults to `false`.
+    ///
+    /// The publisher is recognized by its user id, so messages sent from another
+    /// client with the same username are skipped as well.
+    #[builder(default)]
+    pub(crate) suppress_own_echo: bool,
+
+    /// Send `NotifyOnMessage` for the messages of the local user too.
+    /// Defaults to `false` which notifies only about the messages of other users.
+    #[builder(default)]
+    pub(crate) notify_on_own: bool,
+
+    /// Render the messages sent by the local user as soon as they are sent.
+    /// Defaults to `false` which renders them when they come back from the subscription.
+    ///
+    /// The copy coming back from PubNub is matched by the sender and the text,
+    /// so the message is rendered only once.
+    #[builder(default)]
+    pub(crate) local_echo: bool,
+
+    /// Color each message with a color derived from its sender instead of the `message_style` color.
+    /// Defaults to `false`.
+    ///
+    /// The same user always gets the same color.
+    #[builder(default)]
+    pub(crate) color_by_user: bool,
+
+    /// Render the messages of the local user right-aligned and the others left-aligned.
+    /// Defaults to `false` which left-aligns all messages.
+    #[builder(default)]
+    pub(crate) align_own_messages: bool,
+
+    /// Only count `@username` as a mention of the local user.
+    /// Defaults to `false` which matches the bare username.
+    ///
+    /// Mentions are matched ignoring the case and send a `MentionReceived` event.
+    #[builder(default)]
+    pub(crate) mention_requires_at: bool,
+
+    /// Color of the messages mentioning the local user.
+    /// Defaults to orange.
+    ///
+    /// It overrides the `message_style` and `color_by_user` colors.
+    #[builder(default = "DEFAULT_MENTION_COLOR")]
+    pub(crate) mention_color: Color,
+
+    /// Color of the `http://` and `https://` links in the messages.
+    /// Defaults to light blue.
+    ///
+    /// Clicking a link sends the `LinkClicked` event.
+    #[builder(default = "DEFAULT_LINK_COLOR")]
+    pub(crate) link_color: Color,
+
+    /// Color of the panel drawn behind the messages, usually semi-transparent.
+    /// Defaults to fully transparent which draws no panel.
+    ///
+    /// The panel covers the chat bounds and follows their changes.
+    #[builder(default = "Color::NONE")]
+    pub(crate) background_color: Color,
+
+    /// Render layers of the chat entities.
+    /// Defaults to `None` which keeps the default layer.
+    ///
+    /// Pair it with a camera on the same layers, like [`chat_overlay_camera`],
+    /// to draw the chat on top of a 3D scene without z-fighting.
+    /// Only applies to [`RenderMode::Text2d`], Bevy UI is drawn on top anyway.
+    ///
+    /// [`chat_overlay_camera`]: crate::chat_overlay_camera
+    #[builder(setter(strip_option), default)]
+    pub(crate) render_layer: Option<RenderLayers>,
+
+    /// How long subscribe requests have to keep failing before the chat is reported as disconnected.
+    /// Defaults to `Duration::ZERO` which reports the first failure.
+    ///
+    /// Any successful response resets the period,
+    /// so transient network errors don't flip [`ChatConnected`].
+    ///
+    /// [`ChatConnected`]: crate::ChatConnected
+    #[builder(default)]
+    pub(crate) disconnect_grace: Duration,
+
+    /// Prepend a `[HH:MM]` timestamp column to every message, independent of `message_format`.
+    /// Defaults to `false`.
+    ///
+    /// The time is taken from the message timetoken in the local time zone.
+    #[builder(default)]
+    pub(crate) show_timestamps: bool,
+
+    /// strftime-style format of the `show_timestamps` column, like `"%H:%M:%S"`.
+    /// Defaults to `"%H:%M"`.
+    #[builder(setter(into), default = "DEFAULT_TIMESTAMP_FORMAT.into()")]
+    pub(crate) timestamp_format: String,
+
+    /// Placeholder rendered while there are no messages, like `"No messages yet - say hi!"`.
+    /// Defaults to `None` which leaves the chat empty.
+    ///
+    /// The placeholder uses the `system_message_style`, removed when the first message arrives
+    /// and shown again once all messages are gone.
+    #[builder(setter(into, strip_option), default)]
+    pub(crate) empty_state_text: Option<String>,
+
+    /// Expand `:shortcode:`s, like `:smile:`, into emoji in the messages typed by the user.
+    /// Defaults to `false`.
+    ///
+    /// A small set of common shortcodes is built in, extend it with `custom_emoji`.
+    /// Unknown shortcodes are published as typed.
+    #[builder(default)]
+    pub(crate) emoji_shortcodes: bool,
+
+    /// Additional shortcodes expanded with `emoji_shortcodes` enabled.
+    /// Defaults to an empty map.
+    ///
+    /// The keys are the names without colons, like `"parrot"` for `:parrot:`.
+    /// They take precedence over the built-in shortcodes.
+    #[builder(default)]
+    pub(crate) custom_emoji: HashMap<String, String>,
+
+    /// Predicate deciding if a typed character is inserted into the input box.
+    /// Defaults to `None` which allows every character.
+    ///
+    /// Rejected characters are silently dropped, control characters are never typed.
+    /// See [`char_policy`] for the built-in policies.
+    #[builder(setter(custom), default)]
+    pub(crate) allowed_characters: Option<CharPredicate>,
+
+    /// Shows the chat without the built-in input box.
+    /// Defaults to `false`.
+    ///
+    /// Use it for a read-only feed or with a custom input UI,
+    /// messages can still be published with the [`SendMessage`] event.
+    ///
+    /// [`SendMessage`]: crate::SendMessage
+    #[builder(default)]
+    pub(crate) read_only: bool,
+
+    /// Key focusing the input box.
+    /// Defaults to `KeyCode::Return`.
+    ///
+    /// The key opening the chat is never typed into it. Escape unfocuses the input box.
+    #[builder(default = "KeyCode::Return")]
+    pub(crate) toggle_key: KeyCode,
+
+    /// Hint rendered in the empty input box, like `"Press Enter to chat"`.
+    /// Defaults to `None` which leaves the input box empty.
+    ///
+    /// The placeholder is dimmed and hidden as soon as the user types.
+    #[builder(setter(into, strip_option), default)]
+    pub(crate) placeholder: Option<String>,
+
+    /// Access Manager token granting the permissions of this client.
+    /// Defaults to `None` for keysets without Access Manager.
+    ///
+    /// It is attached to every request, including the ones sent by the PubNub client.
+    /// Tokens expire, send [`UpdateAuthToken`] with a fresh one.
+    ///
+    /// [`UpdateAuthToken`]: crate::UpdateAuthToken
+    #[builder(setter(into, strip_option), default)]
+    pub(crate) auth_token: Option<String>,
+
+    /// The secret key for the PubNub infrastructure.
+    /// Defaults to `None`.
+    ///
+    /// It is only needed for access manager operations, like granting permissions.
+    ///
+    /// # Warning
+    ///
+    /// Never ship the secret key in a game client, it gives full access to the keyset.
+    #[builder(setter(into, strip_option), default)]
+    pub(crate) secret_key: Option<String>,
+
+    /// Key used to encrypt published and decrypt received messages.
+    /// Defaults to `None` which sends messages in plain text.
+    ///
+    /// Messages are encrypted with AES-256-CBC the same way as in the PubNub SDKs,
+    /// so all chat participants have to use the same key.
+    /// Messages that cannot be decrypted are skipped.
+    #[builder(setter(into, strip_option), default)]
+    pub(crate) cipher_key: Option<String>,
 }
 
 impl ChatPluginConfigBuilder {
@@ -156,7 +682,16 @@ impl ChatPluginConfigBuilder {
     /// # Errors
     ///
     /// This method returns an error if the configuration is invalid.
-    pub fn build(self) -> Result<ChatPlugin> {
+    pub fn build(mut self) -> Result<ChatPlugin> {
+        let groups_only = self.channels.is_none()
+            && self
+                .channel_groups
+                .as_ref()
+                .map_or(false, |groups| !groups.is_empty());
+        if groups_only {
+            self.channels = Some(Vec::new());
+        }
+
         ChatPlugin::try_from(self.internal_build()?)
     }
 
@@ -173,6 +708,156 @@ impl ChatPluginConfigBuilder {
         self
     }
 
+    /// Adds a channel to subscribe to.
+    ///
+    /// The first added channel is also the one messages are published to.
+    pub fn channel(mut self, channel: impl Into<String>) -> Self {
+        self.channels
+            .get_or_insert_with(Vec::new)
+            .push(channel.into());
+
+        self
+    }
+
+    /// Adds a channel group to subscribe to.
+    ///
+    /// The channels are added to the group with the PubNub channel groups API,
+    /// e.g. from the game server.
+    pub fn channel_group(mut self, group: impl Into<String>) -> Self {
+        self.channel_groups
+            .get_or_insert_with(Vec::new)
+            .push(group.into());
+
+        self
+    }
+
+    /// Adds multiple channels to subscribe to.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bevy_pn_chat::{ChatPlugin, Keyset};
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let chat = ChatPlugin::builder()
+    ///     .keyset(Keyset {
+    ///         publish_key: "pub-c-...",
+    ///         subscribe_key: "sub-c-...",
+    ///     })
+    ///     .channels(["lobby", "team-red"])
+    ///     .build()?;
+    /// # Ok(())}
+    /// ```
+    pub fn channels<I>(mut self, channels: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.channels
+            .get_or_insert_with(Vec::new)
+            .extend(channels.into_iter().map(Into::into));
+
+        self
+    }
+
+    /// Predicate consulted before any message is published.
+    ///
+    /// If it returns `false`, the message is not sent and the notice
+    /// from [`ChatStrings::publish_rejected`] is rendered instead.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bevy_pn_chat::{ChatPlugin, Keyset};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let chat = ChatPlugin::builder()
+    ///             .keyset(Keyset{
+    ///                publish_key: "pub-c-...",
+    ///                subscribe_key: "sub-c-..."
+    ///             })
+    ///             .can_publish(|text| !text.contains("http"))
+    ///             .build()?;
+    /// # Ok(())}
+    /// ```
+    pub fn can_publish<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.can_publish = Some(Some(PublishPredicate(Arc::new(predicate))));
+
+        self
+    }
+
+    /// Predicate consulted before a typed character is inserted into the input box.
+    ///
+    /// If it returns `false`, the character is dropped.
+    /// The pasted text is filtered the same way.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bevy_pn_chat::{char_policy, ChatPlugin, Keyset};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let chat = ChatPlugin::builder()
+    ///             .keyset(Keyset{
+    ///                publish_key: "pub-c-...",
+    ///                subscribe_key: "sub-c-..."
+    ///             })
+    ///             .allowed_characters(char_policy::ascii_only)
+    ///             .build()?;
+    /// # Ok(())}
+    /// ```
+    pub fn allowed_characters<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(char) -> bool + Send + Sync + 'static,
+    {
+        self.allowed_characters = Some(Some(CharPredicate(Arc::new(predicate))));
+
+        self
+    }
+
+    /// Sends all requests to PubNub with the given transport.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bevy_pn_chat::{
+    ///     async_trait, ChatPlugin, Keyset, PubNubError, Transport, TransportRequest,
+    ///     TransportResponse,
+    /// };
+    ///
+    /// struct Offline;
+    ///
+    /// #[async_trait]
+    /// impl Transport for Offline {
+    ///     async fn send(&self, _: TransportRequest) -> Result<TransportResponse, PubNubError> {
+    ///         Ok(TransportResponse {
+    ///             status: 200,
+    ///             headers: [].into(),
+    ///             body: Some(br#"{"t":{"t":"0","r":1},"m":[]}"#.to_vec()),
+    ///         })
+    ///     }
+    /// }
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let chat = ChatPlugin::builder()
+    ///             .keyset(Keyset{
+    ///                publish_key: "pub-c-...",
+    ///                subscribe_key: "sub-c-..."
+    ///             })
+    ///             .custom_transport(Offline)
+    ///             .build()?;
+    /// # Ok(())}
+    /// ```
+    pub fn custom_transport<T>(mut self, transport: T) -> Self
+    where
+        T: Transport + Send + Sync + 'static,
+    {
+        self.custom_transport = Some(Some(CustomTransport(Arc::new(transport))));
+
+        self
+    }
+
     fn validate(&self) -> Result<()> {
         self.keyset
             .as_ref()
@@ -185,12 +870,42 @@ impl ChatPluginConfigBuilder {
             })
             .unwrap_or(Ok(()))?;
 
-        self.channel
+        let no_groups = self
+            .channel_groups
+            .as_ref()
+            .map_or(true, |groups| groups.is_empty());
+        self.channels
+            .as_ref()
+            .and_then(|channels| {
+                ((channels.is_empty() && no_groups) || channels.iter().any(String::is_empty)).then(
+                    || {
+                        Err(BevyPNError::Config {
+                            message: "Channel is empty".into(),
+                        })
+                    },
+                )
+            })
+            .unwrap_or(Ok(()))?;
+
+        self.channel_groups
             .as_ref()
-            .and_then(|channel| {
-                channel.is_empty().then(|| {
+            .and_then(|groups| {
+                groups.iter().any(String::is_empty).then(|| {
                     Err(BevyPNError::Config {
-                        message: "Channel is empty".into(),
+                        message: "Channel group is empty".into(),
+                    })
+                })
+            })
+            .unwrap_or(Ok(()))?;
+
+        self.filter_expression
+            .as_ref()
+            .and_then(|filter| {
+                filter.as_ref().and_then(|filter| {
+                    filter.trim().is_empty().then(|| {
+                        Err(BevyPNError::Config {
+                            message: "Filter expression is empty".into(),
+                        })
                     })
                 })
             })
@@ -218,6 +933,82 @@ impl ChatPluginConfigBuilder {
             })
             .unwrap_or(Ok(()))?;
 
+        self.origin
+            .as_ref()
+            .and_then(|origin| {
+                (origin.is_empty() || origin.contains(|c: char| c == '/' || c.is_whitespace()))
+                    .then(|| {
+                        Err(BevyPNError::Config {
+                            message: "Origin is not a host name".into(),
+                        })
+                    })
+            })
+            .unwrap_or(Ok(()))?;
+
+        self.line_spacing
+            .and_then(|spacing| {
+                (!spacing.is_finite() || spacing <= 0.0).then(|| {
+                    Err(BevyPNError::Config {
+                        message: "Line spacing is not positive".into(),
+                    })
+                })
+            })
+            .unwrap_or(Ok(()))?;
+
+        self.max_visible_lines
+            .flatten()
+            .and_then(|lines| {
+                (lines == 0).then(|| {
+                    Err(BevyPNError::Config {
+                        message: "Max visible lines is zero".into(),
+                    })
+                })
+            })
+            .unwrap_or(Ok(()))?;
+
+        self.presence_timeout
+            .and_then(|timeout| {
+                (timeout < MIN_PRESENCE_TIMEOUT).then(|| {
+                    Err(BevyPNError::Config {
+                        message: "Presence timeout is shorter than 20 seconds".into(),
+                    })
+                })
+            })
+            .unwrap_or(Ok(()))?;
+
+        self.heartbeat_interval
+            .flatten()
+            .and_then(|interval| {
+                interval.is_zero().then(|| {
+                    Err(BevyPNError::Config {
+                        message: "Heartbeat interval is zero".into(),
+                    })
+                })
+            })
+            .unwrap_or(Ok(()))?;
+
+        self.tracking_capacity
+            .and_then(|capacity| {
+                (capacity == 0).then(|| {
+                    Err(BevyPNError::Config {
+                        message: "Tracking capacity is zero".into(),
+                    })
+                })
+            })
+            .unwrap_or(Ok(()))?;
+
+        self.font_bytes
+            .as_ref()
+            .and_then(Option::as_ref)
+            .and_then(|bytes| {
+                Font::try_from_bytes(bytes.clone()).is_err().then(|| {
+                    Err(BevyPNError::Config {
+                        message: "Font bytes are not a valid font".into(),
+                    })
+                })
+            })
+            .unwrap_or(Ok(()))?;
+
         Ok(())
     }
 }
@@ -264,7 +1055,8 @@ pub struct TextStyle {
     ///
     /// # Warning
     ///
-    /// If not path provided, then your messages will not be displayed.
+    /// If not path provided, then your messages will not be displayed,
+    /// unless the font is embedded with the `font_bytes` option.
     pub font_path: PathBuf,
 
     /// The font size to use.
@@ -288,6 +1080,205 @@ impl Default for TextStyle {
     }
 }
 
+#[derive(Clone)]
+pub(crate) struct PublishPredicate(Arc<dyn Fn(&str) -> bool + Send + Sync>);
+
+impl PublishPredicate {
+    pub(crate) fn allows(&self, text: &str) -> bool {
+        (self.0)(text)
+    }
+}
+
+impl Debug for PublishPredicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PublishPredicate")
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct CharPredicate(Arc<dyn Fn(char) -> bool + Send + Sync>);
+
+impl CharPredicate {
+    pub(crate) fn allows(&self, character: char) -> bool {
+        (self.0)(character)
+    }
+}
+
+impl Debug for CharPredicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CharPredicate")
+    }
+}
+
+/// Built-in policies for the [`allowed_characters`] option.
+///
+/// [`allowed_characters`]: ChatPluginConfigBuilder::allowed_characters
+pub mod char_policy {
+    /// Allows the printable ASCII characters and the space.
+    pub fn ascii_only(character: char) -> bool {
+        character.is_ascii_graphic() || character == ' '
+    }
+
+    /// Allows the visible characters and the space.
+    ///
+    /// Other whitespace, like tabs or non-breaking spaces,
+    /// and invisible formatting characters, like zero-width spaces, are rejected.
+    pub fn printable(character: char) -> bool {
+        const INVISIBLE: [char; 7] = [
+            '\u{00AD}', '\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}', '\u{FEFF}', '\u{180E}',
+        ];
+
+        !character.is_control()
+            && (character == ' ' || !character.is_whitespace())
+            && !INVISIBLE.contains(&character)
+            && !('\u{200E}'..='\u{200F}').contains(&character)
+            && !('\u{202A}'..='\u{202E}').contains(&character)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct CustomTransport(Arc<dyn Transport + Send + Sync>);
+
+impl CustomTransport {
+    pub(crate) async fn send(
+        &self,
+        request: TransportRequest,
+    ) -> std::result::Result<TransportResponse, PubNubError> {
+        self.0.send(request).await
+    }
+}
+
+impl Debug for CustomTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CustomTransport")
+    }
+}
+
+/// This struct is used to localize the strings displayed by the [`ChatPlugin`].
+///
+/// The `{n}` placeholder is replaced with the number of elapsed units.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy_pn_chat::ChatStrings;
+///
+/// ChatStrings {
+///     just_now: "gerade eben".into(),
+///     minutes_ago: "vor {n} Min.".into(),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatStrings {
+    /// Displayed for messages younger than `just_now_threshold`.
+    pub just_now: String,
+
+    /// Displayed for messages younger than an hour.
+    pub minutes_ago: String,
+
+    /// Displayed for messages younger than a day.
+    pub hours_ago: String,
+
+    /// Displayed for older messages.
+    pub days_ago: String,
+
+    /// How long a message is considered to be sent "just now".
+    pub just_now_threshold: Duration,
+
+    /// Displayed when a message is rejected by the `can_publish` predicate.
+    pub publish_rejected: String,
+}
+
+impl Default for ChatStrings {
+    fn default() -> Self {
+        Self {
+            just_now: "just now".into(),
+            minutes_ago: "{n}m ago".into(),
+            hours_ago: "{n}h ago".into(),
+            days_ago: "{n}d ago".into(),
+            just_now_threshold: Duration::from_secs(60),
+            publish_rejected: "Message was not sent.".into(),
+        }
+    }
+}
+
+/// This enum is used to configure how the [`ChatPlugin`] handles presence.
+///
+/// The mode has to match the presence announce settings of your keyset
+/// in the PubNub dashboard.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PresenceMode {
+    /// Every join, leave and timeout is delivered as a separate event
+    /// and the online users list is updated incrementally.
+    ///
+    /// Best suited for small channels.
+    #[default]
+    Events,
+
+    /// Occupancy is reported periodically as a snapshot of the changes
+    /// since the last interval.
+    ///
+    /// Best suited for large channels, like game lobbies.
+    Interval,
+}
+
+/// This enum describes what happens to outgoing messages containing a filtered word.
+///
+/// Received messages are always masked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilterMode {
+    /// The filtered words are replaced with asterisks and the message is published.
+    #[default]
+    Mask,
+
+    /// The message is not published and a `ChatError` is sent instead.
+    Block,
+}
+
+/// This enum describes how the chat entities are rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Messages are world space `Text2d` entities stacked by the plugin.
+    ///
+    /// They are drawn by any 2D camera and move with it.
+    #[default]
+    Text2d,
+
+    /// Messages are `TextBundle` nodes in a flex column clipped to the chat bounds.
+    ///
+    /// Bevy UI lays out, wraps and clips the messages,
+    /// so the chat stays anchored to the window regardless of the camera.
+    BevyUi,
+}
+
+impl ChatPluginConfig {
+    /// The channel messages are published to, `None` if only channel groups are subscribed.
+    pub(crate) fn channel(&self) -> Option<&str> {
+        self.channels.first().map(String::as_str)
+    }
+
+    /// How often the presence heartbeat is sent.
+    pub(crate) fn heartbeat_period(&self) -> Duration {
+        self.heartbeat_interval
+            .unwrap_or_else(|| (self.presence_timeout / 2).saturating_sub(Duration::from_secs(1)))
+    }
+
+    pub(crate) fn can_publish(&self, text: &str) -> bool {
+        self.can_publish
+            .as_ref()
+            .map(|predicate| predicate.allows(text))
+            .unwrap_or(true)
+    }
+
+    pub(crate) fn allows_character(&self, character: char) -> bool {
+        self.allowed_characters
+            .as_ref()
+            .map(|predicate| predicate.allows(character))
+            .unwrap_or(true)
+    }
+}
+
 impl ChatPlugin {
     /// Creates a new [`ChatPluginBuilder`].
     ///
@@ -338,6 +1329,64 @@ mod should {
         assert!(chat.is_err());
     }
 
+    #[test]
+    fn validate_if_no_channels_are_given() {
+        let chat = ChatPluginConfigBuilder::default()
+            .channels(Vec::<String>::new())
+            .internal_build();
+
+        assert!(chat.is_err());
+    }
+
+    #[test]
+    fn collect_channels() {
+        let config = ChatPluginConfigBuilder::default()
+            .keyset(Keyset {
+                publish_key: "pub",
+                subscribe_key: "sub",
+            })
+            .channel("lobby")
+            .channels(["team-red", "team-blue"])
+            .internal_build()
+            .unwrap();
+
+        assert_eq!(config.channels, vec!["lobby", "team-red", "team-blue"]);
+        assert_eq!(config.channel(), Some("lobby"));
+    }
+
+    #[test]
+    fn allow_no_channels_with_channel_groups() {
+        let config = ChatPluginConfigBuilder::default()
+            .keyset(Keyset {
+                publish_key: "pub",
+                subscribe_key: "sub",
+            })
+            .channels(Vec::<String>::new())
+            .channel_group("g")
+            .internal_build()
+            .unwrap();
+
+        assert_eq!(config.channel(), None);
+    }
+
+    #[test]
+    fn validate_if_channel_group_is_empty() {
+        let chat = ChatPluginConfigBuilder::default()
+            .channel_group("")
+            .internal_build();
+
+        assert!(chat.is_err());
+    }
+
+    #[test]
+    fn validate_if_filter_expression_is_empty() {
+        let chat = ChatPluginConfigBuilder::default()
+            .filter_expression(" ")
+            .internal_build();
+
+        assert!(chat.is_err());
+    }
+
     #[test]
     fn validate_if_username_is_empty() {
         let chat = ChatPluginConfigBuilder::default()
@@ -347,6 +1396,132 @@ mod should {
         assert!(chat.is_err());
     }
 
+    #[test]
+    fn allow_publish_without_predicate() {
+        let config = ChatPluginConfigBuilder::default()
+            .keyset(Keyset {
+                publish_key: "pub",
+                subscribe_key: "sub",
+            })
+            .internal_build()
+            .unwrap();
+
+        assert!(config.can_publish("https://example.com"));
+    }
+
+    #[test]
+    fn consult_publish_predicate() {
+        let config = ChatPluginConfigBuilder::default()
+            .keyset(Keyset {
+                publish_key: "pub",
+                subscribe_key: "sub",
+            })
+            .can_publish(|text| !text.contains("http"))
+            .internal_build()
+            .unwrap();
+
+        assert!(config.can_publish("hello"));
+        assert!(!config.can_publish("https://example.com"));
+    }
+
+    #[test]
+    fn apply_built_in_character_policies() {
+        assert!(char_policy::ascii_only('a'));
+        assert!(char_policy::ascii_only(' '));
+        assert!(!char_policy::ascii_only('é'));
+        assert!(!char_policy::ascii_only('👍'));
+
+        assert!(char_policy::printable('é'));
+        assert!(char_policy::printable('👍'));
+        assert!(char_policy::printable(' '));
+        assert!(!char_policy::printable('\t'));
+        assert!(!char_policy::printable('\u{00A0}'));
+        assert!(!char_policy::printable('\u{200B}'));
+    }
+
+    #[test]
+    fn validate_if_font_bytes_are_not_a_font() {
+        let chat = ChatPluginConfigBuilder::default()
+            .keyset(Keyset {
+                publish_key: "pub",
+                subscribe_key: "sub",
+            })
+            .font_bytes(b"not a font".to_vec())
+            .internal_build();
+
+        assert!(chat.is_err());
+    }
+
+    #[test]
+    fn validate_if_tracking_capacity_is_zero() {
+        let chat = ChatPluginConfigBuilder::default()
+            .tracking_capacity(0)
+            .internal_build();
+
+        assert!(chat.is_err());
+    }
+
+    #[test]
+    fn validate_if_origin_is_empty() {
+        let chat = ChatPluginConfigBuilder::default()
+            .origin("")
+            .internal_build();
+
+        assert!(chat.is_err());
+    }
+
+    #[test]
+    fn validate_if_origin_is_not_a_host_name() {
+        let chat = ChatPluginConfigBuilder::default()
+            .origin("https://ps.pndsn.com")
+            .internal_build();
+
+        assert!(chat.is_err());
+    }
+
+    #[test]
+    fn validate_if_line_spacing_is_not_positive() {
+        [0.0, -1.0, f32::NAN].into_iter().for_each(|spacing| {
+            let chat = ChatPluginConfigBuilder::default()
+                .line_spacing(spacing)
+                .internal_build();
+
+            assert!(chat.is_err(), "{} accepted", spacing);
+        });
+    }
+
+    #[test]
+    fn validate_if_max_visible_lines_is_zero() {
+        let chat = ChatPluginConfigBuilder::default()
+            .max_visible_lines(0)
+            .internal_build();
+
+        assert!(chat.is_err());
+    }
+
+    #[test]
+    fn validate_if_presence_timeout_is_too_short() {
+        let chat = ChatPluginConfigBuilder::default()
+            .presence_timeout(Duration::from_secs(10))
+            .internal_build();
+
+        assert!(chat.is_err());
+    }
+
+    #[test]
+    fn derive_heartbeat_period_from_presence_timeout() {
+        let config = ChatPluginConfigBuilder::default()
+            .keyset(Keyset {
+                publish_key: "pub",
+                subscribe_key: "sub",
+            })
+            .presence_timeout(Duration::from_secs(60))
+            .internal_build()
+            .unwrap();
+
+        assert_eq!(config.heartbeat_period(), Duration::from_secs(29));
+    }
+
     #[test]
     fn validate_if_message_format_is_empty() {
         let chat = ChatPluginConfigBuilder::default()
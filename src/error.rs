@@ -22,6 +22,16 @@ pub enum BevyPNError {
         inner: PubNubError,
     },
 
+    /// This error is returned when PubNub rejects a request, e.g. with an Access Manager error.
+    #[snafu(display("PubNub responded with {status}: {message}!"))]
+    Server {
+        /// The HTTP status reported by PubNub.
+        status: u16,
+
+        /// The message reported by PubNub.
+        message: String,
+    },
+
     /// This error is returned when the empty body is received.
     #[snafu(display("Empty body received on {on}!"))]
     EmptyBody {
@@ -35,6 +45,85 @@ pub enum BevyPNError {
         /// The deserialize error.
         inner: serde_json::Error,
     },
+
+    /// This error is returned when a message is too long to be published.
+    #[snafu(display("Message is {length} bytes long, the limit is {max}!"))]
+    MessageTooLong {
+        /// The length of the message in bytes.
+        length: usize,
+
+        /// The configured limit in bytes.
+        max: usize,
+    },
+
+    /// This error is returned when a message contains a word blocked by the word filter.
+    #[snafu(display("Message contains the blocked word `{word}`!"))]
+    BlockedWord {
+        /// The blocked word as typed in the message.
+        word: String,
+    },
+
+    /// This error is returned when the input box holds an unknown slash command.
+    #[snafu(display("Unknown command `/{command}`!"))]
+    UnknownCommand {
+        /// The command name without the slash.
+        command: String,
+    },
+
+    /// This error is returned when a slash command is missing its argument.
+    #[snafu(display("Command `/{command}` needs {argument}!"))]
+    MissingArgument {
+        /// The command name without the slash.
+        command: String,
+
+        /// What the command expects, e.g. `a name`.
+        argument: String,
+    },
+
+    /// This error is reported when a chat text style has no font path.
+    #[snafu(display("No font path is set for the {text}, it won't be displayed!"))]
+    NoFontPath {
+        /// The text missing the font, e.g. `messages`.
+        text: String,
+    },
+
+    /// This error is reported when the font file cannot be loaded.
+    #[snafu(display("Font `{path}` cannot be loaded, the chat text won't be displayed!"))]
+    FontNotLoaded {
+        /// The configured font path.
+        path: String,
+    },
+
+    /// This error is reported when a message is sent while only channel groups are subscribed.
+    #[snafu(display("No channel to publish to, only channel groups are subscribed!"))]
+    NoPublishChannel,
+
+    /// This error is returned when a received message cannot be decrypted.
+    #[snafu(display("Decrypt error: {message}!"))]
+    Decrypt {
+        /// The error message.
+        message: String,
+    },
+}
+
+impl BevyPNError {
+    /// Short, human-readable kind of the error.
+    pub(crate) fn context(&self) -> &'static str {
+        match self {
+            BevyPNError::Config { .. } => "configuration error",
+            BevyPNError::PubNub { .. } => "network error",
+            BevyPNError::Server { .. } => "server error",
+            BevyPNError::EmptyBody { .. } => "empty response",
+            BevyPNError::Deserialize { .. } => "malformed response",
+            BevyPNError::MessageTooLong { .. } => "message too long",
+            BevyPNError::BlockedWord { .. } => "blocked word",
+            BevyPNError::UnknownCommand { .. } => "unknown command",
+            BevyPNError::MissingArgument { .. } => "invalid command",
+            BevyPNError::NoFontPath { .. } | BevyPNError::FontNotLoaded { .. } => "missing font",
+            BevyPNError::NoPublishChannel => "no channel",
+            BevyPNError::Decrypt { .. } => "decryption error",
+        }
+    }
 }
 
 impl From<derive_builder::UninitializedFieldError> for BevyPNError {
@@ -56,12 +56,30 @@
 
 #![deny(missing_docs)]
 
+pub use async_trait::async_trait;
 pub use bevy::prelude::Color;
-pub use builder::{Keyset, TextStyle};
+pub use builder::{
+    char_policy, ChatStrings, FilterMode, Keyset, PresenceMode, RenderMode, TextStyle,
+};
+pub use pubnub::core::{
+    PubNubError, Transport, TransportMethod, TransportRequest, TransportResponse,
+};
 pub mod builder;
 
 pub use error::BevyPNError;
 pub mod error;
 
-pub use plugin::ChatPlugin;
+pub use plugin::{
+    chat_overlay_camera,
+    events::{
+        ChatError, ChatFocusChanged, ClearChat, ConnectionChanged, HighlightMessage, LinkClicked,
+        MentionReceived, MessageReceived, NotifyOnMessage, PresenceChanged, PublishOutcome, React,
+        ScrollBy, ScrollToBottom, ScrollToTop, SendMessage, SendStructuredMessage,
+        SetFilterExpression, SetUsername, SwitchChannel, SystemMessage, TypingChanged,
+        UnreadBelowChanged, UpdateAuthToken,
+    },
+    ChatConnected, ChatFocus, ChatLog, ChatLogEntry, ChatPlugin, ChatPubNubClient, ChatTransport,
+    ConnectionState, HasUnreadBelow, OnlineUsers, PubNubClientResource, Reactions, ScrollState,
+    TypingUsers,
+};
 pub mod plugin;
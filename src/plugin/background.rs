@@ -0,0 +1,134 @@
+use bevy::{
+    prelude::{Color, Commands, Component, Query, Rect, Res, Transform, Vec3, With, Without},
+    sprite::{Sprite, SpriteBundle},
+    ui::{Size, Style, UiRect, Val},
+};
+
+use super::resources::{ChatBounds, ChatTransform};
+
+/// How far behind the messages the background is drawn.
+const BACKGROUND_DEPTH: f32 = 0.1;
+
+/// Marks the panel drawn behind the messages.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ChatBackground;
+
+/// Spawns the background sprite used with [`RenderMode::Text2d`].
+///
+/// Transparent backgrounds are not spawned at all.
+///
+/// [`RenderMode::Text2d`]: crate::RenderMode::Text2d
+pub fn spawn_background(commands: &mut Commands, color: Color, bounds: &Rect, anchor: &Transform) {
+    if color.a() <= 0.0 {
+        return;
+    }
+
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(bounds.size()),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(background_position(anchor.translation, bounds)),
+            ..Default::default()
+        },
+        ChatBackground,
+    ));
+}
+
+/// Center of the background behind the [`ChatBounds`].
+fn background_position(anchor: Vec3, bounds: &Rect) -> Vec3 {
+    anchor + bounds.center().extend(-BACKGROUND_DEPTH)
+}
+
+/// Follows the [`ChatBounds`] changes with the background.
+pub fn background_handler(
+    bounds: Res<ChatBounds>,
+    chat_transform: Res<ChatTransform>,
+    mut sprites: Query<(&mut Sprite, &mut Transform), With<ChatBackground>>,
+    mut panels: Query<&mut Style, (With<ChatBackground>, Without<Sprite>)>,
+) {
+    if !bounds.is_changed() && !chat_transform.is_changed() {
+        return;
+    }
+
+    sprites.iter_mut().for_each(|(mut sprite, mut transform)| {
+        sprite.custom_size = Some(bounds.size());
+        transform.translation = background_position(chat_transform.translation, &bounds);
+    });
+
+    panels.iter_mut().for_each(|mut style| {
+        style.position = UiRect {
+            left: Val::Px(bounds.min.x),
+            bottom: Val::Px(bounds.min.y),
+            ..Default::default()
+        };
+        style.size = Size::new(Val::Px(bounds.width()), Val::Px(bounds.height()));
+    });
+}
+
+#[cfg(test)]
+mod should {
+    use bevy::prelude::{App, Vec2};
+
+    use super::*;
+
+    fn app(color: Color) -> App {
+        let mut app = App::new();
+        app.insert_resource(ChatBounds(Rect::new(30.0, 70.0, 330.0, 270.0)))
+            .insert_resource(ChatTransform(Transform::from_xyz(100.0, 0.0, 1.0)))
+            .add_startup_system(
+                move |mut commands: Commands,
+                      bounds: Res<ChatBounds>,
+                      transform: Res<ChatTransform>| {
+                    spawn_background(&mut commands, color, &bounds, &transform);
+                },
+            )
+            .add_system(background_handler);
+        app
+    }
+
+    fn background(app: &mut App) -> Option<(Sprite, Transform)> {
+        app.world
+            .query_filtered::<(&Sprite, &Transform), With<ChatBackground>>()
+            .iter(&app.world)
+            .next()
+            .map(|(sprite, transform)| (sprite.clone(), *transform))
+    }
+
+    #[test]
+    fn spawn_background_with_configured_color_and_bounds() {
+        let color = Color::rgba(0.0, 0.0, 0.0, 0.5);
+        let mut app = app(color);
+
+        app.update();
+
+        let (sprite, transform) = background(&mut app).unwrap();
+        assert_eq!(sprite.color, color);
+        assert_eq!(sprite.custom_size, Some(Vec2::new(300.0, 200.0)));
+        assert_eq!(transform.translation, Vec3::new(280.0, 170.0, 0.9));
+    }
+
+    #[test]
+    fn not_spawn_transparent_background() {
+        let mut app = app(Color::NONE);
+
+        app.update();
+
+        assert!(background(&mut app).is_none());
+    }
+
+    #[test]
+    fn resize_background_with_bounds() {
+        let mut app = app(Color::BLACK);
+        app.update();
+
+        app.world.resource_mut::<ChatBounds>().0 = Rect::new(0.0, 0.0, 100.0, 50.0);
+        app.update();
+
+        let (sprite, transform) = background(&mut app).unwrap();
+        assert_eq!(sprite.custom_size, Some(Vec2::new(100.0, 50.0)));
+        assert_eq!(transform.translation, Vec3::new(150.0, 25.0, 0.9));
+    }
+}
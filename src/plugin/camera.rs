@@ -0,0 +1,148 @@
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    prelude::{
+        Added, Camera, Camera2d, Camera2dBundle, Commands, Entity, Or, Query, Res, UiCameraConfig,
+    },
+    render::view::RenderLayers,
+};
+
+use super::{
+    background::ChatBackground,
+    messages::ChatMessage,
+    render::EmptyState,
+    resources::ChatConfig,
+    text::{InputBox, SelectionHighlight},
+};
+
+/// Order of the overlay camera, so it draws after the main cameras.
+const OVERLAY_CAMERA_ORDER: isize = 1;
+
+/// Creates a 2D camera drawing only the chat render layer on top of the scene.
+///
+/// Use it together with [`render_layer`] to keep the chat visible in 3D games,
+/// where world space text can be occluded by the scene geometry.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::{prelude::Commands, render::view::RenderLayers};
+/// use bevy_pn_chat::chat_overlay_camera;
+///
+/// const CHAT_LAYER: u8 = 7;
+///
+/// fn setup(mut commands: Commands) {
+///     commands.spawn(chat_overlay_camera(RenderLayers::layer(CHAT_LAYER)));
+/// }
+/// ```
+///
+/// [`render_layer`]: crate::builder::ChatPluginConfigBuilder::render_layer
+pub fn chat_overlay_camera(layers: RenderLayers) -> (Camera2dBundle, RenderLayers, UiCameraConfig) {
+    (
+        Camera2dBundle {
+            camera: Camera {
+                order: OVERLAY_CAMERA_ORDER,
+                ..Default::default()
+            },
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::None,
+            },
+            ..Default::default()
+        },
+        layers,
+        UiCameraConfig { show_ui: false },
+    )
+}
+
+pub fn render_layer_handler(
+    mut commands: Commands,
+    config: Res<ChatConfig>,
+    spawned: Query<
+        Entity,
+        Or<(
+            Added<ChatMessage>,
+            Added<InputBox>,
+            Added<EmptyState>,
+            Added<ChatBackground>,
+            Added<SelectionHighlight>,
+        )>,
+    >,
+) {
+    if let Some(layers) = config.render_layer {
+        spawned.iter().for_each(|entity| {
+            commands.entity(entity).insert(layers);
+        });
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use bevy::prelude::App;
+
+    use crate::{builder::ChatPluginConfigBuilder, ChatPlugin, Keyset};
+
+    use super::*;
+
+    const CHAT_LAYER: u8 = 7;
+
+    fn app(builder: ChatPluginConfigBuilder) -> App {
+        let config = builder
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .build()
+            .unwrap()
+            .config;
+
+        let mut app = App::new();
+        app.insert_resource(ChatConfig(config))
+            .add_system(render_layer_handler);
+        app
+    }
+
+    fn spawn_chat_entities(app: &mut App) -> Vec<Entity> {
+        vec![
+            app.world
+                .spawn(ChatMessage {
+                    index: 0,
+                    user_id: "john".into(),
+                    payload: "Hello".into(),
+                    channel: "lobby".into(),
+                    group: None,
+                    timetoken: "0".into(),
+                    raw: None,
+                })
+                .id(),
+            app.world.spawn(InputBox::new()).id(),
+            app.world.spawn(ChatBackground).id(),
+            app.world.spawn(SelectionHighlight).id(),
+        ]
+    }
+
+    #[test]
+    fn insert_render_layer_into_chat_entities() {
+        let mut app = app(ChatPlugin::builder().render_layer(RenderLayers::layer(CHAT_LAYER)));
+
+        let entities = spawn_chat_entities(&mut app);
+        app.update();
+
+        entities.into_iter().for_each(|entity| {
+            assert_eq!(
+                app.world.get::<RenderLayers>(entity),
+                Some(&RenderLayers::layer(CHAT_LAYER))
+            );
+        });
+    }
+
+    #[test]
+    fn keep_default_layer_without_render_layer() {
+        let mut app = app(ChatPlugin::builder());
+
+        let entities = spawn_chat_entities(&mut app);
+        app.update();
+
+        entities.into_iter().for_each(|entity| {
+            assert!(app.world.get::<RenderLayers>(entity).is_none());
+        });
+    }
+}
@@ -0,0 +1,164 @@
+use bevy::prelude::{Added, Query, RemovedComponents, ResMut, Resource, Without};
+
+use super::{messages::ChatMessage, render::LocalMessage};
+
+/// A message shown in the chat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatLogEntry {
+    /// The id of the user who sent the message.
+    pub user_id: String,
+
+    /// The message text.
+    pub payload: String,
+
+    /// The channel the message was sent to.
+    pub channel: String,
+
+    /// The timetoken of the message.
+    pub timetoken: String,
+}
+
+impl From<&ChatMessage> for ChatLogEntry {
+    fn from(message: &ChatMessage) -> Self {
+        Self {
+            user_id: message.user_id.clone(),
+            payload: message.payload.clone(),
+            channel: message.channel.clone(),
+            timetoken: message.timetoken.clone(),
+        }
+    }
+}
+
+/// Messages currently shown in the chat, from the oldest to the newest.
+///
+/// Local notices are not included.
+/// The log is updated at the end of the frame the messages are spawned or despawned in.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::Res;
+/// use bevy_pn_chat::ChatLog;
+///
+/// fn save_transcript(log: Res<ChatLog>) {
+///     log.iter()
+///         .for_each(|entry| println!("{}: {}", entry.user_id, entry.payload));
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Resource)]
+pub struct ChatLog(Vec<ChatLogEntry>);
+
+impl ChatLog {
+    /// Iterates over the messages from the oldest to the newest.
+    pub fn iter(&self) -> impl Iterator<Item = &ChatLogEntry> {
+        self.0.iter()
+    }
+
+    /// The number of messages in the log.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if no messages are shown.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Rebuilds the log whenever messages are spawned or despawned.
+pub fn chat_log_handler(
+    mut log: ResMut<ChatLog>,
+    messages: Query<&ChatMessage, Without<LocalMessage>>,
+    changed: Query<(), (Added<ChatMessage>, Without<LocalMessage>)>,
+    mut removed: RemovedComponents<ChatMessage>,
+) {
+    if changed.is_empty() && removed.iter().count() == 0 {
+        return;
+    }
+
+    let mut messages = messages.iter().collect::<Vec<_>>();
+    messages.sort_by_key(|message| message.index);
+
+    log.0 = messages.into_iter().map(ChatLogEntry::from).collect();
+}
+
+#[cfg(test)]
+mod should {
+    use bevy::prelude::App;
+
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.init_resource::<ChatLog>().add_system(chat_log_handler);
+        app
+    }
+
+    fn message(index: u64, payload: &str) -> ChatMessage {
+        ChatMessage {
+            index,
+            user_id: "john".into(),
+            channel: "lobby".into(),
+            ..ChatMessage::local(payload, format!("1680602491000000{}", index))
+        }
+    }
+
+    fn payloads(app: &App) -> Vec<String> {
+        app.world
+            .resource::<ChatLog>()
+            .iter()
+            .map(|entry| entry.payload.clone())
+            .collect()
+    }
+
+    #[test]
+    fn reflect_spawned_messages_in_order() {
+        let mut app = app();
+
+        app.world.spawn(message(2, "second"));
+        app.world.spawn(message(1, "first"));
+        app.update();
+        app.world.spawn(message(3, "third"));
+        app.update();
+
+        assert_eq!(payloads(&app), vec!["first", "second", "third"]);
+        assert_eq!(
+            app.world.resource::<ChatLog>().iter().next(),
+            Some(&ChatLogEntry {
+                user_id: "john".into(),
+                payload: "first".into(),
+                channel: "lobby".into(),
+                timetoken: "16806024910000001".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn forget_despawned_messages() {
+        let mut app = app();
+
+        let first = app.world.spawn(message(1, "first")).id();
+        app.world.spawn(message(2, "second"));
+        app.update();
+        app.world.despawn(first);
+        app.update();
+
+        assert_eq!(payloads(&app), vec!["second"]);
+    }
+
+    #[test]
+    fn skip_local_notices() {
+        let mut app = app();
+
+        app.world.spawn(message(1, "first"));
+        app.world
+            .spawn((message(2, "Reconnecting..."), LocalMessage));
+        app.update();
+
+        assert_eq!(payloads(&app), vec!["first"]);
+    }
+}
@@ -0,0 +1,223 @@
+use bevy::prelude::Resource;
+
+use super::text::InputBox;
+
+/// Access to the clipboard used by the input box.
+pub trait ClipboardBackend: Send + Sync {
+    fn get_text(&mut self) -> Option<String>;
+
+    fn set_text(&mut self, text: String);
+}
+
+/// The system clipboard.
+///
+/// A new handle is opened on every access, so a missing clipboard
+/// only disables copy and paste instead of failing the plugin.
+/// The browser clipboard is asynchronous, so on the web copy and paste stay within the chat.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct SystemClipboard;
+
+#[cfg(target_arch = "wasm32")]
+#[derive(Default)]
+struct SystemClipboard(Option<String>);
+
+#[cfg(target_arch = "wasm32")]
+impl ClipboardBackend for SystemClipboard {
+    fn get_text(&mut self) -> Option<String> {
+        self.0.clone()
+    }
+
+    fn set_text(&mut self, text: String) {
+        self.0 = Some(text);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ClipboardBackend for SystemClipboard {
+    fn get_text(&mut self) -> Option<String> {
+        arboard::Clipboard::new()
+            .and_then(|mut clipboard| clipboard.get_text())
+            .map_err(|err| log::debug!("Clipboard is not available: {}", err))
+            .ok()
+    }
+
+    fn set_text(&mut self, text: String) {
+        arboard::Clipboard::new()
+            .and_then(|mut clipboard| clipboard.set_text(text))
+            .map_err(|err| log::warn!("Cannot write to the clipboard: {}", err))
+            .ok();
+    }
+}
+
+#[derive(Resource)]
+pub struct ChatClipboard(pub Box<dyn ClipboardBackend>);
+
+impl Default for ChatClipboard {
+    fn default() -> Self {
+        Self(Box::<SystemClipboard>::default())
+    }
+}
+
+/// Inserts the clipboard text at the cursor, replacing the selection.
+///
+/// Line breaks of a multi-line paste are still replaced with spaces on purpose,
+/// so pasted text is published as one line unless Shift+Enter breaks it.
+/// The characters rejected by `allowed`, like with `allowed_characters`, are dropped.
+pub fn paste(input: &mut InputBox, clipboard: &mut ChatClipboard, allowed: impl Fn(char) -> bool) {
+    if let Some(text) = clipboard.0.get_text() {
+        flatten(&text)
+            .chars()
+            .filter(|character| allowed(*character))
+            .for_each(|character| input.insert(character));
+    }
+}
+
+/// Copies the selected text, does nothing without a selection.
+pub fn copy(input: &InputBox, clipboard: &mut ChatClipboard) {
+    if let Some(text) = input.selected_text() {
+        clipboard.0.set_text(text.to_string());
+    }
+}
+
+/// Copies and removes the selected text, does nothing without a selection.
+pub fn cut(input: &mut InputBox, clipboard: &mut ChatClipboard) {
+    copy(input, clipboard);
+    input.delete_selection();
+}
+
+fn flatten(text: &str) -> String {
+    text.replace("\r\n", " ").replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod should {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// Clipboard keeping the text in memory shared with the test.
+    struct MemoryClipboard(Arc<Mutex<Option<String>>>);
+
+    impl ClipboardBackend for MemoryClipboard {
+        fn get_text(&mut self) -> Option<String> {
+            self.0.lock().unwrap().clone()
+        }
+
+        fn set_text(&mut self, text: String) {
+            *self.0.lock().unwrap() = Some(text);
+        }
+    }
+
+    fn shared_clipboard(text: Option<&str>) -> (ChatClipboard, Arc<Mutex<Option<String>>>) {
+        let content = Arc::new(Mutex::new(text.map(Into::into)));
+
+        (
+            ChatClipboard(Box::new(MemoryClipboard(content.clone()))),
+            content,
+        )
+    }
+
+    fn clipboard(text: Option<&str>) -> ChatClipboard {
+        shared_clipboard(text).0
+    }
+
+    fn input(value: &str, cursor: usize) -> InputBox {
+        InputBox {
+            value: value.into(),
+            cursor,
+            selection: None,
+            ..InputBox::new()
+        }
+    }
+
+    #[test]
+    fn paste_at_cursor() {
+        let mut input_box = input("Hello!", 5);
+
+        paste(&mut input_box, &mut clipboard(Some(" world")), |_| true);
+
+        assert_eq!(input_box.value, "Hello world!");
+        assert_eq!(input_box.cursor, 11);
+    }
+
+    #[test]
+    fn flatten_multiline_paste() {
+        let mut input_box = input("", 0);
+
+        paste(
+            &mut input_box,
+            &mut clipboard(Some("first\r\nsecond\nthird")),
+            |_| true,
+        );
+
+        assert_eq!(input_box.value, "first second third");
+    }
+
+    #[test]
+    fn ignore_empty_clipboard() {
+        let mut input_box = input("Hello", 5);
+
+        paste(&mut input_box, &mut clipboard(None), |_| true);
+
+        assert_eq!(input_box.value, "Hello");
+        assert_eq!(input_box.cursor, 5);
+    }
+
+    #[test]
+    fn drop_disallowed_characters_from_paste() {
+        let mut input_box = input("", 0);
+
+        paste(
+            &mut input_box,
+            &mut clipboard(Some("zażółć gęślą")),
+            |character| character.is_ascii(),
+        );
+
+        assert_eq!(input_box.value, "za gl");
+    }
+
+    fn selected(value: &str, anchor: usize, cursor: usize) -> InputBox {
+        InputBox {
+            value: value.into(),
+            cursor,
+            selection: Some(anchor),
+            ..InputBox::new()
+        }
+    }
+
+    #[test]
+    fn copy_selected_text() {
+        let (mut clipboard, content) = shared_clipboard(None);
+        let input_box = selected("Hello world", 6, 11);
+
+        copy(&input_box, &mut clipboard);
+
+        assert_eq!(content.lock().unwrap().as_deref(), Some("world"));
+        assert_eq!(input_box.value, "Hello world");
+    }
+
+    #[test]
+    fn cut_selected_text() {
+        let (mut clipboard, content) = shared_clipboard(None);
+        let mut input_box = selected("Hello world", 11, 5);
+
+        cut(&mut input_box, &mut clipboard);
+
+        assert_eq!(content.lock().unwrap().as_deref(), Some(" world"));
+        assert_eq!(input_box.value, "Hello");
+        assert_eq!(input_box.cursor, 5);
+    }
+
+    #[test]
+    fn not_copy_or_cut_without_selection() {
+        let (mut clipboard, content) = shared_clipboard(Some("previous"));
+        let mut input_box = input("Hello", 5);
+
+        copy(&input_box, &mut clipboard);
+        cut(&mut input_box, &mut clipboard);
+
+        assert_eq!(content.lock().unwrap().as_deref(), Some("previous"));
+        assert_eq!(input_box.value, "Hello");
+    }
+}
@@ -0,0 +1,120 @@
+use bevy::{ecs::system::SystemParam, prelude::EventWriter};
+
+use crate::{error::Result, BevyPNError};
+
+use super::events::{ClearChat, SetUsername};
+
+/// A slash command typed in the input box.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `/nick NAME` changes the username.
+    Nick(String),
+
+    /// `/clear` removes all messages from the chat.
+    Clear,
+
+    /// `/me ACTION` publishes an action message, e.g. `* John waves`.
+    Me(String),
+}
+
+/// Parses the input as a slash command.
+///
+/// Returns `None` if the input doesn't start with `/`, so it is published as typed.
+pub fn parse_command(input: &str) -> Option<Result<Command>> {
+    let input = input.trim().strip_prefix('/')?;
+    let (name, argument) = input
+        .split_once(char::is_whitespace)
+        .map(|(name, argument)| (name, argument.trim()))
+        .unwrap_or((input, ""));
+
+    let required = |argument_name: &str| {
+        (!argument.is_empty())
+            .then(|| argument.to_string())
+            .ok_or_else(|| BevyPNError::MissingArgument {
+                command: name.into(),
+                argument: argument_name.into(),
+            })
+    };
+
+    Some(match name {
+        "nick" => required("a name").map(Command::Nick),
+        "clear" => Ok(Command::Clear),
+        "me" => required("an action").map(Command::Me),
+        _ => Err(BevyPNError::UnknownCommand {
+            command: name.into(),
+        }),
+    })
+}
+
+/// The text published for `/me ACTION`.
+pub fn action_message(username: &str, action: &str) -> String {
+    format!("* {} {}", username, action)
+}
+
+/// Events the slash commands are routed to.
+#[derive(SystemParam)]
+pub struct CommandRunner<'w> {
+    usernames: EventWriter<'w, SetUsername>,
+    clears: EventWriter<'w, ClearChat>,
+}
+
+impl CommandRunner<'_> {
+    /// Runs the command, returning the message to publish if it produces one.
+    pub fn run(&mut self, command: Command, username: &str) -> Option<String> {
+        match command {
+            Command::Nick(name) => {
+                self.usernames.send(SetUsername(name));
+                None
+            }
+            Command::Clear => {
+                self.clears.send(ClearChat);
+                None
+            }
+            Command::Me(action) => Some(action_message(username, &action)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    use test_case::test_case;
+
+    #[test_case("/nick Jane" => Command::Nick("Jane".into()) ; "nick")]
+    #[test_case("/nick   Jane Doe " => Command::Nick("Jane Doe".into()) ; "nick with spaces")]
+    #[test_case("/clear" => Command::Clear ; "clear")]
+    #[test_case("/me waves" => Command::Me("waves".into()) ; "me")]
+    fn parse_known_commands(input: &str) -> Command {
+        parse_command(input).unwrap().unwrap()
+    }
+
+    #[test_case("Hello" ; "plain message")]
+    #[test_case("" ; "empty input")]
+    #[test_case("what /me" ; "slash inside message")]
+    fn not_parse_regular_messages(input: &str) {
+        assert!(parse_command(input).is_none());
+    }
+
+    #[test]
+    fn reject_unknown_command() {
+        assert!(matches!(
+            parse_command("/dance now"),
+            Some(Err(BevyPNError::UnknownCommand { command })) if command == "dance"
+        ));
+    }
+
+    #[test_case("/nick" ; "nick")]
+    #[test_case("/me  " ; "me")]
+    fn reject_command_without_argument(input: &str) {
+        assert!(matches!(
+            parse_command(input),
+            Some(Err(BevyPNError::MissingArgument { .. }))
+        ));
+    }
+
+    #[test]
+    fn format_action_message() {
+        assert_eq!(action_message("John", "waves"), "* John waves");
+    }
+}
@@ -0,0 +1,594 @@
+use std::time::Duration;
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::{EventWriter, Res, ResMut, Resource},
+    time::Time,
+};
+
+use super::{events::ConnectionChanged, messages::Resubscribe, resources::ChatConfig};
+
+/// This resource tells if the chat is connected to PubNub.
+///
+/// It is `false` until the first subscribe response arrives
+/// and whenever the connection is lost.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Resource)]
+pub struct ChatConnected(pub bool);
+
+/// This resource describes the state of the connection to PubNub.
+///
+/// Every transition is announced with the [`ConnectionChanged`] event,
+/// e.g. to gray out the input box while offline.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Resource)]
+pub enum ConnectionState {
+    /// Waiting for the first subscribe response.
+    #[default]
+    Connecting,
+
+    /// Subscribe responses arrive.
+    Connected,
+
+    /// The connection was lost and the chat is retrying within the disconnect grace period.
+    Reconnecting,
+
+    /// Subscribe requests keep failing for longer than the disconnect grace period.
+    ///
+    /// The chat keeps retrying in the background.
+    Disconnected,
+}
+
+impl ConnectionState {
+    /// The state after a subscribe response.
+    ///
+    /// `gave_up` tells if the failures outlasted the disconnect grace period.
+    pub(crate) fn next(self, success: bool, gave_up: bool) -> Self {
+        match (success, self) {
+            (true, _) => ConnectionState::Connected,
+            (false, _) if gave_up => ConnectionState::Disconnected,
+            (false, ConnectionState::Connected) => ConnectionState::Reconnecting,
+            (false, state) => state,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Resource)]
+pub struct SubscribeActivity {
+    last_activity: Duration,
+    sent_at: Duration,
+    failing_since: Option<Duration>,
+}
+
+impl SubscribeActivity {
+    pub fn request_sent(&mut self, now: Duration) {
+        self.last_activity = now;
+        self.sent_at = now;
+    }
+
+    /// How long the latest subscribe request has been pending.
+    pub fn pending_for(&self, now: Duration) -> Duration {
+        now.saturating_sub(self.sent_at)
+    }
+
+    pub fn response_received(&mut self, now: Duration) {
+        self.last_activity = now;
+    }
+
+    pub fn is_stale(&self, now: Duration, timeout: Duration) -> bool {
+        now.saturating_sub(self.last_activity) >= timeout
+    }
+
+    pub fn succeeded(&mut self) {
+        self.failing_since = None;
+    }
+
+    pub fn failed(&mut self, now: Duration) {
+        self.failing_since.get_or_insert(now);
+    }
+
+    /// Returns `true` if requests keep failing for at least the grace period.
+    pub fn is_failing_for(&self, now: Duration, grace: Duration) -> bool {
+        self.failing_since
+            .map(|since| now.saturating_sub(since) >= grace)
+            .unwrap_or(false)
+    }
+}
+
+/// Delay of the first resubscribe after a failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest delay between resubscribe attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Delays resubscribing after failed subscribe requests.
+///
+/// The delay doubles with every failure up to [`MAX_BACKOFF`]
+/// and is reset by the first successful response.
+#[derive(Debug, Clone, Resource)]
+pub struct SubscribeBackoff {
+    delay: Duration,
+    retry_at: Option<Duration>,
+}
+
+impl Default for SubscribeBackoff {
+    fn default() -> Self {
+        Self {
+            delay: INITIAL_BACKOFF,
+            retry_at: None,
+        }
+    }
+}
+
+impl SubscribeBackoff {
+    /// Schedules a retry and returns its delay.
+    pub fn failed(&mut self, now: Duration) -> Duration {
+        let delay = self.delay;
+
+        self.delay = (delay * 2).min(MAX_BACKOFF);
+        self.retry_at = Some(now + delay);
+        delay
+    }
+
+    pub fn succeeded(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Schedules a retry without counting a failure.
+    pub fn retry_in(&mut self, now: Duration, delay: Duration) {
+        self.retry_at = Some(now + delay);
+    }
+
+    /// Returns `true` once the scheduled retry is due.
+    pub fn take_due(&mut self, now: Duration) -> bool {
+        let due = self.retry_at.map_or(false, |retry_at| now >= retry_at);
+
+        if due {
+            self.retry_at = None;
+        }
+        due
+    }
+}
+
+/// Subscribe responses without messages arriving faster than this are immediate returns.
+const IMMEDIATE_RETURN: Duration = Duration::from_secs(1);
+
+/// Paces the subscribe loop when long-polls keep returning immediately without messages.
+///
+/// The first immediate return of a new subscription is the timetoken handshake,
+/// every next one doubles the delay from `min_poll_delay`, with jitter, up to [`MAX_BACKOFF`].
+/// Responses with messages are followed by the next poll right away.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct PollPacing {
+    immediate_returns: u32,
+}
+
+impl PollPacing {
+    /// Returns the delay before the next poll, `None` polls right away.
+    ///
+    /// `jitter` between `0` and `1` stretches the delay by up to a half.
+    pub fn next_delay(
+        &mut self,
+        immediate: bool,
+        min_delay: Duration,
+        jitter: f64,
+    ) -> Option<Duration> {
+        if !immediate {
+            self.immediate_returns = 0;
+            return None;
+        }
+
+        self.immediate_returns = self.immediate_returns.saturating_add(1);
+        let doublings = self.immediate_returns.checked_sub(2)?.min(16);
+        let delay = (min_delay * 2u32.pow(doublings)).min(MAX_BACKOFF);
+
+        Some(delay.mul_f64(1.0 + jitter.clamp(0.0, 1.0) / 2.0))
+    }
+}
+
+/// Resources describing the health of the subscribe loop.
+#[derive(SystemParam)]
+pub struct SubscribeStatus<'w> {
+    activity: ResMut<'w, SubscribeActivity>,
+    connected: ResMut<'w, ChatConnected>,
+    backoff: ResMut<'w, SubscribeBackoff>,
+    pacing: ResMut<'w, PollPacing>,
+    state: ResMut<'w, ConnectionState>,
+    changes: EventWriter<'w, ConnectionChanged>,
+}
+
+impl SubscribeStatus<'_> {
+    /// Updates the connection status and schedules a retry after a failure.
+    pub fn report(&mut self, success: bool, now: Duration, grace: Duration) {
+        report_outcome(&mut self.activity, &mut self.connected, success, now, grace);
+
+        if success {
+            self.backoff.succeeded();
+        } else {
+            let delay = self.backoff.failed(now);
+            log::info!("Resubscribing in {:?}", delay);
+        }
+
+        let to = self
+            .state
+            .next(success, self.activity.is_failing_for(now, grace));
+        self.set_state(to);
+    }
+
+    /// Disconnects the chat once the failures outlast the grace period.
+    pub fn check_grace(&mut self, now: Duration, grace: Duration) {
+        if self.connected.0 && self.activity.is_failing_for(now, grace) {
+            self.connected.0 = false;
+            self.set_state(ConnectionState::Disconnected);
+        }
+    }
+
+    /// Reports the subscribe request stuck without a response, it is sent again right away.
+    pub fn stalled(&mut self, now: Duration) {
+        self.connected.0 = false;
+        self.activity.request_sent(now);
+
+        let to = self.state.next(false, false);
+        self.set_state(to);
+    }
+
+    fn set_state(&mut self, to: ConnectionState) {
+        let from = *self.state;
+        if from != to {
+            *self.state = to;
+            self.changes.send(ConnectionChanged { from, to });
+        }
+    }
+}
+
+impl SubscribeStatus<'_> {
+    /// Requests the next poll after a successful response, delayed if it returned immediately.
+    pub fn poll_again(
+        &mut self,
+        empty: bool,
+        now: Duration,
+        min_delay: Duration,
+        resubscribe: &mut EventWriter<Resubscribe>,
+    ) {
+        let immediate = empty && self.activity.pending_for(now) < IMMEDIATE_RETURN;
+
+        match self.pacing.next_delay(immediate, min_delay, rand::random()) {
+            Some(delay) => {
+                log::debug!(
+                    "Subscribe returned immediately, polling again in {:?}",
+                    delay
+                );
+                self.backoff.retry_in(now, delay);
+            }
+            None => resubscribe.send(Resubscribe),
+        }
+    }
+}
+
+/// Updates the connection status after a subscribe response.
+pub fn report_outcome(
+    activity: &mut SubscribeActivity,
+    connected: &mut ChatConnected,
+    success: bool,
+    now: Duration,
+    grace: Duration,
+) {
+    activity.response_received(now);
+
+    if success {
+        activity.succeeded();
+        connected.0 = true;
+    } else {
+        activity.failed(now);
+        connected.0 &= !activity.is_failing_for(now, grace);
+    }
+}
+
+pub fn watchdog_handler(
+    time: Res<Time>,
+    config: Res<ChatConfig>,
+    mut status: SubscribeStatus,
+    mut resubscribe: EventWriter<Resubscribe>,
+) {
+    let now = time.elapsed();
+
+    status.check_grace(now, config.disconnect_grace);
+
+    if let Some(timeout) = config
+        .watchdog_timeout
+        .filter(|timeout| status.activity.is_stale(now, *timeout))
+    {
+        log::warn!("No subscribe response for {:?}, reconnecting", timeout);
+
+        status.stalled(now);
+        resubscribe.send(Resubscribe);
+    }
+}
+
+pub fn backoff_handler(
+    time: Res<Time>,
+    mut backoff: ResMut<SubscribeBackoff>,
+    mut resubscribe: EventWriter<Resubscribe>,
+) {
+    if backoff.take_due(time.elapsed()) {
+        resubscribe.send(Resubscribe);
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(320);
+
+    #[test]
+    fn not_fire_before_timeout() {
+        let mut activity = SubscribeActivity::default();
+        activity.request_sent(Duration::from_secs(10));
+
+        assert!(!activity.is_stale(Duration::from_secs(329), TIMEOUT));
+    }
+
+    #[test]
+    fn fire_after_timeout_without_activity() {
+        let mut activity = SubscribeActivity::default();
+        activity.request_sent(Duration::from_secs(10));
+
+        assert!(activity.is_stale(Duration::from_secs(330), TIMEOUT));
+    }
+
+    const GRACE: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn stay_connected_after_transient_failure() {
+        let mut activity = SubscribeActivity::default();
+        let mut connected = ChatConnected(true);
+
+        report_outcome(
+            &mut activity,
+            &mut connected,
+            false,
+            Duration::from_secs(10),
+            GRACE,
+        );
+        assert!(connected.0);
+        assert!(!activity.is_failing_for(Duration::from_secs(14), GRACE));
+
+        report_outcome(
+            &mut activity,
+            &mut connected,
+            true,
+            Duration::from_secs(12),
+            GRACE,
+        );
+        assert!(connected.0);
+        assert!(!activity.is_failing_for(Duration::from_secs(20), GRACE));
+    }
+
+    #[test]
+    fn disconnect_when_failures_outlast_grace() {
+        let mut activity = SubscribeActivity::default();
+        let mut connected = ChatConnected(true);
+
+        report_outcome(
+            &mut activity,
+            &mut connected,
+            false,
+            Duration::from_secs(10),
+            GRACE,
+        );
+        report_outcome(
+            &mut activity,
+            &mut connected,
+            false,
+            Duration::from_secs(15),
+            GRACE,
+        );
+
+        assert!(!connected.0);
+    }
+
+    #[test]
+    fn disconnect_immediately_without_grace() {
+        let mut activity = SubscribeActivity::default();
+        let mut connected = ChatConnected(true);
+
+        report_outcome(
+            &mut activity,
+            &mut connected,
+            false,
+            Duration::from_secs(10),
+            Duration::ZERO,
+        );
+
+        assert!(!connected.0);
+    }
+
+    #[test]
+    fn reset_on_response() {
+        let mut activity = SubscribeActivity::default();
+        activity.request_sent(Duration::from_secs(10));
+        activity.response_received(Duration::from_secs(300));
+
+        assert!(!activity.is_stale(Duration::from_secs(330), TIMEOUT));
+    }
+
+    #[test]
+    fn recover_connection_state_after_failures() {
+        let transitions = [
+            (false, false),
+            (true, false),
+            (false, false),
+            (false, true),
+            (true, false),
+        ]
+        .into_iter()
+        .scan(ConnectionState::default(), |state, (success, gave_up)| {
+            *state = state.next(success, gave_up);
+            Some(*state)
+        })
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            transitions,
+            vec![
+                ConnectionState::Connecting,
+                ConnectionState::Connected,
+                ConnectionState::Reconnecting,
+                ConnectionState::Disconnected,
+                ConnectionState::Connected,
+            ]
+        );
+    }
+
+    #[test]
+    fn double_backoff_up_to_the_limit() {
+        let mut backoff = SubscribeBackoff::default();
+
+        assert_eq!(
+            (0..7)
+                .map(|_| backoff.failed(Duration::ZERO).as_secs())
+                .collect::<Vec<_>>(),
+            vec![1, 2, 4, 8, 16, 30, 30]
+        );
+    }
+
+    #[test]
+    fn reset_backoff_on_success() {
+        let mut backoff = SubscribeBackoff::default();
+        backoff.failed(Duration::ZERO);
+        backoff.failed(Duration::ZERO);
+
+        backoff.succeeded();
+
+        assert_eq!(backoff.failed(Duration::ZERO), Duration::from_secs(1));
+    }
+
+    const MIN_DELAY: Duration = Duration::from_millis(250);
+
+    #[test]
+    fn poll_right_away_after_handshake() {
+        let mut pacing = PollPacing::default();
+
+        assert_eq!(pacing.next_delay(true, MIN_DELAY, 0.0), None);
+    }
+
+    #[test]
+    fn delay_consecutive_immediate_returns() {
+        let mut pacing = PollPacing::default();
+
+        assert_eq!(
+            (0..5)
+                .map(|_| pacing.next_delay(true, MIN_DELAY, 0.0))
+                .collect::<Vec<_>>(),
+            vec![
+                None,
+                Some(MIN_DELAY),
+                Some(MIN_DELAY * 2),
+                Some(MIN_DELAY * 4),
+                Some(MIN_DELAY * 8),
+            ]
+        );
+    }
+
+    #[test]
+    fn stretch_delay_with_jitter() {
+        let mut pacing = PollPacing::default();
+        pacing.next_delay(true, MIN_DELAY, 1.0);
+
+        assert_eq!(
+            pacing.next_delay(true, MIN_DELAY, 1.0),
+            Some(MIN_DELAY.mul_f64(1.5))
+        );
+    }
+
+    #[test]
+    fn reset_pacing_after_regular_response() {
+        let mut pacing = PollPacing::default();
+        pacing.next_delay(true, MIN_DELAY, 0.0);
+        pacing.next_delay(true, MIN_DELAY, 0.0);
+
+        assert_eq!(pacing.next_delay(false, MIN_DELAY, 0.0), None);
+        assert_eq!(pacing.next_delay(true, MIN_DELAY, 0.0), None);
+        assert_eq!(pacing.next_delay(true, MIN_DELAY, 0.0), Some(MIN_DELAY));
+    }
+
+    #[test]
+    fn cap_pacing_delay() {
+        let mut pacing = PollPacing::default();
+
+        assert_eq!(
+            (0..20)
+                .filter_map(|_| pacing.next_delay(true, MIN_DELAY, 0.0))
+                .last(),
+            Some(MAX_BACKOFF)
+        );
+    }
+
+    #[test]
+    fn retry_once_when_due() {
+        let mut backoff = SubscribeBackoff::default();
+        backoff.failed(Duration::from_secs(10));
+
+        assert!(!backoff.take_due(Duration::from_millis(10_999)));
+        assert!(backoff.take_due(Duration::from_secs(11)));
+        assert!(!backoff.take_due(Duration::from_secs(12)));
+    }
+
+    fn watchdog_app() -> bevy::prelude::App {
+        let config = crate::ChatPlugin::builder()
+            .keyset(crate::Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .watchdog_timeout(TIMEOUT)
+            .build()
+            .unwrap()
+            .config;
+
+        let mut app = bevy::prelude::App::new();
+        app.insert_resource(ChatConfig(config))
+            .init_resource::<Time>()
+            .init_resource::<SubscribeActivity>()
+            .init_resource::<SubscribeBackoff>()
+            .init_resource::<PollPacing>()
+            .insert_resource(ChatConnected(true))
+            .insert_resource(ConnectionState::Connected)
+            .add_event::<ConnectionChanged>()
+            .add_event::<Resubscribe>()
+            .add_system(watchdog_handler);
+        app
+    }
+
+    #[test]
+    fn reconnect_stuck_connection_with_watchdog() {
+        let mut app = watchdog_app();
+        let startup = app.world.resource::<Time>().startup();
+        app.world
+            .resource_mut::<Time>()
+            .update_with_instant(startup + TIMEOUT);
+
+        app.update();
+
+        assert!(!app.world.resource::<ChatConnected>().0);
+        assert_eq!(
+            *app.world.resource::<ConnectionState>(),
+            ConnectionState::Reconnecting
+        );
+        assert_eq!(
+            app.world
+                .resource::<bevy::ecs::event::Events<ConnectionChanged>>()
+                .iter_current_update_events()
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![ConnectionChanged {
+                from: ConnectionState::Connected,
+                to: ConnectionState::Reconnecting,
+            }]
+        );
+        assert_eq!(
+            app.world
+                .resource::<bevy::ecs::event::Events<Resubscribe>>()
+                .len(),
+            1
+        );
+    }
+}
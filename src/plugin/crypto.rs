@@ -0,0 +1,106 @@
+use aes::{
+    cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit},
+    Aes256,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::{error::Result, BevyPNError};
+
+type Encryptor = cbc::Encryptor<Aes256>;
+type Decryptor = cbc::Decryptor<Aes256>;
+
+const IV_LENGTH: usize = 16;
+const KEY_LENGTH: usize = 32;
+
+/// Encrypts the text with AES-256-CBC, compatible with the PubNub SDKs using a random IV.
+///
+/// The IV is prepended to the cipher text and the result is base64 encoded.
+pub fn encrypt(cipher_key: &str, text: &str) -> String {
+    let mut iv = [0u8; IV_LENGTH];
+    rand::thread_rng().fill_bytes(&mut iv);
+
+    let encrypted = Encryptor::new(&key(cipher_key).into(), &iv.into())
+        .encrypt_padded_vec_mut::<Pkcs7>(text.as_bytes());
+
+    STANDARD.encode([iv.as_slice(), &encrypted].concat())
+}
+
+pub fn decrypt(cipher_key: &str, encoded: &str) -> Result<String> {
+    let data = STANDARD
+        .decode(encoded)
+        .map_err(|err| decrypt_error(err.to_string()))?;
+
+    if data.len() <= IV_LENGTH {
+        return Err(decrypt_error("payload is too short".into()));
+    }
+
+    let (iv, encrypted) = data.split_at(IV_LENGTH);
+    let decrypted = Decryptor::new_from_slices(&key(cipher_key), iv)
+        .map_err(|err| decrypt_error(err.to_string()))?
+        .decrypt_padded_vec_mut::<Pkcs7>(encrypted)
+        .map_err(|err| decrypt_error(err.to_string()))?;
+
+    String::from_utf8(decrypted).map_err(|err| decrypt_error(err.to_string()))
+}
+
+// PubNub derives the key from the hex encoded SHA-256 of the cipher key.
+fn key(cipher_key: &str) -> [u8; KEY_LENGTH] {
+    let hex = Sha256::digest(cipher_key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    let mut key = [0u8; KEY_LENGTH];
+    key.copy_from_slice(&hex.as_bytes()[..KEY_LENGTH]);
+    key
+}
+
+fn decrypt_error(message: String) -> BevyPNError {
+    BevyPNError::Decrypt { message }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    const CIPHER_KEY: &str = "enigma";
+
+    #[test]
+    fn round_trip_message() {
+        let encrypted = encrypt(CIPHER_KEY, "Hello, zażółć!");
+
+        assert_ne!(encrypted, "Hello, zażółć!");
+        assert_eq!(decrypt(CIPHER_KEY, &encrypted).unwrap(), "Hello, zażółć!");
+    }
+
+    #[test]
+    fn use_random_iv() {
+        assert_ne!(encrypt(CIPHER_KEY, "Hello"), encrypt(CIPHER_KEY, "Hello"));
+    }
+
+    // The PubNub SDKs encrypt `yay!` with `enigma` and the legacy `0123456789012345` IV
+    // to `q/xJqqN6qbiZMXYmiQC1Fw==`, with a random IV it is prepended to the same cipher text.
+    #[test]
+    fn decrypt_pubnub_sdk_cipher_text() {
+        assert_eq!(
+            decrypt(CIPHER_KEY, "MDEyMzQ1Njc4OTAxMjM0Nav8Saqjeqm4mTF2JokAtRc=").unwrap(),
+            "yay!"
+        );
+    }
+
+    #[test]
+    fn fail_with_wrong_key() {
+        let encrypted = encrypt(CIPHER_KEY, "Hello");
+
+        assert!(decrypt("other", &encrypted)
+            .map(|text| text != "Hello")
+            .unwrap_or(true));
+    }
+
+    #[test]
+    fn fail_on_plain_text() {
+        assert!(decrypt(CIPHER_KEY, "Hello").is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+/// Shortcodes expanded with `emoji_shortcodes` enabled.
+const SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("angry", "😠"),
+    ("clap", "👏"),
+    ("cry", "😢"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("grin", "😁"),
+    ("heart", "❤️"),
+    ("joy", "😂"),
+    ("laughing", "😆"),
+    ("ok_hand", "👌"),
+    ("party", "🥳"),
+    ("pray", "🙏"),
+    ("rocket", "🚀"),
+    ("skull", "💀"),
+    ("slightly_smiling_face", "🙂"),
+    ("smile", "😄"),
+    ("sob", "😭"),
+    ("sunglasses", "😎"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("wave", "👋"),
+    ("wink", "😉"),
+];
+
+/// Replaces the known `:shortcode:`s with emoji.
+///
+/// Custom shortcodes are looked up first, so they can override the built-in ones.
+/// Unknown and unterminated shortcodes are left untouched.
+pub fn expand_shortcodes(text: &str, custom: &HashMap<String, String>) -> String {
+    let lookup = |name: &str| {
+        custom.get(name).map(String::as_str).or_else(|| {
+            SHORTCODES
+                .iter()
+                .find(|(shortcode, _)| *shortcode == name)
+                .map(|(_, emoji)| *emoji)
+        })
+    };
+
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(':') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        match after
+            .find(':')
+            .and_then(|end| lookup(&after[..end]).map(|emoji| (end, emoji)))
+        {
+            Some((end, emoji)) => {
+                expanded.push_str(emoji);
+                rest = &after[end + 1..];
+            }
+            // The closing colon may open the next shortcode.
+            None => {
+                expanded.push(':');
+                rest = after;
+            }
+        }
+    }
+
+    expanded.push_str(rest);
+    expanded
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    use test_case::test_case;
+
+    #[test_case("Hi :smile:" => "Hi 😄" ; "known shortcode")]
+    #[test_case("Hi :unknown:" => "Hi :unknown:" ; "unknown shortcode")]
+    #[test_case(":fire::rocket:" => "🔥🚀" ; "adjacent shortcodes")]
+    #[test_case("at 10:30 :smile" => "at 10:30 :smile" ; "unterminated shortcode")]
+    #[test_case("ratio 1:2:smile:" => "ratio 1:2😄" ; "shortcode after a colon")]
+    #[test_case("::" => "::" ; "empty shortcode")]
+    fn expand_built_in_shortcodes(text: &str) -> String {
+        expand_shortcodes(text, &HashMap::new())
+    }
+
+    #[test]
+    fn expand_custom_shortcodes() {
+        let custom = HashMap::from([
+            ("parrot".to_string(), "🦜".to_string()),
+            ("smile".to_string(), "🙃".to_string()),
+        ]);
+
+        assert_eq!(expand_shortcodes(":parrot: :smile:", &custom), "🦜 🙃");
+    }
+}
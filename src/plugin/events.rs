@@ -0,0 +1,567 @@
+//! This module contains the events that can be used to interact with the [`ChatPlugin`].
+//!
+//! Events are registered by the plugin, so they can be read or written
+//! by the host app systems without any additional setup.
+//!
+//! [`ChatPlugin`]: crate::ChatPlugin
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::connection::ConnectionState;
+
+/// This event is used to briefly highlight a chat message.
+///
+/// The message is matched by its timetoken and pulses its color
+/// for the given duration.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use bevy::prelude::EventWriter;
+/// use bevy_pn_chat::HighlightMessage;
+///
+/// fn highlight(mut highlights: EventWriter<HighlightMessage>) {
+///     highlights.send(HighlightMessage {
+///         message_tt: "16806024912893744".into(),
+///         duration: Duration::from_secs(2),
+///     });
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct HighlightMessage {
+    /// The timetoken of the message to highlight.
+    pub message_tt: String,
+
+    /// How long the highlight lasts.
+    pub duration: Duration,
+}
+
+/// This event reacts to a chat message, e.g. with 👍.
+///
+/// The reaction is sent as a PubNub signal and counted in the [`Reactions`] resource
+/// of every client once it comes back, the local one included.
+///
+/// [`Reactions`]: crate::Reactions
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventWriter;
+/// use bevy_pn_chat::React;
+///
+/// fn thumbs_up(mut reactions: EventWriter<React>) {
+///     reactions.send(React {
+///         message_tt: "16806024912893744".into(),
+///         emoji: "👍".into(),
+///     });
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct React {
+    /// The timetoken of the message to react to.
+    pub message_tt: String,
+
+    /// The reaction emoji.
+    pub emoji: String,
+}
+
+/// This event scrolls the chat to the newest message.
+///
+/// It also makes the chat follow new messages again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScrollToBottom;
+
+/// This event is sent when the [`HasUnreadBelow`] flag changes.
+///
+/// It lets the app show a jump to latest button while the user reads older messages.
+///
+/// [`HasUnreadBelow`]: crate::HasUnreadBelow
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventReader;
+/// use bevy_pn_chat::UnreadBelowChanged;
+///
+/// fn jump_to_latest_button(mut changes: EventReader<UnreadBelowChanged>) {
+///     changes
+///         .iter()
+///         .for_each(|change| println!("Show the button: {}", change.unread));
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnreadBelowChanged {
+    /// `true` if new messages arrived below the shown ones.
+    pub unread: bool,
+}
+
+/// This event scrolls the chat to the oldest message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScrollToTop;
+
+/// This event scrolls the chat by the given amount of pixels.
+///
+/// Positive values scroll towards older messages.
+/// The resulting offset is clamped to the available messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScrollBy(pub f32);
+
+/// This event removes all messages from the chat.
+///
+/// The messages waiting to be rendered are dropped as well and the [`ChatLog`] is emptied.
+/// It is useful when switching rooms or for a `/clear` command.
+///
+/// [`ChatLog`]: crate::ChatLog
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventWriter;
+/// use bevy_pn_chat::ClearChat;
+///
+/// fn leave_match(mut clears: EventWriter<ClearChat>) {
+///     clears.send(ClearChat);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClearChat;
+
+/// This event changes the username of the local user.
+///
+/// The new name is used as the PubNub user id of the next requests,
+/// so the other users see the messages published from now on under the new name.
+/// Empty usernames are ignored with a warning.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventWriter;
+/// use bevy_pn_chat::SetUsername;
+///
+/// fn rename(mut usernames: EventWriter<SetUsername>) {
+///     usernames.send(SetUsername("Jane Doe".into()));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetUsername(pub String);
+
+/// This event shows a system message in the chat, like "You joined the channel".
+///
+/// System messages are rendered with the `system_message_style` and laid out with the other
+/// messages, but they are never published to PubNub.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventWriter;
+/// use bevy_pn_chat::SystemMessage;
+///
+/// fn welcome(mut notices: EventWriter<SystemMessage>) {
+///     notices.send(SystemMessage("You joined the channel".into()));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemMessage(pub String);
+
+/// This event moves the chat to another channel.
+///
+/// The shown messages are cleared and the subscription starts over on the new channel,
+/// so only messages published from now on are received.
+/// The result of the subscribe request in flight for the previous channel is ignored.
+/// Empty channel names are ignored with a warning.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventWriter;
+/// use bevy_pn_chat::SwitchChannel;
+///
+/// fn join_team(mut switches: EventWriter<SwitchChannel>) {
+///     switches.send(SwitchChannel("team-red".into()));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitchChannel(pub String);
+
+/// This event replaces the server-side filter expression of the subscription.
+///
+/// The subscribe loop is restarted, so the new expression applies to the next messages.
+/// `None` removes the filter. Empty expressions are ignored with a warning.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventWriter;
+/// use bevy_pn_chat::SetFilterExpression;
+///
+/// fn enter_match(mut filters: EventWriter<SetFilterExpression>) {
+///     filters.send(SetFilterExpression(Some("context == 'in-match'".into())));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetFilterExpression(pub Option<String>);
+
+/// This event replaces the Access Manager token attached to the requests.
+///
+/// Tokens expire, so the app has to fetch a fresh one from its server in time.
+/// The subscribe request in flight keeps the previous token until it returns.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventWriter;
+/// use bevy_pn_chat::UpdateAuthToken;
+///
+/// fn refresh_token(mut updates: EventWriter<UpdateAuthToken>) {
+///     updates.send(UpdateAuthToken("p0F2AkF0GmQ...".into()));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateAuthToken(pub String);
+
+/// This event is sent for every chat message received from PubNub.
+///
+/// It lets the app react to incoming messages, e.g. play a sound or update a HUD.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventReader;
+/// use bevy_pn_chat::MessageReceived;
+///
+/// fn notify(mut received: EventReader<MessageReceived>) {
+///     received
+///         .iter()
+///         .for_each(|message| println!("{} wrote: {}", message.user_id, message.payload));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageReceived {
+    /// The id of the user who sent the message.
+    pub user_id: String,
+
+    /// The message text.
+    pub payload: String,
+
+    /// The channel the message was sent to.
+    pub channel: String,
+
+    /// The timetoken of the message.
+    pub timetoken: String,
+
+    /// Metadata attached to the message.
+    ///
+    /// It is only filled with [`structured_messages`] enabled.
+    ///
+    /// [`structured_messages`]: crate::builder::ChatPluginConfigBuilder::structured_messages
+    pub meta: Option<Value>,
+}
+
+/// This event is sent for received messages worth notifying the player about.
+///
+/// The plugin doesn't play any sound, so the app can use its own notification.
+/// Messages of the local user only notify with [`notify_on_own`] enabled.
+///
+/// [`notify_on_own`]: crate::builder::ChatPluginConfigBuilder::notify_on_own
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::{AssetServer, Audio, EventReader, Res};
+/// use bevy_pn_chat::NotifyOnMessage;
+///
+/// fn ping(
+///     mut notifications: EventReader<NotifyOnMessage>,
+///     asset_server: Res<AssetServer>,
+///     audio: Res<Audio>,
+/// ) {
+///     if notifications.iter().count() > 0 {
+///         audio.play(asset_server.load("sounds/ping.ogg"));
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotifyOnMessage {
+    /// The id of the user who sent the message.
+    pub user_id: String,
+
+    /// The message text.
+    pub payload: String,
+}
+
+/// This event is sent when the user clicks a link in a chat message.
+///
+/// The plugin doesn't open the link, so the app can ask the player first.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventReader;
+/// use bevy_pn_chat::LinkClicked;
+///
+/// fn open_links(mut clicks: EventReader<LinkClicked>) {
+///     clicks
+///         .iter()
+///         .for_each(|click| println!("Opening {}", click.url));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkClicked {
+    /// The clicked URL.
+    pub url: String,
+
+    /// The timetoken of the message containing the link.
+    pub message_tt: String,
+}
+
+/// This event is sent when a received message mentions the local user.
+///
+/// The username is matched ignoring the case,
+/// with [`mention_requires_at`] enabled only `@username` counts.
+/// Messages sent by the local user never count as mentions.
+///
+/// [`mention_requires_at`]: crate::builder::ChatPluginConfigBuilder::mention_requires_at
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventReader;
+/// use bevy_pn_chat::MentionReceived;
+///
+/// fn ping(mut mentions: EventReader<MentionReceived>) {
+///     mentions
+///         .iter()
+///         .for_each(|mention| println!("{} mentioned you: {}", mention.user_id, mention.payload));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MentionReceived {
+    /// The id of the user who sent the message.
+    pub user_id: String,
+
+    /// The message text.
+    pub payload: String,
+
+    /// The channel the message was sent to.
+    pub channel: String,
+
+    /// The timetoken of the message.
+    pub timetoken: String,
+}
+
+/// This event reports the result of publishing a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublishOutcome {
+    /// The message was published.
+    Sent {
+        /// The message text.
+        message: String,
+    },
+
+    /// Publishing the message failed.
+    Failed {
+        /// The message text.
+        message: String,
+
+        /// Why the publish failed.
+        error: String,
+    },
+}
+
+/// This event publishes a message to the chat channel without the input box.
+///
+/// It is published the same way as the messages typed by the user,
+/// so it can be used for system messages or bot replies.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventWriter;
+/// use bevy_pn_chat::SendMessage;
+///
+/// fn greet(mut messages: EventWriter<SendMessage>) {
+///     messages.send(SendMessage("Welcome to the lobby!".into()));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendMessage(pub String);
+
+/// This event publishes a message with metadata.
+///
+/// The message is always published as a JSON object with `text` and `meta` fields,
+/// regardless of the [`structured_messages`] setting.
+///
+/// [`structured_messages`]: crate::builder::ChatPluginConfigBuilder::structured_messages
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventWriter;
+/// use bevy_pn_chat::SendStructuredMessage;
+/// use serde_json::json;
+///
+/// fn reply(mut messages: EventWriter<SendStructuredMessage>) {
+///     messages.send(SendStructuredMessage {
+///         text: "Sure, see you there!".into(),
+///         meta: json!({ "reply_to": "16806024912893744" }),
+///     });
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendStructuredMessage {
+    /// The message text.
+    pub text: String,
+
+    /// Metadata published with the message.
+    pub meta: Value,
+}
+
+/// This event is sent when publishing or subscribing fails.
+///
+/// The errors are still logged, the event lets the app show them to the player,
+/// e.g. as a connection lost banner.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventReader;
+/// use bevy_pn_chat::ChatError;
+///
+/// fn show_errors(mut errors: EventReader<ChatError>) {
+///     errors
+///         .iter()
+///         .for_each(|error| println!("{}: {}", error.context, error.message));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatError {
+    /// What failed, e.g. `subscribe: network error`.
+    pub context: String,
+
+    /// The error message.
+    pub message: String,
+}
+
+impl ChatError {
+    pub(crate) fn new(operation: &str, error: &crate::BevyPNError) -> Self {
+        Self {
+            context: format!("{}: {}", operation, error.context()),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// This event is sent when the [`ConnectionState`] changes.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventReader;
+/// use bevy_pn_chat::{ConnectionChanged, ConnectionState};
+///
+/// fn offline_banner(mut changes: EventReader<ConnectionChanged>) {
+///     changes.iter().for_each(|change| match change.to {
+///         ConnectionState::Disconnected => println!("Chat is offline"),
+///         ConnectionState::Connected => println!("Chat is back online"),
+///         _ => {}
+///     });
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionChanged {
+    /// The previous state.
+    pub from: ConnectionState,
+
+    /// The current state.
+    pub to: ConnectionState,
+}
+
+/// This event is sent when users join or leave the chat channel.
+///
+/// It is only sent with [`presence`] enabled.
+/// The current users are available in the [`OnlineUsers`] resource.
+///
+/// [`presence`]: crate::builder::ChatPluginConfigBuilder::presence
+/// [`OnlineUsers`]: crate::OnlineUsers
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventReader;
+/// use bevy_pn_chat::PresenceChanged;
+///
+/// fn announce(mut changes: EventReader<PresenceChanged>) {
+///     changes.iter().for_each(|change| {
+///         change.joined.iter().for_each(|user| println!("{} joined", user));
+///         change.left.iter().for_each(|user| println!("{} left", user));
+///     });
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresenceChanged {
+    /// The users who joined.
+    pub joined: Vec<String>,
+
+    /// The users who left or timed out.
+    pub left: Vec<String>,
+
+    /// The channel occupancy reported by PubNub.
+    pub occupancy: usize,
+}
+
+/// This event is sent when a user starts or stops typing.
+///
+/// It is only sent with [`typing_indicators`] enabled.
+/// The current typing users are available in the [`TypingUsers`] resource.
+///
+/// [`typing_indicators`]: crate::builder::ChatPluginConfigBuilder::typing_indicators
+/// [`TypingUsers`]: crate::TypingUsers
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventReader;
+/// use bevy_pn_chat::TypingChanged;
+///
+/// fn typing_indicator(mut changes: EventReader<TypingChanged>) {
+///     changes
+///         .iter()
+///         .filter(|change| change.typing)
+///         .for_each(|change| println!("{} is typing...", change.user_id));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypingChanged {
+    /// The id of the user.
+    pub user_id: String,
+
+    /// `true` if the user started typing, `false` if they stopped.
+    pub typing: bool,
+}
+
+/// This event is sent when the input box gains or loses the focus.
+///
+/// See [`ChatFocus`] for more information.
+///
+/// [`ChatFocus`]: crate::ChatFocus
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::prelude::EventReader;
+/// use bevy_pn_chat::ChatFocusChanged;
+///
+/// fn pause_movement(mut changes: EventReader<ChatFocusChanged>) {
+///     changes
+///         .iter()
+///         .for_each(|change| println!("Movement enabled: {}", !change.focused));
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChatFocusChanged {
+    /// `true` if the input box is focused now.
+    pub focused: bool,
+}
@@ -0,0 +1,61 @@
+use bevy::prelude::{KeyCode, Resource};
+
+use super::events::ChatFocusChanged;
+
+/// This resource tells if the input box receives the keyboard input.
+///
+/// The input box is focused with the `toggle_key`, Enter by default,
+/// and loses the focus with Escape, so the keys used by the game don't end up in the chat.
+/// Every change is announced with the [`ChatFocusChanged`] event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource)]
+pub struct ChatFocus {
+    focused: bool,
+    toggle_key: KeyCode,
+}
+
+impl Default for ChatFocus {
+    fn default() -> Self {
+        Self::new(KeyCode::Return)
+    }
+}
+
+impl ChatFocus {
+    pub(crate) fn new(toggle_key: KeyCode) -> Self {
+        Self {
+            focused: false,
+            toggle_key,
+        }
+    }
+
+    /// Returns `true` if the typed keys go to the input box.
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// The key focusing the input box.
+    pub fn toggle_key(&self) -> KeyCode {
+        self.toggle_key
+    }
+
+    /// Changes the focus and returns the event announcing the change, if any.
+    pub(crate) fn set(&mut self, focused: bool) -> Option<ChatFocusChanged> {
+        (self.focused != focused).then(|| {
+            self.focused = focused;
+            ChatFocusChanged { focused }
+        })
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    #[test]
+    fn announce_only_changes() {
+        let mut focus = ChatFocus::default();
+
+        assert_eq!(focus.set(true), Some(ChatFocusChanged { focused: true }));
+        assert_eq!(focus.set(true), None);
+        assert!(focus.is_focused());
+    }
+}
@@ -0,0 +1,279 @@
+use bevy::{
+    asset::{AssetServer, Assets, Handle, LoadState},
+    ecs::system::SystemParam,
+    prelude::{Commands, EventWriter, Res, ResMut, Resource, World},
+    text::Font,
+};
+
+use crate::{builder::ChatPluginConfig, BevyPNError};
+
+use super::{events::ChatError, resources::ChatConfig};
+
+/// The font registered from the `font_bytes` option.
+#[derive(Debug, Clone, Resource)]
+pub struct EmbeddedFont(pub Handle<Font>);
+
+/// Registers the `font_bytes` font, so the chat text doesn't need the font files.
+pub fn embedded_font_startup(
+    mut commands: Commands,
+    config: Res<ChatConfig>,
+    fonts: Option<ResMut<Assets<Font>>>,
+) {
+    let (Some(bytes), Some(mut fonts)) = (&config.font_bytes, fonts) else {
+        return;
+    };
+
+    match Font::try_from_bytes(bytes.clone()) {
+        Ok(font) => commands.insert_resource(EmbeddedFont(fonts.add(font))),
+        Err(err) => log::error!("Embedded font not registered: {}", err),
+    }
+}
+
+/// Loads the fonts of the chat text.
+///
+/// Without `AssetServer` only the embedded font is available,
+/// the text spawned without it has no font.
+#[derive(SystemParam)]
+pub struct ChatFonts<'w> {
+    asset_server: Option<Res<'w, AssetServer>>,
+    embedded: Option<Res<'w, EmbeddedFont>>,
+}
+
+impl ChatFonts<'_> {
+    /// The embedded font if there is one, otherwise the font of the style.
+    pub fn load(&self, style: &crate::TextStyle) -> Handle<Font> {
+        match (&self.embedded, &self.asset_server) {
+            (Some(embedded), _) => embedded.0.clone(),
+            (None, Some(asset_server)) => asset_server.load(style.font_path.to_str().unwrap_or("")),
+            (None, None) => Handle::default(),
+        }
+    }
+}
+
+/// Explains why fonts cannot be loaded in the given world, if they cannot.
+///
+/// Without `AssetPlugin` and `TextPlugin` the font handles never resolve
+/// and the chat silently renders nothing.
+pub fn missing_font_support(world: &World) -> Option<&'static str> {
+    if !world.contains_resource::<AssetServer>() {
+        return Some(
+            "AssetServer is missing, add `AssetPlugin` (part of `DefaultPlugins`) to load the chat fonts",
+        );
+    }
+
+    (!world.contains_resource::<Assets<Font>>()).then_some(
+        "Fonts are not registered, add `TextPlugin` (part of `DefaultPlugins`) to render the chat",
+    )
+}
+
+pub fn font_support_check(world: &mut World) {
+    if let Some(reason) = missing_font_support(world) {
+        log::warn!("{}", reason);
+    }
+}
+
+/// The chat text styles without a font path, the embedded font covers all of them.
+pub fn missing_font_paths(config: &ChatPluginConfig) -> Vec<BevyPNError> {
+    if config.font_bytes.is_some() {
+        return Vec::new();
+    }
+
+    [
+        (!config.read_only).then_some(("input box", &config.input_style)),
+        Some(("messages", &config.message_style)),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|(_, style)| style.font_path.as_os_str().is_empty())
+    .map(|(text, _)| BevyPNError::NoFontPath { text: text.into() })
+    .collect()
+}
+
+/// Fonts loaded from the font paths, waiting to be checked.
+#[derive(Debug, Default, Resource)]
+pub struct PendingFonts(Vec<(Handle<Font>, String)>);
+
+/// Reports the text styles without fonts and starts checking the configured font files.
+pub fn font_path_check(
+    mut commands: Commands,
+    config: Res<ChatConfig>,
+    asset_server: Option<Res<AssetServer>>,
+    mut errors: EventWriter<ChatError>,
+) {
+    missing_font_paths(&config).into_iter().for_each(|err| {
+        log::error!("{}", err);
+        errors.send(ChatError::new("font", &err));
+    });
+
+    // `font_support_check` already warned about the missing `AssetServer`.
+    let (None, Some(asset_server)) = (&config.font_bytes, asset_server) else {
+        return;
+    };
+
+    let mut paths = [&config.input_style, &config.message_style]
+        .into_iter()
+        .filter_map(|style| style.font_path.to_str())
+        .filter(|path| !path.is_empty())
+        .collect::<Vec<_>>();
+    paths.dedup();
+
+    commands.insert_resource(PendingFonts(
+        paths
+            .into_iter()
+            .map(|path| (asset_server.load(path), path.to_string()))
+            .collect(),
+    ));
+}
+
+/// Reports the font files that failed to load.
+pub fn font_load_handler(
+    asset_server: Option<Res<AssetServer>>,
+    pending: Option<ResMut<PendingFonts>>,
+    mut errors: EventWriter<ChatError>,
+) {
+    let (Some(asset_server), Some(mut pending)) = (asset_server, pending) else {
+        return;
+    };
+
+    pending
+        .0
+        .retain(|(font, path)| match asset_server.get_load_state(font) {
+            LoadState::Failed => {
+                let err = BevyPNError::FontNotLoaded { path: path.clone() };
+                log::error!("{}", err);
+                errors.send(ChatError::new("font", &err));
+                false
+            }
+            LoadState::Loaded | LoadState::Unloaded => false,
+            LoadState::NotLoaded | LoadState::Loading => true,
+        });
+}
+
+#[cfg(test)]
+mod should {
+    use bevy::{
+        prelude::App,
+        tasks::{IoTaskPool, TaskPool},
+    };
+
+    use super::*;
+
+    #[test]
+    fn warn_about_missing_asset_server() {
+        let app = App::new();
+
+        assert!(missing_font_support(&app.world)
+            .unwrap()
+            .contains("AssetPlugin"));
+    }
+
+    #[test]
+    fn warn_about_missing_font_loader() {
+        let mut app = App::new();
+        app.insert_resource(AssetServer::new(bevy::asset::FileAssetIo::new(
+            "assets", false,
+        )));
+
+        assert!(missing_font_support(&app.world)
+            .unwrap()
+            .contains("TextPlugin"));
+    }
+
+    fn config(builder: crate::builder::ChatPluginConfigBuilder) -> ChatPluginConfig {
+        builder
+            .keyset(crate::Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .build()
+            .unwrap()
+            .config
+    }
+
+    #[test]
+    fn report_styles_without_font_path() {
+        let errors = missing_font_paths(&config(crate::ChatPlugin::builder().input_style(
+            crate::TextStyle {
+                font_path: "fonts/FiraSans-Bold.ttf".into(),
+                ..Default::default()
+            },
+        )));
+
+        assert!(matches!(
+            errors.as_slice(),
+            [BevyPNError::NoFontPath { text }] if text == "messages"
+        ));
+    }
+
+    #[test]
+    fn skip_input_box_font_when_read_only() {
+        let errors = missing_font_paths(&config(crate::ChatPlugin::builder().read_only(true)));
+
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn not_report_font_paths_with_font_path_set() {
+        let style = crate::TextStyle {
+            font_path: "fonts/FiraSans-Bold.ttf".into(),
+            ..Default::default()
+        };
+
+        assert!(missing_font_paths(&config(
+            crate::ChatPlugin::builder()
+                .input_style(style.clone())
+                .message_style(style)
+        ))
+        .is_empty());
+    }
+
+    fn loaded_font(app: &mut App) -> Handle<Font> {
+        app.add_system(|mut commands: Commands, fonts: ChatFonts| {
+            commands.insert_resource(LoadedFont(fonts.load(&crate::TextStyle {
+                font_path: "fonts/FiraSans-Bold.ttf".into(),
+                ..Default::default()
+            })));
+        });
+        app.update();
+
+        app.world.resource::<LoadedFont>().0.clone()
+    }
+
+    #[derive(Resource)]
+    struct LoadedFont(Handle<Font>);
+
+    fn font_app() -> App {
+        IoTaskPool::init(TaskPool::default);
+
+        let mut app = App::new();
+        app.insert_resource(AssetServer::new(bevy::asset::FileAssetIo::new(
+            "assets", false,
+        )));
+        app
+    }
+
+    #[test]
+    fn prefer_embedded_font_over_font_path() {
+        let mut app = font_app();
+        let embedded = Handle::<Font>::weak(bevy::asset::HandleId::random::<Font>());
+        app.insert_resource(EmbeddedFont(embedded.clone()));
+
+        assert_eq!(loaded_font(&mut app), embedded);
+    }
+
+    #[test]
+    fn load_no_font_without_asset_server() {
+        assert_eq!(loaded_font(&mut App::new()), Handle::default());
+    }
+
+    #[test]
+    fn load_font_path_without_embedded_font() {
+        let mut app = font_app();
+        let from_path = app
+            .world
+            .resource::<AssetServer>()
+            .load::<Font, _>("fonts/FiraSans-Bold.ttf");
+
+        assert_eq!(loaded_font(&mut app), from_path);
+    }
+}
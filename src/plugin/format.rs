@@ -0,0 +1,467 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bevy::{
+    prelude::{Query, Res, ResMut, Resource, Without},
+    text::Text,
+    time::{Time, Timer, TimerMode},
+};
+use chrono::{DateTime, Local, SecondsFormat, TimeZone};
+
+use crate::builder::{ChatPluginConfig, ChatStrings};
+
+use super::{
+    links::{replace_message_value, MessageLinks},
+    messages::ChatMessage,
+    render::LocalMessage,
+    resources::{ChatConfig, Clock, MessageFormat},
+};
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+
+const TIME_FORMAT: &str = "%H:%M:%S";
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+const RELATIVE_TIME_REFRESH: Duration = Duration::from_secs(10);
+
+#[derive(Resource)]
+pub struct RelativeTimeRefresh(pub Timer);
+
+impl Default for RelativeTimeRefresh {
+    fn default() -> Self {
+        Self(Timer::new(RELATIVE_TIME_REFRESH, TimerMode::Repeating))
+    }
+}
+
+pub fn relative_time_handler(
+    time: Res<Time>,
+    clock: Res<Clock>,
+    config: Res<ChatConfig>,
+    message_format: Res<MessageFormat>,
+    mut refresh: ResMut<RelativeTimeRefresh>,
+    mut messages: Query<
+        (&ChatMessage, &mut Text, Option<&mut MessageLinks>),
+        Without<LocalMessage>,
+    >,
+) {
+    if !message_format.contains("{relative}") || !refresh.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let now = clock.now();
+    messages
+        .iter_mut()
+        .for_each(|(message, mut text, mut links)| {
+            replace_message_value(
+                &mut text,
+                links.as_deref_mut(),
+                format_message(&message_format, message, now, &config),
+                config.link_color,
+            );
+        });
+}
+
+pub fn format_message(
+    format: &str,
+    message: &ChatMessage,
+    now: SystemTime,
+    config: &ChatPluginConfig,
+) -> String {
+    let payload = if config.normalize_incoming {
+        normalize(&message.payload)
+    } else {
+        message.payload.clone()
+    };
+
+    let relative = format
+        .contains("{relative}")
+        .then(|| relative_time(&message.timetoken, now, &config.strings))
+        .unwrap_or_default();
+
+    let mut formatted = replace_placeholders(
+        &format_timestamps(format, &message.timetoken, &Local),
+        &[
+            ("{username}", message.user_id.as_str()),
+            ("{message}", payload.as_str()),
+            ("{channel}", message.channel.as_str()),
+            (
+                "{group}",
+                message.group.as_deref().unwrap_or(&message.channel),
+            ),
+            ("{relative}", relative.as_str()),
+        ],
+    );
+
+    if config.show_timestamps {
+        formatted.insert_str(
+            0,
+            &timestamp_prefix(&message.timetoken, &config.timestamp_format, &Local),
+        );
+    }
+
+    formatted
+}
+
+/// Replaces the placeholders in a single pass over the format,
+/// so the inserted values, like a payload typed as `{relative}`, are never replaced again.
+fn replace_placeholders(format: &str, values: &[(&str, &str)]) -> String {
+    let mut formatted = String::with_capacity(format.len());
+    let mut rest = format;
+
+    while let Some(start) = rest.find('{') {
+        formatted.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        match values
+            .iter()
+            .find(|(placeholder, _)| rest.starts_with(placeholder))
+        {
+            Some((placeholder, value)) => {
+                formatted.push_str(value);
+                rest = &rest[placeholder.len()..];
+            }
+            None => {
+                formatted.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    formatted.push_str(rest);
+    formatted
+}
+
+/// The `[time] ` column prepended with `show_timestamps` enabled.
+///
+/// It is empty if the timetoken is invalid.
+pub fn timestamp_prefix<Tz>(timetoken: &str, pattern: &str, tz: &Tz) -> String
+where
+    Tz: TimeZone,
+    Tz::Offset: std::fmt::Display,
+{
+    timetoken_to_datetime(timetoken, tz)
+        .map(|sent| format!("[{}] ", sent.format(pattern)))
+        .unwrap_or_default()
+}
+
+/// Replaces the `{time}`, `{date}`, `{datetime}` and `{timestamp}` placeholders
+/// with the timetoken converted to the given time zone.
+pub fn format_timestamps<Tz>(format: &str, timetoken: &str, tz: &Tz) -> String
+where
+    Tz: TimeZone,
+    Tz::Offset: std::fmt::Display,
+{
+    let sent = timetoken_to_datetime(timetoken, tz);
+    let formatted = |pattern: &str| {
+        sent.as_ref()
+            .map(|sent| sent.format(pattern).to_string())
+            .unwrap_or_default()
+    };
+
+    format
+        .replace("{timestamp}", timetoken)
+        .replace(
+            "{datetime}",
+            &sent
+                .as_ref()
+                .map(|sent| sent.to_rfc3339_opts(SecondsFormat::Secs, true))
+                .unwrap_or_default(),
+        )
+        .replace("{time}", &formatted(TIME_FORMAT))
+        .replace("{date}", &formatted(DATE_FORMAT))
+}
+
+pub fn timetoken_to_datetime<Tz: TimeZone>(timetoken: &str, tz: &Tz) -> Option<DateTime<Tz>> {
+    timetoken_to_system_time(timetoken)
+        .map(|sent| DateTime::<chrono::Utc>::from(sent).with_timezone(tz))
+}
+
+/// Normalizes line endings to `\n`, strips trailing whitespace of every line
+/// and leading empty lines.
+pub fn normalize(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .split('\n')
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_start_matches('\n')
+        .trim_end()
+        .to_string()
+}
+
+pub fn timetoken_to_system_time(timetoken: &str) -> Option<SystemTime> {
+    timetoken
+        .parse::<u64>()
+        .ok()
+        .and_then(|timetoken| UNIX_EPOCH.checked_add(Duration::from_nanos(timetoken * 100)))
+}
+
+pub fn system_time_to_timetoken(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| (elapsed.as_nanos() / 100).to_string())
+        .unwrap_or_default()
+}
+
+pub fn relative_time(timetoken: &str, now: SystemTime, strings: &ChatStrings) -> String {
+    timetoken_to_system_time(timetoken)
+        .map(|sent| now.duration_since(sent).unwrap_or_default())
+        .map(|elapsed| {
+            let seconds = elapsed.as_secs();
+
+            if elapsed < strings.just_now_threshold {
+                strings.just_now.clone()
+            } else if seconds < HOUR {
+                strings
+                    .minutes_ago
+                    .replace("{n}", &(seconds / MINUTE).to_string())
+            } else if seconds < DAY {
+                strings
+                    .hours_ago
+                    .replace("{n}", &(seconds / HOUR).to_string())
+            } else {
+                strings
+                    .days_ago
+                    .replace("{n}", &(seconds / DAY).to_string())
+            }
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    use crate::{builder::ChatPluginConfigBuilder, plugin::messages::Message, ChatPlugin, Keyset};
+
+    use test_case::test_case;
+
+    const SENT_AT: &str = "16806024910000000";
+
+    fn sent_at() -> SystemTime {
+        timetoken_to_system_time(SENT_AT).unwrap()
+    }
+
+    #[test_case(0 => "just now")]
+    #[test_case(59 => "just now")]
+    #[test_case(60 => "1m ago")]
+    #[test_case(HOUR - 1 => "59m ago")]
+    #[test_case(HOUR => "1h ago")]
+    #[test_case(DAY - 1 => "23h ago")]
+    #[test_case(DAY => "1d ago")]
+    #[test_case(3 * DAY + HOUR => "3d ago")]
+    fn compute_relative_time(elapsed: u64) -> String {
+        relative_time(
+            SENT_AT,
+            sent_at() + Duration::from_secs(elapsed),
+            &ChatStrings::default(),
+        )
+    }
+
+    #[test]
+    fn convert_system_time_back_to_timetoken() {
+        assert_eq!(system_time_to_timetoken(sent_at()), SENT_AT);
+    }
+
+    #[test]
+    fn treat_future_timetoken_as_just_now() {
+        assert_eq!(
+            relative_time(
+                SENT_AT,
+                sent_at() - Duration::from_secs(10),
+                &ChatStrings::default()
+            ),
+            "just now"
+        );
+    }
+
+    #[test]
+    fn use_configured_strings() {
+        let strings = ChatStrings {
+            minutes_ago: "vor {n} Min.".into(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            relative_time(SENT_AT, sent_at() + Duration::from_secs(120), &strings),
+            "vor 2 Min."
+        );
+    }
+
+    fn config(builder: ChatPluginConfigBuilder) -> ChatPluginConfig {
+        builder
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .build()
+            .unwrap()
+            .config
+    }
+
+    fn message(payload: &str) -> ChatMessage {
+        ChatMessage {
+            index: 0,
+            user_id: "John".into(),
+            payload: payload.into(),
+            channel: "lobby".into(),
+            group: None,
+            timetoken: SENT_AT.into(),
+            raw: None,
+        }
+    }
+
+    #[test]
+    fn format_relative_placeholder() {
+        assert_eq!(
+            format_message(
+                "{username} ({relative}): {message}",
+                &message("Hello"),
+                sent_at() + Duration::from_secs(2 * HOUR),
+                &config(ChatPlugin::builder())
+            ),
+            "John (2h ago): Hello"
+        );
+    }
+
+    #[test_case(Some("friends") => "[friends] Hello" ; "received through group")]
+    #[test_case(None => "[lobby] Hello" ; "received through channel")]
+    fn format_group_placeholder(group: Option<&str>) -> String {
+        format_message(
+            "[{group}] {message}",
+            &ChatMessage {
+                group: group.map(Into::into),
+                ..message("Hello")
+            },
+            sent_at(),
+            &config(ChatPlugin::builder()),
+        )
+    }
+
+    #[test_case("{timestamp}" => SENT_AT ; "timestamp")]
+    #[test_case("{datetime}" => "2023-04-04T10:01:31Z" ; "datetime")]
+    #[test_case("{time}" => "10:01:31" ; "time")]
+    #[test_case("{date}" => "2023-04-04" ; "date")]
+    #[test_case("[{date} {time}] {username}" => "[2023-04-04 10:01:31] {username}" ; "mixed")]
+    fn format_timestamp_placeholders(format: &str) -> String {
+        format_timestamps(format, SENT_AT, &chrono::Utc)
+    }
+
+    #[test]
+    fn leave_time_placeholders_empty_for_invalid_timetoken() {
+        assert_eq!(format_timestamps("[{time}]", "local", &chrono::Utc), "[]");
+    }
+
+    #[test]
+    fn resolve_username_to_publisher_of_decoded_message() {
+        let message = serde_json::from_str::<Message>(
+            r#"{
+                "a": "4",
+                "f": 0,
+                "i": "john",
+                "p": { "t": "16806024910000000", "r": 12 },
+                "k": "sub-c-test",
+                "c": "lobby",
+                "d": "Hello",
+                "b": "lobby"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            format_message(
+                "[{time}] {username}@{channel}: {message}",
+                &ChatMessage::from(&message),
+                sent_at(),
+                &config(ChatPlugin::builder())
+            ),
+            format!(
+                "[{}] john@lobby: Hello",
+                format_timestamps("{time}", SENT_AT, &Local)
+            )
+        );
+    }
+
+    #[test_case("%H:%M" => "[10:01] " ; "hours and minutes")]
+    #[test_case("%H:%M:%S" => "[10:01:31] " ; "with seconds")]
+    #[test_case("%d.%m %H:%M" => "[04.04 10:01] " ; "with date")]
+    fn compute_timestamp_prefix(pattern: &str) -> String {
+        timestamp_prefix(SENT_AT, pattern, &chrono::Utc)
+    }
+
+    #[test]
+    fn skip_timestamp_prefix_for_invalid_timetoken() {
+        assert_eq!(timestamp_prefix("local", "%H:%M", &chrono::Utc), "");
+    }
+
+    #[test]
+    fn render_placeholders_in_payload_literally() {
+        assert_eq!(
+            format_message(
+                "{username} ({relative}) in {channel}: {message}",
+                &message("{relative} {channel}"),
+                sent_at() + Duration::from_secs(2 * HOUR),
+                &config(ChatPlugin::builder())
+            ),
+            "John (2h ago) in lobby: {relative} {channel}"
+        );
+    }
+
+    #[test]
+    fn prepend_timestamps_only_when_configured() {
+        let message = message("Hello");
+
+        assert_eq!(
+            format_message(
+                "{username}: {message}",
+                &message,
+                sent_at(),
+                &config(ChatPlugin::builder())
+            ),
+            "John: Hello"
+        );
+        assert!(format_message(
+            "{username}: {message}",
+            &message,
+            sent_at(),
+            &config(ChatPlugin::builder().show_timestamps(true))
+        )
+        .ends_with("] John: Hello"));
+    }
+
+    #[test_case("Hello\r\nWorld" => "Hello\nWorld" ; "crlf")]
+    #[test_case("Hello\rWorld" => "Hello\nWorld" ; "cr")]
+    #[test_case("Hello   \t" => "Hello" ; "trailing whitespace")]
+    #[test_case("Hello  \r\nWorld  " => "Hello\nWorld" ; "trailing whitespace on every line")]
+    #[test_case("\n\nHello" => "Hello" ; "leading newlines")]
+    #[test_case("  indented" => "  indented" ; "leading spaces")]
+    fn normalize_incoming_text(text: &str) -> String {
+        normalize(text)
+    }
+
+    #[test]
+    fn normalize_only_when_configured() {
+        let message = message("Hello  \r\n");
+
+        assert_eq!(
+            format_message(
+                "{message}",
+                &message,
+                sent_at(),
+                &config(ChatPlugin::builder().normalize_incoming(true))
+            ),
+            "Hello"
+        );
+        assert_eq!(
+            format_message(
+                "{message}",
+                &message,
+                sent_at(),
+                &config(ChatPlugin::builder())
+            ),
+            "Hello  \r\n"
+        );
+        assert_eq!(message.payload, "Hello  \r\n");
+    }
+}
@@ -0,0 +1,196 @@
+use std::{f32::consts::PI, time::Duration};
+
+use bevy::{
+    prelude::{Color, Commands, Component, Entity, EventReader, Query, Res},
+    text::Text,
+    time::{Time, Timer, TimerMode},
+};
+
+use super::{events::HighlightMessage, messages::ChatMessage};
+
+/// Duration of the highlight triggered by a mention of the local user.
+pub const MENTION_HIGHLIGHT_DURATION: Duration = Duration::from_millis(1500);
+
+const HIGHLIGHT_COLOR: Color = Color::GOLD;
+const PULSES: f32 = 3.0;
+
+// Highlight only pulses the color, the layout stays untouched
+// so highlighted messages never overlap their neighbours.
+#[derive(Component, Debug, Clone)]
+pub struct Highlight {
+    pub timer: Timer,
+    /// Colors of the text sections, like the links and the dimmed reaction row,
+    /// restored when the highlight ends.
+    base_colors: Vec<Color>,
+}
+
+impl Highlight {
+    pub fn new(duration: Duration, text: &Text) -> Self {
+        Self {
+            timer: Timer::new(duration, TimerMode::Once),
+            base_colors: text
+                .sections
+                .iter()
+                .map(|section| section.style.color)
+                .collect(),
+        }
+    }
+}
+
+pub fn highlight_requests_handler(
+    mut commands: Commands,
+    mut requests: EventReader<HighlightMessage>,
+    messages: Query<(Entity, &ChatMessage, &Text, Option<&Highlight>)>,
+) {
+    requests.iter().for_each(|request| {
+        messages
+            .iter()
+            .filter(|(_, message, _, _)| message.timetoken == request.message_tt)
+            .for_each(|(entity, _, text, highlight)| {
+                // A running highlight already changed the colors, its base colors are kept.
+                let highlight = match highlight {
+                    Some(highlight) => Highlight {
+                        timer: Timer::new(request.duration, TimerMode::Once),
+                        ..highlight.clone()
+                    },
+                    None => Highlight::new(request.duration, text),
+                };
+
+                commands.entity(entity).insert(highlight);
+            });
+    });
+}
+
+pub fn highlight_handler(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut highlighted: Query<(Entity, &mut Highlight, &mut Text)>,
+) {
+    highlighted
+        .iter_mut()
+        .for_each(|(entity, mut highlight, mut text)| {
+            highlight.timer.tick(time.delta());
+
+            let finished = highlight.timer.finished();
+            if finished {
+                commands.entity(entity).remove::<Highlight>();
+            }
+
+            // The sections added during the highlight keep their own colors.
+            text.sections
+                .iter_mut()
+                .zip(&highlight.base_colors)
+                .for_each(|(section, base_color)| {
+                    section.style.color = if finished {
+                        *base_color
+                    } else {
+                        pulse_color(*base_color, highlight.timer.percent())
+                    };
+                });
+        });
+}
+
+/// Returns `true` if the payload mentions the user, ignoring the case.
+///
+/// With `require_at` only `@username` counts as a mention.
+pub fn mentions(payload: &str, username: &str, require_at: bool) -> bool {
+    if username.is_empty() {
+        return false;
+    }
+
+    let username = username.to_lowercase();
+    let mention = if require_at {
+        format!("@{}", username)
+    } else {
+        username
+    };
+
+    payload.to_lowercase().contains(&mention)
+}
+
+fn pulse_color(base: Color, progress: f32) -> Color {
+    let intensity = (progress * PULSES * PI).sin().abs();
+    let [r, g, b, a] = base.as_rgba_f32();
+    let [hr, hg, hb, _] = HIGHLIGHT_COLOR.as_rgba_f32();
+
+    Color::rgba(
+        r + (hr - r) * intensity,
+        g + (hg - g) * intensity,
+        b + (hb - b) * intensity,
+        a,
+    )
+}
+
+#[cfg(test)]
+mod should {
+    use bevy::{prelude::App, text::TextStyle};
+
+    use crate::plugin::links::split_links;
+
+    use super::*;
+
+    #[test]
+    fn detect_mention_case_insensitive() {
+        assert!(mentions("hey JOHN, look at this", "John", false));
+        assert!(!mentions("hey there", "John", false));
+    }
+
+    #[test]
+    fn require_at_prefix_when_configured() {
+        assert!(mentions("hey @JOHN, look at this", "John", true));
+        assert!(!mentions("hey john, look at this", "John", true));
+    }
+
+    #[test]
+    fn not_mention_without_username() {
+        assert!(!mentions("hey @, look at this", "", true));
+        assert!(!mentions("hey there", "", false));
+    }
+
+    #[test]
+    fn pulse_from_base_color() {
+        assert_eq!(pulse_color(Color::WHITE, 0.0), Color::WHITE);
+        assert_ne!(pulse_color(Color::WHITE, 0.5 / PULSES), Color::WHITE);
+    }
+
+    fn colors(app: &App, entity: Entity) -> Vec<Color> {
+        app.world
+            .get::<Text>(entity)
+            .unwrap()
+            .sections
+            .iter()
+            .map(|section| section.style.color)
+            .collect()
+    }
+
+    #[test]
+    fn restore_link_color_after_mention_highlight() {
+        let mut text = Text::from_section(
+            "jane: john see https://pubnub.com",
+            TextStyle {
+                color: Color::WHITE,
+                ..Default::default()
+            },
+        );
+        split_links(&mut text, Color::BLUE);
+
+        let mut app = App::new();
+        app.init_resource::<Time>().add_system(highlight_handler);
+        let highlight = Highlight::new(Duration::from_secs(1), &text);
+        let entity = app.world.spawn((text, highlight)).id();
+        let startup = app.world.resource::<Time>().startup();
+
+        app.world
+            .resource_mut::<Time>()
+            .update_with_instant(startup + Duration::from_millis(500));
+        app.update();
+        assert_ne!(colors(&app, entity), vec![Color::WHITE, Color::BLUE]);
+
+        app.world
+            .resource_mut::<Time>()
+            .update_with_instant(startup + Duration::from_secs(2));
+        app.update();
+        assert_eq!(colors(&app, entity), vec![Color::WHITE, Color::BLUE]);
+        assert!(app.world.get::<Highlight>(entity).is_none());
+    }
+}
@@ -0,0 +1,135 @@
+use bevy::prelude::Resource;
+
+/// Messages sent from the input box, recalled with Up and Down.
+///
+/// The recalled message is copied into the input box,
+/// so editing it doesn't change the history.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Resource)]
+pub struct InputHistory {
+    entries: Vec<String>,
+    capacity: usize,
+    position: Option<usize>,
+}
+
+impl InputHistory {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+            capacity,
+            position: None,
+        }
+    }
+
+    /// Records a sent message, dropping the oldest one over the capacity.
+    pub fn push(&mut self, message: impl Into<String>) {
+        self.position = None;
+
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push(message.into());
+    }
+
+    /// Recalls the message sent before the current one.
+    ///
+    /// It wraps around to the newest message after the oldest one.
+    pub fn older(&mut self) -> Option<&str> {
+        let last = self.entries.len().checked_sub(1)?;
+
+        let position = match self.position {
+            Some(0) | None => last,
+            Some(position) => position - 1,
+        };
+        self.position = Some(position);
+
+        Some(&self.entries[position])
+    }
+
+    /// Recalls the message sent after the current one.
+    ///
+    /// Going past the newest message returns an empty line.
+    pub fn newer(&mut self) -> Option<&str> {
+        let position = self.position?;
+
+        match position + 1 {
+            next if next < self.entries.len() => {
+                self.position = Some(next);
+                Some(&self.entries[next])
+            }
+            _ => {
+                self.position = None;
+                Some("")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    fn history(entries: &[&str]) -> InputHistory {
+        let mut history = InputHistory::with_capacity(10);
+        entries.iter().for_each(|entry| history.push(*entry));
+        history
+    }
+
+    #[test]
+    fn cycle_through_sent_messages() {
+        let mut history = history(&["first", "second", "third"]);
+
+        assert_eq!(history.older(), Some("third"));
+        assert_eq!(history.older(), Some("second"));
+        assert_eq!(history.older(), Some("first"));
+        assert_eq!(history.newer(), Some("second"));
+        assert_eq!(history.newer(), Some("third"));
+        assert_eq!(history.newer(), Some(""));
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    fn wrap_around_after_oldest_message() {
+        let mut history = history(&["first", "second"]);
+
+        history.older();
+        history.older();
+
+        assert_eq!(history.older(), Some("second"));
+    }
+
+    #[test]
+    fn start_from_newest_after_push() {
+        let mut history = history(&["first", "second"]);
+
+        history.older();
+        history.older();
+        history.push("third");
+
+        assert_eq!(history.older(), Some("third"));
+    }
+
+    #[test]
+    fn drop_oldest_messages_over_capacity() {
+        let mut history = InputHistory::with_capacity(2);
+        ["first", "second", "third"]
+            .into_iter()
+            .for_each(|entry| history.push(entry));
+
+        assert_eq!(history.older(), Some("third"));
+        assert_eq!(history.older(), Some("second"));
+        assert_eq!(history.older(), Some("third"));
+    }
+
+    #[test]
+    fn recall_nothing_without_history() {
+        let mut history = InputHistory::with_capacity(0);
+        history.push("first");
+
+        assert_eq!(history.older(), None);
+        assert_eq!(history.newer(), None);
+    }
+}
@@ -1,155 +1,577 @@
-use std::future;
-
 use bevy::{
     input::keyboard::KeyboardInput,
-    prelude::{Commands, EventReader, KeyCode, Query, Res},
-    tasks::AsyncComputeTaskPool,
-    text::Text,
+    prelude::{Commands, EventReader, EventWriter, Input, KeyCode, Query, Res, ResMut, Resource},
+    window::ReceivedCharacter,
 };
 
-use crate::error;
-
 use super::{
-    resources::{ChannelResource, PubNubClientResource},
-    tasks::PublishTask,
+    clipboard::{copy, cut, paste, ChatClipboard},
+    command::{parse_command, CommandRunner},
+    emoji::expand_shortcodes,
+    events::{ChatError, ChatFocusChanged},
+    focus::ChatFocus,
+    history::InputHistory,
+    publish::{validate_length, Publisher},
+    render::LocalMessages,
+    resources::ChatConfig,
     text::InputBox,
+    word_filter::filter_outgoing,
 };
 
 pub fn keyboard_handler(
     mut commands: Commands,
     mut key_evr: EventReader<KeyboardInput>,
-    mut input: Query<(&mut InputBox, &mut Text)>,
-    pubnub: Res<PubNubClientResource>,
-    channel: Res<ChannelResource>,
+    mut characters: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut modifiers: ResMut<ModifierState>,
+    mut clipboard: ResMut<ChatClipboard>,
+    mut input: Query<&mut InputBox>,
+    mut publisher: Publisher,
+    config: Res<ChatConfig>,
+    mut local_messages: LocalMessages,
+    mut errors: EventWriter<ChatError>,
+    mut focus: ResMut<ChatFocus>,
+    mut focus_changed: EventWriter<ChatFocusChanged>,
+    mut history: ResMut<InputHistory>,
+    mut command_runner: CommandRunner,
 ) {
+    modifiers.shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    modifiers.ctrl = keys.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    let modifiers = *modifiers;
+    let was_focused = focus.is_focused();
+
     key_evr
         .iter()
         .filter(|key| key.state.is_pressed())
         .filter_map(|key| key.key_code)
         .for_each(|key| {
+            // The key opening the chat is not typed.
+            if !focus.is_focused() {
+                if key == focus.toggle_key() {
+                    focus_changed.send_batch(focus.set(true));
+                }
+                return;
+            }
+
             match key {
+                KeyCode::Escape => {
+                    focus_changed.send_batch(focus.set(false));
+                }
+                KeyCode::Return if modifiers.shift => {
+                    input.iter_mut().for_each(|mut input| input.insert('\n'));
+                }
                 KeyCode::Return => {
-                    let thread_pool = AsyncComputeTaskPool::get();
                     input.iter_mut().for_each(|mut input| {
-                        let message = input.1.sections[0].value.clone();
-                        input.1.sections[0].value.clear();
-                        input.0.cursor = 0;
-                        input.0.selection = None;
-
-                        let pubnub = pubnub.clone();
-                        let channel = channel.clone();
-                        let task = thread_pool.spawn(async move {
-                            pubnub
-                                .publish_message(message)
-                                .channel(channel)
-                                .execute_blocking()
-                                .map(|_| ())
-                                .map_err(Into::into)
-                        });
-
-                        commands.spawn(PublishTask(task));
+                        let message = match parse_command(&input.value) {
+                            None => input.value.clone(),
+                            Some(Ok(command)) => {
+                                match command_runner.run(command, &config.username) {
+                                    Some(message) => message,
+                                    None => {
+                                        history.push(input.take());
+                                        return;
+                                    }
+                                }
+                            }
+                            Some(Err(err)) => {
+                                errors.send(ChatError::new("command", &err));
+                                return;
+                            }
+                        };
+
+                        let message = if config.emoji_shortcodes {
+                            expand_shortcodes(&message, &config.custom_emoji)
+                        } else {
+                            message
+                        };
+
+                        let message = match filter_outgoing(
+                            message,
+                            &config.word_filter,
+                            config.word_filter_mode,
+                        ) {
+                            Ok(message) => message,
+                            Err(err) => {
+                                errors.send(ChatError::new("publish", &err));
+                                return;
+                            }
+                        };
+
+                        if !config.can_publish(&message) {
+                            local_messages
+                                .show(&mut commands, config.strings.publish_rejected.clone());
+                            return;
+                        }
+
+                        // The text stays in the box, so the user can shorten it.
+                        if let Err(err) = validate_length(&message, config.max_message_length) {
+                            errors.send(ChatError::new("publish", &err));
+                            return;
+                        }
+
+                        input.take();
+                        history.push(message.clone());
+
+                        if let Err(err) = publisher.publish(&mut commands, message, None) {
+                            errors.send(ChatError::new("publish", &err));
+                        }
                     });
-                    None
+                }
+                KeyCode::Back if modifiers.ctrl => {
+                    input.iter_mut().for_each(|mut input| input.delete_word());
                 }
                 KeyCode::Back => {
+                    input.iter_mut().for_each(|mut input| input.backspace());
+                }
+                KeyCode::Left if modifiers.ctrl => {
+                    input
+                        .iter_mut()
+                        .for_each(|mut input| input.move_word_left());
+                }
+                KeyCode::Right if modifiers.ctrl => {
+                    input
+                        .iter_mut()
+                        .for_each(|mut input| input.move_word_right());
+                }
+                KeyCode::Left if modifiers.shift => {
+                    input.iter_mut().for_each(|mut input| input.select_left());
+                }
+                KeyCode::Right if modifiers.shift => {
+                    input.iter_mut().for_each(|mut input| input.select_right());
+                }
+                KeyCode::Left => {
+                    input.iter_mut().for_each(|mut input| input.move_left());
+                }
+                KeyCode::Right => {
+                    input.iter_mut().for_each(|mut input| input.move_right());
+                }
+                KeyCode::Up => {
+                    if let Some(message) = history.older() {
+                        input
+                            .iter_mut()
+                            .for_each(|mut input| input.set_value(message));
+                    }
+                }
+                KeyCode::Down => {
+                    if let Some(message) = history.newer() {
+                        input
+                            .iter_mut()
+                            .for_each(|mut input| input.set_value(message));
+                    }
+                }
+                KeyCode::Home if modifiers.shift => {
+                    input.iter_mut().for_each(|mut input| input.select_home());
+                }
+                KeyCode::End if modifiers.shift => {
+                    input.iter_mut().for_each(|mut input| input.select_end());
+                }
+                KeyCode::Home => {
+                    input.iter_mut().for_each(|mut input| input.move_home());
+                }
+                KeyCode::End => {
+                    input.iter_mut().for_each(|mut input| input.move_end());
+                }
+                KeyCode::V if modifiers.ctrl => {
                     input.iter_mut().for_each(|mut input| {
-                        input.1.sections[0].value.pop();
+                        paste(&mut input, &mut clipboard, |character| {
+                            config.allows_character(character)
+                        })
                     });
-                    None
                 }
-                _ => characters_filter(key),
+                KeyCode::C if modifiers.ctrl => {
+                    input.iter().for_each(|input| copy(input, &mut clipboard));
+                }
+                KeyCode::X if modifiers.ctrl => {
+                    input
+                        .iter_mut()
+                        .for_each(|mut input| cut(&mut input, &mut clipboard));
+                }
+                _ => {}
             }
-            .map(|character| {
-                input.iter_mut().for_each(|mut input| {
-                    input.1.sections[0].value.push(character);
-                });
-            });
         });
+
+    // The characters are composed by the OS, so they follow the keyboard layout and the IME.
+    // The character of the key opening the chat arrives in the same frame and is skipped.
+    characters
+        .iter()
+        .filter(|_| was_focused && focus.is_focused())
+        .map(|received| received.char)
+        .filter(|character| !character.is_control() && config.allows_character(*character))
+        .for_each(|character| {
+            input
+                .iter_mut()
+                .for_each(|mut input| input.insert(character));
+        });
+}
+
+/// Modifier keys changing the meaning of the control keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Resource)]
+pub struct ModifierState {
+    pub shift: bool,
+    pub ctrl: bool,
 }
 
-const SERIALIZED_LETTERS_POSITION: usize = 3;
-const SERIALIZED_DIGITS_POSITION: usize = 4;
-const SERIALIZED_NUMPAD_POSITION: usize = 7;
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    use bevy::{
+        asset::{AssetServer, FileAssetIo},
+        input::ButtonState,
+        prelude::{App, Entity},
+        tasks::{AsyncComputeTaskPool, TaskPool},
+        time::Time,
+    };
 
-fn characters_filter(key_code: KeyCode) -> Option<char> {
-    special_characters_filter(&key_code).or_else(|| {
-        serde_json::to_string(&key_code)
-            .ok()
-            .and_then(|serialized| {
-                letter_filter(&serialized).or_else(|| digits_filter(&serialized))
+    use crate::{
+        char_policy,
+        plugin::{
+            clipboard::ChatClipboard,
+            events::{ClearChat, SetUsername},
+            render::PendingMessages,
+            resources::{
+                ChannelResource, ChatMessageStyle, Clock, MessageCounter, PubNubClientResource,
+                PubNubSubscribeResource,
+            },
+            tasks::PublishTask,
+        },
+        ChatPlugin, Keyset,
+    };
+
+    fn app() -> App {
+        app_with_toggle_key(KeyCode::Return)
+    }
+
+    fn app_with_toggle_key(toggle_key: KeyCode) -> App {
+        app_with(
+            ChatPlugin::builder()
+                .keyset(Keyset {
+                    publish_key: "pub-c-test",
+                    subscribe_key: "sub-c-test",
+                })
+                .toggle_key(toggle_key)
+                .build()
+                .unwrap(),
+            toggle_key,
+        )
+    }
+
+    fn app_with(chat: ChatPlugin, toggle_key: KeyCode) -> App {
+        AsyncComputeTaskPool::init(TaskPool::default);
+
+        let mut app = App::new();
+        app.insert_resource(PubNubClientResource(chat.pubnub.clone()))
+            .insert_resource(ChannelResource(chat.config.channel().map(str::to_string)))
+            .insert_resource(PubNubSubscribeResource {
+                channels: chat.config.channels.clone(),
+                ..PubNubSubscribeResource::for_tests()
             })
-    })
-}
+            .insert_resource(ChatMessageStyle(chat.config.message_style.clone()))
+            .insert_resource(ChatConfig(chat.config))
+            .insert_resource(AssetServer::new(FileAssetIo::new("assets", false)))
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<ModifierState>()
+            .init_resource::<ChatClipboard>()
+            .init_resource::<Clock>()
+            .init_resource::<Time>()
+            .init_resource::<MessageCounter>()
+            .init_resource::<PendingMessages>()
+            .insert_resource(ChatFocus::new(toggle_key))
+            .insert_resource(InputHistory::with_capacity(10))
+            .add_event::<KeyboardInput>()
+            .add_event::<ReceivedCharacter>()
+            .add_event::<ChatError>()
+            .add_event::<ChatFocusChanged>()
+            .add_event::<SetUsername>()
+            .add_event::<ClearChat>()
+            .add_system(keyboard_handler);
+        app.world.spawn(InputBox::new());
+        app
+    }
 
-fn letter_filter(serialized: &String) -> Option<char> {
-    (serialized.len() == SERIALIZED_LETTERS_POSITION)
-        .then(|| serialized.chars().nth(1))
-        .flatten()
-}
+    fn press(app: &mut App, keys: &[KeyCode]) {
+        keys.iter().for_each(|key| {
+            app.world.send_event(KeyboardInput {
+                scan_code: 0,
+                key_code: Some(*key),
+                state: ButtonState::Pressed,
+            });
+            app.update();
+        });
+    }
 
-fn digits_filter(serialized: &String) -> Option<char> {
-    serialized
-        .starts_with("\"Key")
-        .then(|| serialized.chars().nth(SERIALIZED_DIGITS_POSITION))
-        .flatten()
-        .or_else(|| {
-            serialized
-                .starts_with("\"Numpad")
-                .then(|| serialized.chars().nth(SERIALIZED_NUMPAD_POSITION))
-                .flatten()
-        })
-}
+    fn received(character: char) -> ReceivedCharacter {
+        ReceivedCharacter {
+            window: Entity::from_raw(0),
+            char: character,
+        }
+    }
 
-fn special_characters_filter(key_code: &KeyCode) -> Option<char> {
-    match key_code {
-        KeyCode::Space => Some(' '),
-        KeyCode::Comma => Some(','),
-        KeyCode::Period => Some('.'),
-        KeyCode::Slash => Some('/'),
-        KeyCode::Semicolon => Some(';'),
-        KeyCode::Apostrophe => Some('\''),
-        KeyCode::Backslash => Some('\\'),
-        KeyCode::LBracket => Some('['),
-        KeyCode::RBracket => Some(']'),
-        KeyCode::Grave => Some('`'),
-        KeyCode::Minus => Some('-'),
-        KeyCode::Equals => Some('='),
-        _ => None,
+    /// Sends the characters like an IME, without the key presses.
+    fn type_text(app: &mut App, text: &str) {
+        text.chars().for_each(|character| {
+            app.world.send_event(received(character));
+            app.update();
+        });
     }
-}
 
-#[cfg(test)]
-mod should {
-    use super::*;
+    /// Presses a key producing a character, both arrive in the same frame.
+    fn press_character(app: &mut App, key: KeyCode, character: char) {
+        app.world.send_event(KeyboardInput {
+            scan_code: 0,
+            key_code: Some(key),
+            state: ButtonState::Pressed,
+        });
+        app.world.send_event(received(character));
+        app.update();
+    }
+
+    fn typed(app: &mut App) -> String {
+        app.world
+            .query::<&InputBox>()
+            .single(&app.world)
+            .value
+            .clone()
+    }
+
+    #[test]
+    fn ignore_keys_when_unfocused() {
+        let mut app = app();
+
+        type_text(&mut app, "hi");
+        press(&mut app, &[KeyCode::Back]);
+
+        assert_eq!(typed(&mut app), "");
+        assert!(!app.world.resource::<ChatFocus>().is_focused());
+    }
+
+    #[test]
+    fn type_after_focusing() {
+        let mut app = app();
+
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "hi");
+
+        assert_eq!(typed(&mut app), "hi");
+        assert!(app.world.resource::<ChatFocus>().is_focused());
+    }
+
+    #[test]
+    fn stop_typing_after_escape() {
+        let mut app = app();
+
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "h");
+        press(&mut app, &[KeyCode::Escape]);
+        type_text(&mut app, "i");
+
+        assert_eq!(typed(&mut app), "h");
+        assert!(!app.world.resource::<ChatFocus>().is_focused());
+    }
+
+    #[test]
+    fn focus_with_configured_key_without_typing_it() {
+        let mut app = app_with_toggle_key(KeyCode::T);
+
+        press(&mut app, &[KeyCode::Return]);
+        press_character(&mut app, KeyCode::T, 't');
+        type_text(&mut app, "hi");
+
+        assert_eq!(typed(&mut app), "hi");
+        assert!(app.world.resource::<ChatFocus>().is_focused());
+    }
+
+    #[test]
+    fn type_configured_key_when_focused() {
+        let mut app = app_with_toggle_key(KeyCode::T);
+
+        press_character(&mut app, KeyCode::T, 't');
+        press_character(&mut app, KeyCode::T, 't');
+        press(&mut app, &[KeyCode::Escape]);
+
+        assert_eq!(typed(&mut app), "t");
+        assert!(!app.world.resource::<ChatFocus>().is_focused());
+    }
+
+    #[test]
+    fn recall_sent_messages_without_changing_them() {
+        let mut app = app();
+
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "hi");
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "ok");
+        press(&mut app, &[KeyCode::Return, KeyCode::Up, KeyCode::Up]);
+        assert_eq!(typed(&mut app), "hi");
+
+        type_text(&mut app, "!");
+        press(&mut app, &[KeyCode::Down]);
+        assert_eq!(typed(&mut app), "ok");
+
+        press(&mut app, &[KeyCode::Up]);
+        assert_eq!(typed(&mut app), "hi");
+
+        press(&mut app, &[KeyCode::Down, KeyCode::Down]);
+        assert_eq!(typed(&mut app), "");
+    }
+
+    #[test]
+    fn type_composed_unicode_characters() {
+        let mut app = app();
+
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "Zażółć, 日本語 😄");
+
+        assert_eq!(typed(&mut app), "Zażółć, 日本語 😄");
+    }
+
+    #[test]
+    fn skip_control_characters() {
+        let mut app = app();
+
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "a\u{8}\u{16}\rb\u{7f}");
+
+        assert_eq!(typed(&mut app), "ab");
+    }
+
+    fn sent<E: bevy::ecs::event::Event + Clone>(app: &App) -> Vec<E> {
+        app.world
+            .resource::<bevy::ecs::event::Events<E>>()
+            .iter_current_update_events()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn change_username_with_nick_command() {
+        let mut app = app();
+
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "/nick Jane");
+        press(&mut app, &[KeyCode::Return]);
+
+        assert_eq!(sent::<SetUsername>(&app), vec![SetUsername("Jane".into())]);
+        assert_eq!(typed(&mut app), "");
+    }
+
+    #[test]
+    fn clear_chat_with_clear_command() {
+        let mut app = app();
+
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "/clear");
+        press(&mut app, &[KeyCode::Return]);
+
+        assert_eq!(sent::<ClearChat>(&app), vec![ClearChat]);
+        assert_eq!(
+            app.world.query::<&PublishTask>().iter(&app.world).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn publish_action_with_me_command() {
+        let mut app = app();
+
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "/me waves");
+        press(&mut app, &[KeyCode::Return]);
+
+        assert_eq!(
+            app.world
+                .query::<&PublishTask>()
+                .iter(&app.world)
+                .map(|task| task.message.clone())
+                .collect::<Vec<_>>(),
+            vec![format!(
+                "* {} waves",
+                app.world.resource::<ChatConfig>().username
+            )]
+        );
+    }
+
+    #[test]
+    fn report_unknown_command() {
+        let mut app = app();
+
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "/dance");
+        press(&mut app, &[KeyCode::Return]);
+
+        assert_eq!(
+            sent::<ChatError>(&app)
+                .into_iter()
+                .map(|error| error.context)
+                .collect::<Vec<_>>(),
+            vec!["command: unknown command"]
+        );
+        assert_eq!(typed(&mut app), "/dance");
+    }
+
+    #[test]
+    fn insert_newline_with_shift_return() {
+        let mut app = app();
+
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "first");
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::LShift);
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "second");
+
+        assert_eq!(typed(&mut app), "first\nsecond");
+        assert_eq!(
+            app.world.query::<&PublishTask>().iter(&app.world).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn publish_multiline_message_with_return() {
+        let mut app = app();
+
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "first");
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::LShift);
+        press(&mut app, &[KeyCode::Return]);
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .release(KeyCode::LShift);
+        type_text(&mut app, "second");
+        press(&mut app, &[KeyCode::Return]);
+
+        assert_eq!(
+            app.world
+                .query::<&PublishTask>()
+                .iter(&app.world)
+                .map(|task| task.message.clone())
+                .collect::<Vec<_>>(),
+            vec!["first\nsecond"]
+        );
+        assert_eq!(typed(&mut app), "");
+    }
+
+    #[test]
+    fn drop_disallowed_characters() {
+        let mut app = app_with(
+            ChatPlugin::builder()
+                .keyset(Keyset {
+                    publish_key: "pub-c-test",
+                    subscribe_key: "sub-c-test",
+                })
+                .allowed_characters(char_policy::ascii_only)
+                .build()
+                .unwrap(),
+            KeyCode::Return,
+        );
+
+        press(&mut app, &[KeyCode::Return]);
+        type_text(&mut app, "Zoë 👍 ok");
 
-    use test_case::test_case;
-
-    #[test_case(KeyCode::A => Some('A'))]
-    #[test_case(KeyCode::B => Some('B'))]
-    #[test_case(KeyCode::C => Some('C'))]
-    #[test_case(KeyCode::F1 => None)]
-    #[test_case(KeyCode::F2 => None)]
-    #[test_case(KeyCode::Left => None)]
-    #[test_case(KeyCode::Right => None)]
-    #[test_case(KeyCode::Key1 => Some('1'))]
-    #[test_case(KeyCode::Key2 => Some('2'))]
-    #[test_case(KeyCode::Key3 => Some('3'))]
-    #[test_case(KeyCode::Numpad1 => Some('1'))]
-    #[test_case(KeyCode::Numpad2 => Some('2'))]
-    #[test_case(KeyCode::Numpad3 => Some('3'))]
-    #[test_case(KeyCode::Space => Some(' '))]
-    #[test_case(KeyCode::Comma => Some(','))]
-    #[test_case(KeyCode::Period => Some('.'))]
-    #[test_case(KeyCode::Slash => Some('/'))]
-    #[test_case(KeyCode::Semicolon => Some(';'))]
-    #[test_case(KeyCode::Apostrophe => Some('\''))]
-    #[test_case(KeyCode::Backslash => Some('\\'))]
-    #[test_case(KeyCode::LBracket => Some('['))]
-    #[test_case(KeyCode::RBracket => Some(']'))]
-    #[test_case(KeyCode::Grave => Some('`'))]
-    #[test_case(KeyCode::Minus => Some('-'))]
-    #[test_case(KeyCode::Equals => Some('='))]
-    fn filter_not_characters_codes(key_code: KeyCode) -> Option<char> {
-        characters_filter(key_code)
+        assert_eq!(typed(&mut app), "Zo  ok");
     }
 }
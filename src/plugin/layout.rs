@@ -0,0 +1,686 @@
+use std::cmp::Reverse;
+
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::{
+        Added, BuildChildren, Color, Commands, EventReader, EventWriter, NodeBundle, Or, Query,
+        Rect, Res, ResMut, Resource, Transform, Vec2, Vec3, Visibility, With, Without,
+    },
+    sprite::Anchor,
+    text::{Text2dBounds, TextLayoutInfo},
+    ui::{FlexDirection, Node, Overflow, PositionType, Size, Style, UiRect, Val},
+    window::{PrimaryWindow, Window},
+};
+
+use super::{
+    background::ChatBackground,
+    events::{ScrollBy, ScrollToBottom, ScrollToTop, UnreadBelowChanged},
+    messages::ChatMessage,
+    render::{EmptyState, MessagesColumn},
+    resources::{ChatBounds, ChatConfig, ChatMessageStyle, ChatTransform},
+};
+
+/// Default position of the newest message.
+pub const MESSAGES_ORIGIN: Vec2 = Vec2::new(30.0, 70.0);
+
+/// Scroll position of the chat messages.
+///
+/// The offset is measured in pixels from the newest message,
+/// so `0.0` means the chat shows the latest messages.
+///
+/// Use the [`ScrollToBottom`], [`ScrollToTop`] and [`ScrollBy`] events to change it.
+/// The mouse wheel scrolls the messages while the cursor is over the chat bounds.
+#[derive(Debug, Clone, PartialEq, Resource)]
+pub struct ScrollState {
+    offset: f32,
+    max_offset: f32,
+    content_height: f32,
+    follow: bool,
+}
+
+impl Default for ScrollState {
+    fn default() -> Self {
+        Self {
+            offset: 0.0,
+            max_offset: 0.0,
+            content_height: 0.0,
+            follow: true,
+        }
+    }
+}
+
+impl ScrollState {
+    /// Current scroll offset in pixels from the newest message.
+    pub fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    /// The largest offset the messages can be scrolled to.
+    pub fn max_offset(&self) -> f32 {
+        self.max_offset
+    }
+
+    /// Returns `true` if the chat follows new messages as they arrive.
+    pub fn is_following(&self) -> bool {
+        self.follow
+    }
+
+    /// Updates the content size, returns `true` if it grew while the chat is scrolled up.
+    ///
+    /// The offset grows with the content then, so the shown messages stay in place.
+    pub(crate) fn set_content(&mut self, content_height: f32, viewport_height: f32) -> bool {
+        let grown = content_height - self.content_height;
+        let arrived_below = !self.follow && grown > 0.0;
+
+        if arrived_below {
+            self.offset += grown;
+        }
+
+        self.content_height = content_height;
+        self.max_offset = (content_height - viewport_height).max(0.0);
+        self.offset = clamp_offset(self.offset, self.max_offset);
+        arrived_below
+    }
+
+    pub(crate) fn scroll_to_bottom(&mut self) {
+        self.offset = 0.0;
+        self.follow = true;
+    }
+
+    pub(crate) fn scroll_to_top(&mut self) {
+        self.offset = self.max_offset;
+        self.follow = self.offset == 0.0;
+    }
+
+    pub(crate) fn scroll_by(&mut self, delta: f32) {
+        self.offset = clamp_offset(self.offset + delta, self.max_offset);
+        self.follow = self.offset == 0.0;
+    }
+}
+
+/// This resource tells if new messages arrived below the scrolled up chat.
+///
+/// It is cleared once the chat is scrolled to the newest message again,
+/// e.g. with the [`ScrollToBottom`] event sent by a jump to latest button.
+/// Every change is announced with the [`UnreadBelowChanged`] event.
+///
+/// [`UnreadBelowChanged`]: crate::UnreadBelowChanged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Resource)]
+pub struct HasUnreadBelow(pub bool);
+
+/// Keeps the offset between the newest message and the oldest one.
+fn clamp_offset(offset: f32, max_offset: f32) -> f32 {
+    offset.clamp(0.0, max_offset.max(0.0))
+}
+
+/// Scrolls the messages with the mouse wheel while the cursor is over the [`ChatBounds`].
+pub fn scroll_wheel_handler(
+    mut wheel: EventReader<MouseWheel>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    bounds: Res<ChatBounds>,
+    message_style: Res<ChatMessageStyle>,
+    config: Res<ChatConfig>,
+    mut scroll_by: EventWriter<ScrollBy>,
+) {
+    // Without a window there is no cursor to check, e.g. in headless apps.
+    let hovered = windows.get_single().map_or(true, |window| {
+        window
+            .cursor_position()
+            .map_or(false, |cursor| bounds.contains(cursor))
+    });
+    let line_height = line_height(message_style.font_size, config.line_spacing);
+
+    let delta = wheel
+        .iter()
+        .map(|event| wheel_delta(event.unit, event.y, line_height))
+        .sum::<f32>();
+
+    if hovered && delta != 0.0 {
+        scroll_by.send(ScrollBy(delta));
+    }
+}
+
+/// Scroll distance in pixels, scrolling the wheel up moves towards older messages.
+fn wheel_delta(unit: MouseScrollUnit, y: f32, line_height: f32) -> f32 {
+    match unit {
+        MouseScrollUnit::Line => y * line_height,
+        MouseScrollUnit::Pixel => y,
+    }
+}
+
+pub fn layout_handler(
+    mut scroll: ResMut<ScrollState>,
+    mut scroll_to_bottom: EventReader<ScrollToBottom>,
+    mut scroll_to_top: EventReader<ScrollToTop>,
+    mut scroll_by: EventReader<ScrollBy>,
+    message_style: Res<ChatMessageStyle>,
+    config: Res<ChatConfig>,
+    chat_transform: Res<ChatTransform>,
+    bounds: Res<ChatBounds>,
+    mut messages: Query<
+        (
+            &ChatMessage,
+            Option<&TextLayoutInfo>,
+            Option<&Anchor>,
+            &mut Transform,
+            &mut Visibility,
+        ),
+        Without<Node>,
+    >,
+    mut columns: Query<(&Node, &mut Style), With<MessagesColumn>>,
+    mut unread_below: ResMut<HasUnreadBelow>,
+    mut unread_changes: EventWriter<UnreadBelowChanged>,
+) {
+    let line_height = line_height(message_style.font_size, config.line_spacing);
+    let viewport_height = match columns.get_single() {
+        Ok(_) => bounds.height().max(1.0),
+        Err(_) => visible_height(bounds.height(), line_height, config.max_visible_lines),
+    };
+
+    let mut rows = messages.iter_mut().collect::<Vec<_>>();
+    rows.sort_by_key(|(message, ..)| Reverse(message.index));
+    let heights = rows
+        .iter()
+        .map(|(_, layout, ..)| row_height(layout.map_or(0.0, |layout| layout.size.y), line_height))
+        .collect::<Vec<_>>();
+
+    let content_height = columns
+        .get_single()
+        .map(|(node, _)| node.size().y)
+        .unwrap_or(heights.iter().sum());
+    let arrived_below = scroll.set_content(content_height, viewport_height);
+
+    scroll_by
+        .iter()
+        .for_each(|ScrollBy(delta)| scroll.scroll_by(*delta));
+    scroll_to_top.iter().for_each(|_| scroll.scroll_to_top());
+    scroll_to_bottom
+        .iter()
+        .for_each(|_| scroll.scroll_to_bottom());
+
+    let unread = !scroll.is_following() && (unread_below.0 || arrived_below);
+    if unread != unread_below.0 {
+        unread_below.0 = unread;
+        unread_changes.send(UnreadBelowChanged { unread });
+    }
+
+    // Bevy UI stacks and clips the messages, only the column has to be moved.
+    if let Ok((_, mut style)) = columns.get_single_mut() {
+        style.position.bottom = Val::Px(-scroll.offset);
+        return;
+    }
+
+    let offsets = row_offsets(&heights, scroll.offset);
+    rows.iter_mut().zip(heights).zip(offsets).for_each(
+        |(((_, _, anchor, transform, visibility), height), y)| {
+            transform.translation =
+                message_position(chat_transform.translation, row_origin(&bounds, *anchor), y);
+            **visibility = if row_visible(y, height, viewport_height) {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        },
+    );
+}
+
+/// Spawns the clipped panel with the [`MessagesColumn`] used by [`RenderMode::BevyUi`].
+///
+/// [`RenderMode::BevyUi`]: crate::RenderMode::BevyUi
+pub fn spawn_messages_panel(commands: &mut Commands, bounds: &Rect, background: Color) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Px(bounds.min.x),
+                        bottom: Val::Px(bounds.min.y),
+                        ..Default::default()
+                    },
+                    size: Size::new(Val::Px(bounds.width()), Val::Px(bounds.height())),
+                    overflow: Overflow::Hidden,
+                    ..Default::default()
+                },
+                background_color: background.into(),
+                ..Default::default()
+            },
+            ChatBackground,
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: UiRect {
+                            left: Val::Px(0.0),
+                            bottom: Val::Px(0.0),
+                            ..Default::default()
+                        },
+                        size: Size::width(Val::Percent(100.0)),
+                        flex_direction: FlexDirection::Column,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                MessagesColumn,
+            ));
+        });
+}
+
+/// Wraps the new messages to the width of the [`ChatBounds`].
+pub fn text_bounds_handler(
+    bounds: Res<ChatBounds>,
+    mut lines: Query<&mut Text2dBounds, Or<(Added<ChatMessage>, Added<EmptyState>)>>,
+) {
+    lines.iter_mut().for_each(|mut text_bounds| {
+        text_bounds.size.x = bounds.width();
+    });
+}
+
+/// Height of a message row.
+pub fn line_height(font_size: f32, line_spacing: f32) -> f32 {
+    font_size * line_spacing
+}
+
+/// Height of the rows shown at once, at most `max_visible_lines` rows fit in the bounds.
+fn visible_height(bounds_height: f32, line_height: f32, max_visible_lines: Option<usize>) -> f32 {
+    let height = bounds_height.max(1.0);
+
+    max_visible_lines.map_or(height, |lines| height.min(lines as f32 * line_height))
+}
+
+/// Height of a message row, wrapped and multi-line messages take their whole text height.
+///
+/// The messages not laid out yet take a single line.
+fn row_height(text_height: f32, line_height: f32) -> f32 {
+    text_height.max(line_height)
+}
+
+/// Returns `true` if the whole row fits in the viewport, the Text2d messages can't be clipped.
+fn row_visible(row_offset: f32, row_height: f32, viewport_height: f32) -> bool {
+    row_offset >= 0.0 && row_offset + row_height <= viewport_height
+}
+
+/// Offsets of the rows stacked from the newest one, every row starts above the previous one.
+fn row_offsets(heights: &[f32], scroll_offset: f32) -> Vec<f32> {
+    heights
+        .iter()
+        .scan(-scroll_offset, |offset, height| {
+            let row = *offset;
+            *offset += height;
+            Some(row)
+        })
+        .collect()
+}
+
+/// Corner of the [`ChatBounds`] the row is anchored to.
+///
+/// The right-aligned own messages are anchored at the right edge, the others at the left one.
+fn row_origin(bounds: &Rect, anchor: Option<&Anchor>) -> Vec2 {
+    match anchor {
+        Some(Anchor::BottomRight) => Vec2::new(bounds.max.x, bounds.min.y),
+        _ => bounds.min,
+    }
+}
+
+/// Position of the anchored corner of a message row relative to the chat anchor.
+pub fn message_position(anchor: Vec3, origin: Vec2, row_offset: f32) -> Vec3 {
+    anchor + origin.extend(0.0) + Vec3::Y * row_offset
+}
+
+#[cfg(test)]
+mod should {
+    use bevy::prelude::{App, Entity};
+
+    use crate::{ChatPlugin, Keyset};
+
+    use super::*;
+
+    fn scrollable() -> ScrollState {
+        let mut scroll = ScrollState::default();
+        scroll.set_content(300.0, 100.0);
+        scroll
+    }
+
+    #[test]
+    fn clamp_scroll_by() {
+        let mut scroll = scrollable();
+
+        scroll.scroll_by(500.0);
+        assert_eq!(scroll.offset(), 200.0);
+
+        scroll.scroll_by(-1000.0);
+        assert_eq!(scroll.offset(), 0.0);
+    }
+
+    #[test]
+    fn stop_following_when_scrolled_up() {
+        let mut scroll = scrollable();
+
+        scroll.scroll_by(50.0);
+        assert!(!scroll.is_following());
+
+        scroll.set_content(330.0, 100.0);
+        assert_eq!(scroll.offset(), 80.0);
+    }
+
+    #[test]
+    fn follow_again_after_scroll_to_bottom() {
+        let mut scroll = scrollable();
+
+        scroll.scroll_to_top();
+        assert_eq!(scroll.offset(), 200.0);
+        assert!(!scroll.is_following());
+
+        scroll.scroll_to_bottom();
+        assert_eq!(scroll.offset(), 0.0);
+        assert!(scroll.is_following());
+
+        scroll.set_content(400.0, 100.0);
+        assert_eq!(scroll.offset(), 0.0);
+    }
+
+    fn config(max_visible_lines: Option<usize>) -> ChatConfig {
+        let builder = ChatPlugin::builder().keyset(Keyset {
+            publish_key: "pub-c-test",
+            subscribe_key: "sub-c-test",
+        });
+        let builder = match max_visible_lines {
+            Some(lines) => builder.max_visible_lines(lines),
+            None => builder,
+        };
+
+        ChatConfig(builder.build().unwrap().config)
+    }
+
+    fn layout_app() -> App {
+        layout_app_with(config(None))
+    }
+
+    fn layout_app_with(config: ChatConfig) -> App {
+        let mut app = App::new();
+        app.insert_resource(config)
+            .init_resource::<ScrollState>()
+            .init_resource::<HasUnreadBelow>()
+            .insert_resource(ChatMessageStyle(crate::TextStyle::default()))
+            .insert_resource(ChatTransform(Transform::default()))
+            .insert_resource(ChatBounds(Rect::new(0.0, 0.0, 300.0, 100.0)))
+            .add_event::<ScrollBy>()
+            .add_event::<ScrollToTop>()
+            .add_event::<ScrollToBottom>()
+            .add_event::<UnreadBelowChanged>()
+            .add_system(layout_handler);
+        app
+    }
+
+    fn spawn_messages(app: &mut App, indices: std::ops::Range<u64>) {
+        indices.for_each(|index| {
+            app.world.spawn((
+                ChatMessage {
+                    index,
+                    ..ChatMessage::local("Hello", "16806024910000000")
+                },
+                Transform::default(),
+                Visibility::Inherited,
+            ));
+        });
+    }
+
+    fn spawn_laid_out_message(app: &mut App, index: u64, text_height: f32) -> Entity {
+        app.world
+            .spawn((
+                ChatMessage {
+                    index,
+                    ..ChatMessage::local("Hello", "16806024910000000")
+                },
+                TextLayoutInfo {
+                    size: Vec2::new(200.0, text_height),
+                    ..Default::default()
+                },
+                Transform::default(),
+                Visibility::Inherited,
+            ))
+            .id()
+    }
+
+    fn row_y(app: &App, entity: Entity) -> f32 {
+        app.world.get::<Transform>(entity).unwrap().translation.y
+    }
+
+    fn unread_changes(app: &App) -> Vec<bool> {
+        app.world
+            .resource::<bevy::ecs::event::Events<UnreadBelowChanged>>()
+            .iter_current_update_events()
+            .map(|change| change.unread)
+            .collect()
+    }
+
+    #[test]
+    fn flag_messages_arriving_below_scrolled_up_chat() {
+        let mut app = layout_app();
+        spawn_messages(&mut app, 0..20);
+        app.update();
+        app.world.send_event(ScrollBy(100.0));
+        app.update();
+        let top_row = app.world.resource::<ScrollState>().offset()
+            - app.world.resource::<ScrollState>().content_height;
+
+        spawn_messages(&mut app, 20..23);
+        app.update();
+
+        let scroll = app.world.resource::<ScrollState>();
+        assert_eq!(scroll.offset() - scroll.content_height, top_row);
+        assert!(!scroll.is_following());
+        assert!(app.world.resource::<HasUnreadBelow>().0);
+        assert_eq!(unread_changes(&app), vec![true]);
+    }
+
+    #[test]
+    fn clear_unread_flag_when_jumping_to_latest() {
+        let mut app = layout_app();
+        spawn_messages(&mut app, 0..20);
+        app.update();
+        app.world.send_event(ScrollBy(100.0));
+        app.update();
+        spawn_messages(&mut app, 20..23);
+        app.update();
+
+        app.world.send_event(ScrollToBottom);
+        app.update();
+
+        assert_eq!(app.world.resource::<ScrollState>().offset(), 0.0);
+        assert!(!app.world.resource::<HasUnreadBelow>().0);
+        assert_eq!(unread_changes(&app), vec![false]);
+    }
+
+    #[test]
+    fn not_flag_messages_while_following() {
+        let mut app = layout_app();
+        spawn_messages(&mut app, 0..20);
+        app.update();
+
+        spawn_messages(&mut app, 20..23);
+        app.update();
+
+        assert_eq!(app.world.resource::<ScrollState>().offset(), 0.0);
+        assert!(!app.world.resource::<HasUnreadBelow>().0);
+    }
+
+    #[test]
+    fn not_scroll_when_content_fits() {
+        let mut scroll = ScrollState::default();
+        scroll.set_content(50.0, 100.0);
+
+        scroll.scroll_to_top();
+
+        assert_eq!(scroll.offset(), 0.0);
+        assert!(scroll.is_following());
+    }
+
+    #[test]
+    fn clamp_offset_to_available_messages() {
+        assert_eq!(clamp_offset(-10.0, 200.0), 0.0);
+        assert_eq!(clamp_offset(120.0, 200.0), 120.0);
+        assert_eq!(clamp_offset(250.0, 200.0), 200.0);
+    }
+
+    #[test]
+    fn not_scroll_past_the_newest_without_scrollback() {
+        assert_eq!(clamp_offset(50.0, -20.0), 0.0);
+    }
+
+    #[test]
+    fn scroll_by_wheel_lines() {
+        assert_eq!(wheel_delta(MouseScrollUnit::Line, 2.0, 24.0), 48.0);
+        assert_eq!(wheel_delta(MouseScrollUnit::Line, -1.0, 24.0), -24.0);
+    }
+
+    #[test]
+    fn scroll_by_wheel_pixels() {
+        assert_eq!(wheel_delta(MouseScrollUnit::Pixel, 15.0, 24.0), 15.0);
+    }
+
+    #[test]
+    fn space_lines_relative_to_font_size() {
+        assert_eq!(line_height(20.0, 1.2), 24.0);
+        assert_eq!(line_height(20.0, 1.5), 30.0);
+    }
+
+    #[test]
+    fn stack_rows_with_line_spacing() {
+        let line_height = line_height(20.0, 1.5);
+
+        assert_eq!(row_offsets(&[line_height; 3], 0.0), vec![0.0, 30.0, 60.0]);
+    }
+
+    #[test]
+    fn limit_visible_height_to_max_lines() {
+        assert_eq!(visible_height(100.0, 24.0, None), 100.0);
+        assert_eq!(visible_height(100.0, 24.0, Some(3)), 72.0);
+        assert_eq!(visible_height(100.0, 24.0, Some(10)), 100.0);
+    }
+
+    #[test]
+    fn hide_rows_over_max_visible_lines() {
+        let mut app = layout_app_with(config(Some(3)));
+        spawn_messages(&mut app, 0..5);
+        app.update();
+
+        let mut rows = app
+            .world
+            .query::<(&ChatMessage, &Visibility)>()
+            .iter(&app.world)
+            .map(|(message, visibility)| (message.index, *visibility == Visibility::Inherited))
+            .collect::<Vec<_>>();
+        rows.sort();
+
+        assert_eq!(
+            rows,
+            vec![(0, false), (1, false), (2, true), (3, true), (4, true)]
+        );
+        assert_eq!(
+            app.world.query::<&ChatMessage>().iter(&app.world).count(),
+            5
+        );
+    }
+
+    #[test]
+    fn give_wrapped_rows_their_text_height() {
+        assert_eq!(row_height(0.0, 24.0), 24.0);
+        assert_eq!(row_height(20.0, 24.0), 24.0);
+        assert_eq!(row_height(72.0, 24.0), 72.0);
+    }
+
+    #[test]
+    fn stack_wrapped_messages_by_their_height() {
+        let mut app = layout_app();
+        let oldest = spawn_laid_out_message(&mut app, 0, 24.0);
+        let wrapped = spawn_laid_out_message(&mut app, 1, 72.0);
+        let newest = spawn_laid_out_message(&mut app, 2, 24.0);
+
+        app.update();
+
+        assert_eq!(row_y(&app, newest), 0.0);
+        assert_eq!(row_y(&app, wrapped), 24.0);
+        assert_eq!(row_y(&app, oldest), 96.0);
+        assert_eq!(app.world.resource::<ScrollState>().content_height, 120.0);
+        assert_eq!(app.world.resource::<ScrollState>().max_offset(), 20.0);
+    }
+
+    #[test]
+    fn not_overlap_multi_line_message_with_next_one() {
+        let mut app = layout_app();
+        let multi_line = spawn_laid_out_message(&mut app, 0, 48.0);
+        let next = spawn_laid_out_message(&mut app, 1, 24.0);
+
+        app.update();
+
+        assert!(row_y(&app, next) + 24.0 <= row_y(&app, multi_line));
+    }
+
+    #[test]
+    fn move_older_rows_up_when_reaction_row_is_added() {
+        let mut app = layout_app();
+        let older = spawn_laid_out_message(&mut app, 0, 24.0);
+        let reacted = spawn_laid_out_message(&mut app, 1, 24.0);
+        app.update();
+
+        app.world.get_mut::<TextLayoutInfo>(reacted).unwrap().size.y = 44.0;
+        app.update();
+
+        assert_eq!(row_y(&app, reacted), 0.0);
+        assert_eq!(row_y(&app, older), 44.0);
+    }
+
+    #[test]
+    fn anchor_own_messages_at_right_edge_of_bounds() {
+        let mut app = layout_app();
+        let others = spawn_laid_out_message(&mut app, 0, 24.0);
+        let own = spawn_laid_out_message(&mut app, 1, 24.0);
+        app.world.entity_mut(own).insert(Anchor::BottomRight);
+
+        app.update();
+
+        assert_eq!(
+            app.world.get::<Transform>(own).unwrap().translation,
+            Vec3::new(300.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            app.world.get::<Transform>(others).unwrap().translation,
+            Vec3::new(0.0, 24.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn hide_rows_not_fitting_the_viewport() {
+        assert!(row_visible(0.0, 24.0, 100.0));
+        assert!(row_visible(76.0, 24.0, 100.0));
+        assert!(!row_visible(80.0, 24.0, 100.0));
+        assert!(!row_visible(-1.0, 24.0, 100.0));
+    }
+
+    #[test]
+    fn stack_rows_from_the_newest() {
+        assert_eq!(row_offsets(&[24.0; 3], 0.0), vec![0.0, 24.0, 48.0]);
+        assert_eq!(row_offsets(&[24.0; 3], 24.0), vec![-24.0, 0.0, 24.0]);
+        assert_eq!(row_offsets(&[48.0, 24.0, 24.0], 0.0), vec![0.0, 48.0, 72.0]);
+    }
+
+    #[test]
+    fn position_messages_from_the_chat_anchor() {
+        let anchor = Vec3::new(100.0, -50.0, 5.0);
+
+        assert_eq!(
+            row_offsets(&[24.0; 3], 0.0)
+                .into_iter()
+                .map(|row_offset| message_position(anchor, MESSAGES_ORIGIN, row_offset))
+                .collect::<Vec<_>>(),
+            vec![
+                Vec3::new(130.0, 20.0, 5.0),
+                Vec3::new(130.0, 44.0, 5.0),
+                Vec3::new(130.0, 68.0, 5.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,357 @@
+use std::ops::Range;
+
+use bevy::{
+    prelude::{
+        Color, Component, EventWriter, GlobalTransform, Input, MouseButton, Query, Rect, Res, Vec2,
+        With,
+    },
+    render::view::ComputedVisibility,
+    sprite::Anchor,
+    text::{Text, TextLayoutInfo, TextSection, TextStyle},
+    ui::Node,
+    window::{PrimaryWindow, Window},
+};
+
+use super::{
+    events::LinkClicked,
+    messages::ChatMessage,
+    resources::{ChatBounds, ChatTransform},
+};
+
+const SCHEMES: [&str; 2] = ["https://", "http://"];
+
+/// Punctuation ending a sentence rather than the URL, like in `see https://pubnub.com.`
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', ')', ']', '}', '\'', '"'];
+
+/// The text sections of a message holding links, with their URLs.
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct MessageLinks {
+    /// The number of sections the message text is split into.
+    sections: usize,
+    urls: Vec<(usize, String)>,
+}
+
+/// Byte ranges of the `http://` and `https://` URLs in the text.
+///
+/// A URL starts at a word boundary and ends at the next whitespace,
+/// the trailing punctuation is left out.
+pub fn find_urls(text: &str) -> Vec<Range<usize>> {
+    let mut urls = Vec::new();
+    let mut searched = 0;
+
+    while let Some(found) = text[searched..].find("http") {
+        let start = searched + found;
+        let candidate = &text[start..];
+        let at_word_start = text[..start]
+            .chars()
+            .next_back()
+            .map_or(true, |character| !character.is_alphanumeric());
+
+        let Some(scheme) = SCHEMES
+            .iter()
+            .find(|scheme| candidate.starts_with(*scheme))
+            .filter(|_| at_word_start)
+        else {
+            searched = start + "http".len();
+            continue;
+        };
+
+        let word_end = candidate
+            .find(char::is_whitespace)
+            .unwrap_or(candidate.len());
+        let word = &candidate[..word_end];
+        let url = word.trim_end_matches(TRAILING_PUNCTUATION);
+        if url.len() > scheme.len() {
+            urls.push(start..start + url.len());
+        }
+
+        searched = start + word.len();
+    }
+
+    urls
+}
+
+/// Splits the text into sections, the URLs are colored with the link color.
+///
+/// Returns `None` if there are no URLs in the text.
+pub fn link_sections(
+    value: &str,
+    style: &TextStyle,
+    link_color: Color,
+) -> Option<(Vec<TextSection>, MessageLinks)> {
+    let urls = find_urls(value);
+    if urls.is_empty() {
+        return None;
+    }
+
+    let mut sections = Vec::new();
+    let mut link_urls = Vec::new();
+    let mut previous_end = 0;
+
+    urls.into_iter().for_each(|url| {
+        if url.start > previous_end {
+            sections.push(TextSection::new(
+                &value[previous_end..url.start],
+                style.clone(),
+            ));
+        }
+
+        link_urls.push((sections.len(), value[url.clone()].to_string()));
+        sections.push(TextSection::new(
+            &value[url.clone()],
+            TextStyle {
+                color: link_color,
+                ..style.clone()
+            },
+        ));
+        previous_end = url.end;
+    });
+
+    if previous_end < value.len() {
+        sections.push(TextSection::new(&value[previous_end..], style.clone()));
+    }
+
+    let links = MessageLinks {
+        sections: sections.len(),
+        urls: link_urls,
+    };
+    Some((sections, links))
+}
+
+/// Replaces the first section of the message text with its link sections.
+///
+/// The sections appended later, like the debug output, keep following the message.
+pub fn split_links(text: &mut Text, link_color: Color) -> Option<MessageLinks> {
+    let first = &text.sections[0];
+    let (sections, links) = link_sections(&first.value, &first.style, link_color)?;
+
+    text.sections.splice(0..1, sections);
+    Some(links)
+}
+
+/// Formats the message text again, like when its relative time changes.
+///
+/// The links are split again, the sections appended after the message are kept.
+pub fn replace_message_value(
+    text: &mut Text,
+    links: Option<&mut MessageLinks>,
+    value: String,
+    link_color: Color,
+) {
+    let Some(links) = links else {
+        text.sections[0].value = value;
+        return;
+    };
+
+    // The links are colored, any other section has the message style.
+    let style = (0..links.sections)
+        .find(|section| {
+            links
+                .urls
+                .iter()
+                .all(|(url_section, _)| url_section != section)
+        })
+        .map_or_else(
+            || text.sections[0].style.clone(),
+            |section| text.sections[section].style.clone(),
+        );
+
+    let (sections, new_links) = link_sections(&value, &style, link_color).unwrap_or_else(|| {
+        let links = MessageLinks {
+            sections: 1,
+            urls: Vec::new(),
+        };
+        (vec![TextSection::new(value, style)], links)
+    });
+
+    text.sections.splice(0..links.sections, sections);
+    *links = new_links;
+}
+
+/// The URL of the link under the point, in the coordinates of the glyphs.
+pub fn link_at<'a>(
+    links: &'a MessageLinks,
+    layout: &TextLayoutInfo,
+    point: Vec2,
+) -> Option<&'a str> {
+    links
+        .urls
+        .iter()
+        .find(|(section, _)| {
+            layout
+                .glyphs
+                .iter()
+                .filter(|glyph| glyph.section_index == *section)
+                .any(|glyph| Rect::from_center_size(glyph.position, glyph.size).contains(point))
+        })
+        .map(|(_, url)| url.as_str())
+}
+
+/// Sends [`LinkClicked`] when a link of a visible message is clicked.
+pub fn link_click_handler(
+    buttons: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    bounds: Res<ChatBounds>,
+    chat_transform: Res<ChatTransform>,
+    messages: Query<(
+        &ChatMessage,
+        &MessageLinks,
+        &TextLayoutInfo,
+        &GlobalTransform,
+        &ComputedVisibility,
+        Option<&Anchor>,
+        Option<&Node>,
+    )>,
+    mut clicked: EventWriter<LinkClicked>,
+) {
+    if !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let Some(cursor) = window
+        .cursor_position()
+        .filter(|cursor| bounds.contains(*cursor))
+    else {
+        return;
+    };
+
+    let link = messages.iter().find_map(
+        |(message, links, layout, transform, visibility, anchor, node)| {
+            if !visibility.is_visible() {
+                return None;
+            }
+
+            let point = match node {
+                // UI glyphs are positioned from the top left corner of the node, y pointing down.
+                Some(node) => {
+                    let top_left = transform.translation().truncate() - node.size() / 2.0;
+                    Vec2::new(cursor.x, window.height() - cursor.y) - top_left
+                }
+                // World space glyphs are positioned from the bottom left corner of the text block,
+                // the cursor is compared in the chat space like the bounds.
+                None => {
+                    let anchor = anchor.map_or(Vec2::ZERO, Anchor::as_vec);
+                    let offset = layout.size * -(anchor + 0.5);
+                    let position = transform.translation() - chat_transform.translation;
+                    cursor - position.truncate() - offset
+                }
+            };
+
+            link_at(links, layout, point).map(|url| LinkClicked {
+                url: url.to_string(),
+                message_tt: message.timetoken.clone(),
+            })
+        },
+    );
+
+    if let Some(link) = link {
+        clicked.send(link);
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    use test_case::test_case;
+
+    #[test_case("see https://pubnub.com" => vec![4..22] ; "at the end")]
+    #[test_case("http://a.io and https://b.io/x?y=1" => vec![0..11, 16..34] ; "multiple")]
+    #[test_case("docs at https://pubnub.com/docs." => vec![8..31] ; "before a full stop")]
+    #[test_case("(https://pubnub.com)" => vec![1..19] ; "in parentheses")]
+    #[test_case("żółw http://ż.pl" => vec![8..20] ; "after multibyte characters")]
+    fn find_url_ranges(text: &str) -> Vec<Range<usize>> {
+        find_urls(text)
+    }
+
+    #[test_case("no links here" ; "plain text")]
+    #[test_case("https:// alone" ; "scheme only")]
+    #[test_case("xhttps://pubnub.com" ; "inside a word")]
+    #[test_case("the http protocol" ; "word http")]
+    fn find_no_urls(text: &str) {
+        assert!(find_urls(text).is_empty());
+    }
+
+    #[test]
+    fn color_links_in_separate_sections() {
+        let style = TextStyle {
+            color: Color::WHITE,
+            ..Default::default()
+        };
+
+        let (sections, links) =
+            link_sections("jane: see https://pubnub.com now", &style, Color::BLUE).unwrap();
+
+        assert_eq!(
+            sections
+                .iter()
+                .map(|section| (section.value.as_str(), section.style.color))
+                .collect::<Vec<_>>(),
+            vec![
+                ("jane: see ", Color::WHITE),
+                ("https://pubnub.com", Color::BLUE),
+                (" now", Color::WHITE),
+            ]
+        );
+        assert_eq!(
+            links,
+            MessageLinks {
+                sections: 3,
+                urls: vec![(1, "https://pubnub.com".to_string())],
+            }
+        );
+    }
+
+    #[test]
+    fn split_links_again_after_reformatting() {
+        let style = TextStyle {
+            color: Color::WHITE,
+            ..Default::default()
+        };
+        let mut text = Text::from_sections([
+            TextSection::new("just now jane: https://pubnub.com", style.clone()),
+            TextSection::new("\n👍 1", style),
+        ]);
+        let mut links = split_links(&mut text, Color::BLUE).unwrap();
+
+        replace_message_value(
+            &mut text,
+            Some(&mut links),
+            "1m ago jane: https://pubnub.com".into(),
+            Color::BLUE,
+        );
+
+        assert_eq!(
+            text.sections
+                .iter()
+                .map(|section| (section.value.as_str(), section.style.color))
+                .collect::<Vec<_>>(),
+            vec![
+                ("1m ago jane: ", Color::WHITE),
+                ("https://pubnub.com", Color::BLUE),
+                ("\n👍 1", Color::WHITE),
+            ]
+        );
+    }
+
+    #[test]
+    fn keep_sections_appended_after_message() {
+        let mut text = Text::from_sections([
+            TextSection::new("https://pubnub.com", TextStyle::default()),
+            TextSection::new("\n[debug] {}", TextStyle::default()),
+        ]);
+
+        split_links(&mut text, Color::BLUE);
+
+        assert_eq!(
+            text.sections
+                .iter()
+                .map(|section| section.value.as_str())
+                .collect::<Vec<_>>(),
+            vec!["https://pubnub.com", "\n[debug] {}"]
+        );
+    }
+}
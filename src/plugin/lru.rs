@@ -0,0 +1,150 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+use crate::builder::DEFAULT_TRACKING_CAPACITY;
+
+/// Set keeping at most `capacity` entries.
+///
+/// Inserting into a full set evicts the least recently inserted or touched entry.
+/// An evicted entry is forgotten, so it is treated as new when it shows up again.
+#[derive(Debug, Clone)]
+pub struct LruSet<T> {
+    capacity: usize,
+    stamp: u64,
+    entries: HashMap<T, u64>,
+    order: BTreeMap<u64, T>,
+}
+
+impl<T> Default for LruSet<T>
+where
+    T: Eq + Hash + Clone,
+{
+    fn default() -> Self {
+        Self::new(DEFAULT_TRACKING_CAPACITY)
+    }
+}
+
+impl<T> LruSet<T>
+where
+    T: Eq + Hash + Clone,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            stamp: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    /// Inserts the value as the most recent entry.
+    ///
+    /// Returns `true` if the value was not present.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.stamp += 1;
+
+        let new = match self.entries.insert(value.clone(), self.stamp) {
+            Some(previous) => {
+                self.order.remove(&previous);
+                false
+            }
+            None => true,
+        };
+        self.order.insert(self.stamp, value);
+
+        while self.entries.len() > self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        new
+    }
+
+    pub fn remove(&mut self, value: &T) -> bool {
+        self.entries
+            .remove(value)
+            .map(|stamp| self.order.remove(&stamp))
+            .is_some()
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.entries.contains_key(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Iterates from the oldest to the most recent entry.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.order.values()
+    }
+}
+
+impl<T> Extend<T> for LruSet<T>
+where
+    T: Eq + Hash + Clone,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|value| {
+            self.insert(value);
+        });
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    #[test]
+    fn evict_oldest_entry() {
+        let mut set = LruSet::new(2);
+
+        set.insert("john");
+        set.insert("jane");
+        set.insert("bob");
+
+        assert!(!set.contains(&"john"));
+        assert!(set.contains(&"jane"));
+        assert!(set.contains(&"bob"));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn refresh_reinserted_entry() {
+        let mut set = LruSet::new(2);
+
+        set.insert("john");
+        set.insert("jane");
+        assert!(!set.insert("john"));
+        set.insert("bob");
+
+        assert!(set.contains(&"john"));
+        assert!(!set.contains(&"jane"));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![&"john", &"bob"]);
+    }
+
+    #[test]
+    fn treat_evicted_entry_as_new() {
+        let mut set = LruSet::new(1);
+
+        set.insert("john");
+        set.insert("jane");
+
+        assert!(set.insert("john"));
+    }
+
+    #[test]
+    fn remove_entry() {
+        let mut set = LruSet::new(2);
+
+        set.insert("john");
+
+        assert!(set.remove(&"john"));
+        assert!(!set.remove(&"john"));
+        assert_eq!(set.len(), 0);
+    }
+}
@@ -1,81 +1,353 @@
+use std::collections::HashMap;
+
 use bevy::{
-    prelude::{Commands, Component, Res},
-    tasks::AsyncComputeTaskPool,
-};
-use pubnub::{
-    core::{blocking::Transport, TransportMethod, TransportRequest},
-    transport::reqwest::blocking::TransportReqwest,
+    prelude::{
+        Commands, Component, Entity, EventReader, EventWriter, Query, Res, ResMut, Resource, With,
+    },
+    time::Time,
 };
-use serde::{Deserialize, Serialize};
+use pubnub::core::{Transport, TransportMethod, TransportRequest};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
 use crate::{error::Result, BevyPNError};
 
-use super::{resources::PubNubSubscribeResource, tasks::SubscribeTask};
+use super::{
+    connection::SubscribeActivity,
+    crypto::decrypt,
+    events::{ClearChat, SetFilterExpression, SwitchChannel},
+    lru::LruSet,
+    presence::PRESENCE_CHANNEL_SUFFIX,
+    resources::{ChannelResource, ChatConfig, PubNubSubscribeResource, RawTransportResource},
+    tasks::{spawn_request, SubscribeTask},
+    transport::ChatTransport,
+};
+
+#[derive(Component, Debug, Clone)]
+pub struct ChatMessage {
+    pub index: u64,
+    pub user_id: String,
+    pub payload: String,
+    pub channel: String,
+    pub group: Option<String>,
+    pub timetoken: String,
+    pub raw: Option<String>,
+}
 
-#[derive(Component)]
-pub struct ChatMessage;
+impl ChatMessage {
+    pub fn local(payload: impl Into<String>, timetoken: impl Into<String>) -> Self {
+        Self {
+            index: 0,
+            user_id: String::new(),
+            payload: payload.into(),
+            channel: String::new(),
+            group: None,
+            timetoken: timetoken.into(),
+            raw: None,
+        }
+    }
+}
 
-pub fn message_handler(mut commands: Commands, subscription_info: Res<PubNubSubscribeResource>) {
-    let thread_pool = AsyncComputeTaskPool::get();
+impl From<&Message> for ChatMessage {
+    fn from(message: &Message) -> Self {
+        Self {
+            index: 0,
+            user_id: message.sender(),
+            payload: message.text(),
+            channel: message.channel.clone(),
+            group: message
+                .subscription
+                .clone()
+                .filter(|subscription| *subscription != message.channel),
+            timetoken: message.timetoken.clone(),
+            raw: None,
+        }
+    }
+}
 
-    let subscribe_key = subscription_info.subscribe_key.clone();
-    let channel = subscription_info.channel.clone();
-    let tt = subscription_info.tt.clone();
-    let tr = subscription_info.tr.clone();
-    let user_id = subscription_info.user_id.clone();
+/// Messages already received, so the overlapping subscribe responses render them once.
+///
+/// Messages are identified by their channel, publisher and publish timetoken.
+/// Only the latest `tracking_capacity` messages are remembered.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct SeenMessages(LruSet<(String, String, String)>);
 
-    let task = thread_pool.spawn(async move { subscribe(subscribe_key, channel, tt, tr, user_id) });
+impl SeenMessages {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self(LruSet::new(capacity))
+    }
 
-    commands.spawn(SubscribeTask(task));
+    /// Returns `true` if the message is received for the first time.
+    pub fn first_seen(&mut self, message: &Message) -> bool {
+        self.0.insert((
+            message.channel.clone(),
+            message.publisher.clone(),
+            message.timetoken.clone(),
+        ))
+    }
 }
 
-pub fn subscribe(
-    subscribe_key: String,
-    channel: String,
-    tt: String,
-    tr: String,
-    user_id: String,
-) -> Result<SubscriptionResult> {
-    let transport = TransportReqwest::new();
-
-    let request = TransportRequest {
-        path: format!("v2/subscribe/{}/{}/0", subscribe_key, channel),
-        query_parameters: [
-            ("tt".into(), tt),
-            ("tr".into(), tr),
-            ("uuid".into(), user_id),
-        ]
-        .into(),
+/// Requests restarting the subscribe loop with the current [`PubNubSubscribeResource`].
+///
+/// Any in-flight subscribe request is cancelled.
+#[derive(Debug, Clone, Copy)]
+pub struct Resubscribe;
+
+pub fn message_handler(
+    mut commands: Commands,
+    subscription_info: Res<PubNubSubscribeResource>,
+    transport: Res<RawTransportResource>,
+) {
+    spawn_subscribe(&mut commands, &subscription_info, &transport);
+}
+
+pub fn resubscribe_handler(
+    mut commands: Commands,
+    mut requests: EventReader<Resubscribe>,
+    subscription_info: Res<PubNubSubscribeResource>,
+    transport: Res<RawTransportResource>,
+    subscribe_tasks: Query<Entity, With<SubscribeTask>>,
+    time: Res<Time>,
+    mut activity: ResMut<SubscribeActivity>,
+) {
+    if requests.iter().count() == 0 {
+        return;
+    }
+
+    subscribe_tasks
+        .iter()
+        .for_each(|entity| commands.entity(entity).despawn());
+
+    spawn_subscribe(&mut commands, &subscription_info, &transport);
+    activity.request_sent(time.elapsed());
+}
+
+pub fn filter_expression_handler(
+    mut requests: EventReader<SetFilterExpression>,
+    mut subscription_info: ResMut<PubNubSubscribeResource>,
+    mut resubscribe: EventWriter<Resubscribe>,
+) {
+    let latest = requests
+        .iter()
+        .filter(|SetFilterExpression(filter)| match filter {
+            Some(expression) if expression.trim().is_empty() => {
+                log::warn!("Ignoring empty filter expression");
+                false
+            }
+            _ => true,
+        })
+        .last();
+
+    if let Some(SetFilterExpression(filter)) = latest {
+        subscription_info.filter = filter.clone();
+        resubscribe.send(Resubscribe);
+    }
+}
+
+pub fn switch_channel_handler(
+    mut requests: EventReader<SwitchChannel>,
+    mut channel: ResMut<ChannelResource>,
+    mut subscription_info: ResMut<PubNubSubscribeResource>,
+    config: Res<ChatConfig>,
+    mut clears: EventWriter<ClearChat>,
+    mut resubscribe: EventWriter<Resubscribe>,
+) {
+    let latest = requests
+        .iter()
+        .filter(|SwitchChannel(name)| {
+            let valid = !name.trim().is_empty();
+            if !valid {
+                log::warn!("Ignoring switch to an empty channel name");
+            }
+            valid
+        })
+        .last();
+
+    let Some(SwitchChannel(name)) = latest else {
+        return;
+    };
+
+    channel.0 = Some(name.clone());
+    subscription_info.channels = std::iter::once(name.clone())
+        .chain(
+            config
+                .presence
+                .then(|| format!("{}{}", name, PRESENCE_CHANNEL_SUFFIX)),
+        )
+        .collect();
+    subscription_info.tt = "0".into();
+    subscription_info.tr = "0".into();
+
+    clears.send(ClearChat);
+    resubscribe.send(Resubscribe);
+}
+
+pub fn spawn_subscribe(
+    commands: &mut Commands,
+    subscription_info: &PubNubSubscribeResource,
+    transport: &ChatTransport,
+) {
+    let channels = subscription_info.channels.clone();
+    let subscription_info = subscription_info.clone();
+    let transport = transport.clone().long_poll();
+    let task = spawn_request(async move { subscribe(&transport, &subscription_info).await });
+
+    commands.spawn(SubscribeTask { channels, task });
+}
+
+/// The channels joined for a request path.
+///
+/// PubNub expects `,` in place of the channels when only groups are subscribed.
+pub fn path_channels(channels: &[String]) -> String {
+    match channels.join(",") {
+        channels if channels.is_empty() => ",".into(),
+        channels => channels,
+    }
+}
+
+/// Adds the `channel-group` query parameter if any groups are subscribed.
+pub fn insert_channel_groups(query_parameters: &mut HashMap<String, String>, groups: &[String]) {
+    if !groups.is_empty() {
+        query_parameters.insert("channel-group".into(), groups.join(","));
+    }
+}
+
+pub fn subscribe_request(subscription_info: &PubNubSubscribeResource) -> TransportRequest {
+    let mut query_parameters: HashMap<String, String> = [
+        ("tt".into(), subscription_info.tt.clone()),
+        ("tr".into(), subscription_info.tr.clone()),
+        ("uuid".into(), subscription_info.user_id.clone()),
+    ]
+    .into();
+
+    if let Some(filter) = &subscription_info.filter {
+        query_parameters.insert("filter-expr".into(), filter.clone());
+    }
+
+    // Subscribing with the state restores it after a presence timeout.
+    if let Some(state) = &subscription_info.state {
+        query_parameters.insert("state".into(), state.clone());
+    }
+
+    insert_channel_groups(&mut query_parameters, &subscription_info.channel_groups);
+
+    TransportRequest {
+        path: format!(
+            "v2/subscribe/{}/{}/0",
+            subscription_info.subscribe_key,
+            path_channels(&subscription_info.channels)
+        ),
+        query_parameters,
         method: TransportMethod::Get,
         headers: [].into(),
         body: None,
-    };
+    }
+}
 
-    let response = transport.send(request);
+pub async fn subscribe<T: Transport>(
+    transport: &T,
+    subscription_info: &PubNubSubscribeResource,
+) -> Result<SubscriptionResult> {
+    let response = transport.send(subscribe_request(subscription_info)).await?;
+    let failed = response.status >= 400;
 
-    response.map_err(Into::into).and_then(|response| {
-        response
-            .body
-            .ok_or_else(|| BevyPNError::EmptyBody {
-                on: "Subscribe".into(),
-            })
-            .and_then(|body| {
-                serde_json::from_slice::<SubscriptionResult>(&body).map_err(Into::into)
-            })
+    let body = response.body.ok_or_else(|| match failed {
+        true => BevyPNError::Server {
+            status: response.status,
+            message: "empty response".into(),
+        },
+        false => BevyPNError::EmptyBody {
+            on: "Subscribe".into(),
+        },
+    })?;
+
+    // Error envelopes don't have the subscribe fields, so they never parse as a result.
+    if let Ok(envelope) = serde_json::from_slice::<ErrorEnvelope>(&body) {
+        if envelope.error || failed {
+            return Err(BevyPNError::Server {
+                status: envelope.status,
+                message: envelope.message,
+            });
+        }
+    }
+
+    serde_json::from_slice::<SubscriptionResult>(&body).map_err(|error| match failed {
+        true => BevyPNError::Server {
+            status: response.status,
+            message: String::from_utf8_lossy(&body).into(),
+        },
+        false => error.into(),
     })
 }
 
+/// Body returned by PubNub when a request is rejected, like `{ "error": true, "status": 403, ... }`.
 #[derive(Debug, Deserialize)]
+struct ErrorEnvelope {
+    #[serde(default)]
+    error: bool,
+
+    status: u16,
+
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(from = "SubscriptionResponse")]
 pub struct SubscriptionResult {
-    #[serde(rename = "t")]
     pub message_info: SubscriptionInfo,
 
-    #[serde(rename = "m")]
     pub messages: Vec<Message>,
+
+    /// Why the skipped messages of the batch couldn't be decoded.
+    pub malformed: Vec<BevyPNError>,
 }
 
-#[derive(Debug, Deserialize)]
+/// The subscribe response with the messages not decoded yet.
+#[derive(Deserialize)]
+struct SubscriptionResponse {
+    #[serde(rename = "t")]
+    message_info: SubscriptionInfo,
+
+    #[serde(rename = "m")]
+    messages: Vec<Value>,
+}
+
+// A single malformed message must not take down the whole batch.
+impl From<SubscriptionResponse> for SubscriptionResult {
+    fn from(response: SubscriptionResponse) -> Self {
+        let mut malformed = Vec::new();
+        let messages = response
+            .messages
+            .into_iter()
+            .filter_map(|message| match Message::deserialize(&message) {
+                Ok(decoded) => Some(Message {
+                    raw: message,
+                    ..decoded
+                }),
+                Err(err) => {
+                    log::warn!("Skipping malformed message {}: {}", message, err);
+                    malformed.push(err.into());
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            message_info: response.message_info,
+            messages,
+            malformed,
+        }
+    }
+}
+
+fn publish_timetoken<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    SubscriptionInfo::deserialize(deserializer).map(|publish_info| publish_info.tt)
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct SubscriptionInfo {
     #[serde(rename = "t")]
     pub tt: String,
@@ -84,14 +356,503 @@ pub struct SubscriptionInfo {
     pub tr: i32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Message {
     #[serde(rename = "c")]
     pub channel: String,
 
     #[serde(rename = "d")]
-    pub payload: String,
+    pub payload: Value,
 
+    /// The uuid of the client that published the message.
     #[serde(rename = "i")]
-    pub user_id: String,
+    pub publisher: String,
+
+    /// The publish timetoken of the message.
+    #[serde(rename = "p", deserialize_with = "publish_timetoken")]
+    pub timetoken: String,
+
+    /// The channel group or wildcard the message was received through,
+    /// `None` for channels subscribed directly.
+    #[serde(rename = "b", default)]
+    pub subscription: Option<String>,
+
+    /// Type of the message, `None` for regular messages.
+    #[serde(rename = "e", default)]
+    pub message_type: Option<u8>,
+
+    /// The whole envelope as received.
+    #[serde(skip)]
+    pub raw: Value,
+}
+
+/// Payload of the messages published with `structured_messages` enabled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredPayload {
+    pub text: String,
+
+    #[serde(default)]
+    pub meta: Value,
+}
+
+/// Message type of PubNub signals.
+const SIGNAL_MESSAGE_TYPE: u8 = 1;
+
+impl Message {
+    /// Returns `true` if the message was sent as a PubNub signal.
+    pub fn is_signal(&self) -> bool {
+        self.message_type == Some(SIGNAL_MESSAGE_TYPE)
+    }
+
+    /// Returns the message with the payload decrypted with the cipher key.
+    ///
+    /// The decrypted text is parsed as JSON, falling back to a plain string payload.
+    pub fn decrypted(&self, cipher_key: &str) -> Result<Message> {
+        let encrypted = self.payload.as_str().ok_or_else(|| BevyPNError::Decrypt {
+            message: "encrypted payload is not a string".into(),
+        })?;
+        let text = decrypt(cipher_key, encrypted)?;
+
+        Ok(Message {
+            payload: serde_json::from_str(&text).unwrap_or(Value::String(text)),
+            ..self.clone()
+        })
+    }
+
+    pub fn text(&self) -> String {
+        match &self.payload {
+            Value::String(text) => text.clone(),
+            Value::Object(object) => object
+                .get("text")
+                .and_then(Value::as_str)
+                .map(Into::into)
+                .unwrap_or_else(|| self.payload.to_string()),
+            payload => payload.to_string(),
+        }
+    }
+
+    /// The payload as a structured message, a bare string becomes its text.
+    pub fn structured(&self) -> StructuredPayload {
+        StructuredPayload::deserialize(&self.payload).unwrap_or_else(|_| StructuredPayload {
+            text: self.text(),
+            meta: Value::Null,
+        })
+    }
+
+    /// The sender embedded in the payload, falling back to the publisher uuid.
+    pub fn sender(&self) -> String {
+        self.payload
+            .get("sender")
+            .and_then(Value::as_str)
+            .map(Into::into)
+            .unwrap_or_else(|| self.publisher.clone())
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use bevy::{
+        ecs::event::Events,
+        prelude::App,
+        tasks::{AsyncComputeTaskPool, TaskPool},
+    };
+    use futures_lite::future;
+    use pubnub::core::{PubNubError, TransportResponse};
+    use test_case::test_case;
+
+    use crate::{ChatPlugin, Keyset};
+
+    use super::*;
+
+    fn subscription(channels: &[&str]) -> PubNubSubscribeResource {
+        PubNubSubscribeResource {
+            tt: "16806024912893745".into(),
+            tr: "1".into(),
+            channels: channels.iter().map(|channel| channel.to_string()).collect(),
+            ..PubNubSubscribeResource::for_tests()
+        }
+    }
+
+    #[test_case(&["lobby"] => "v2/subscribe/sub-c-test/lobby/0" ; "one channel")]
+    #[test_case(&["lobby", "team-red"] => "v2/subscribe/sub-c-test/lobby,team-red/0" ; "two channels")]
+    #[test_case(&["lobby", "team-red", "team-blue"] => "v2/subscribe/sub-c-test/lobby,team-red,team-blue/0" ; "three channels")]
+    fn join_channels_in_subscribe_path(channels: &[&str]) -> String {
+        subscribe_request(&subscription(channels)).path
+    }
+
+    struct MockTransport(u16, Option<&'static str>);
+
+    #[async_trait]
+    impl Transport for MockTransport {
+        async fn send(
+            &self,
+            _request: TransportRequest,
+        ) -> std::result::Result<TransportResponse, PubNubError> {
+            Ok(TransportResponse {
+                status: self.0,
+                headers: [].into(),
+                body: self.1.map(|body| body.as_bytes().to_vec()),
+            })
+        }
+    }
+
+    #[test]
+    fn resolve_subscribe_with_transport_response() {
+        let transport = MockTransport(200, Some(r#"{"t":{"t":"16806024912893746","r":1},"m":[]}"#));
+
+        let result = future::block_on(subscribe(&transport, &subscription(&["lobby"]))).unwrap();
+
+        assert_eq!(result.message_info.tt, "16806024912893746");
+        assert!(result.messages.is_empty());
+    }
+
+    #[test]
+    fn fail_subscribe_without_body() {
+        let transport = MockTransport(200, None);
+
+        assert!(matches!(
+            future::block_on(subscribe(&transport, &subscription(&["lobby"]))),
+            Err(BevyPNError::EmptyBody { .. })
+        ));
+    }
+
+    #[test]
+    fn report_access_denied_envelope() {
+        let transport = MockTransport(
+            403,
+            Some(
+                r#"{
+                    "message": "Forbidden",
+                    "payload": { "channels": ["lobby"] },
+                    "error": true,
+                    "service": "Access Manager",
+                    "status": 403
+                }"#,
+            ),
+        );
+
+        match future::block_on(subscribe(&transport, &subscription(&["lobby"]))) {
+            Err(BevyPNError::Server { status, message }) => {
+                assert_eq!(status, 403);
+                assert_eq!(message, "Forbidden");
+            }
+            other => panic!("expected a server error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn report_error_envelope_with_success_status() {
+        let transport = MockTransport(
+            200,
+            Some(r#"{ "error": true, "message": "Invalid Subscribe Key", "status": 400 }"#),
+        );
+
+        assert!(matches!(
+            future::block_on(subscribe(&transport, &subscription(&["lobby"]))),
+            Err(BevyPNError::Server { status: 400, .. })
+        ));
+    }
+
+    #[test]
+    fn report_failed_status_without_envelope() {
+        let transport = MockTransport(502, Some("Bad Gateway"));
+
+        assert!(matches!(
+            future::block_on(subscribe(&transport, &subscription(&["lobby"]))),
+            Err(BevyPNError::Server { status: 502, .. })
+        ));
+    }
+
+    #[test]
+    fn send_channel_groups() {
+        let subscription = PubNubSubscribeResource {
+            channel_groups: vec!["friends".into(), "guild".into()],
+            ..subscription(&["lobby"])
+        };
+
+        let request = subscribe_request(&subscription);
+
+        assert_eq!(request.query_parameters["channel-group"], "friends,guild");
+        assert_eq!(request.path, "v2/subscribe/sub-c-test/lobby/0");
+    }
+
+    #[test]
+    fn subscribe_to_channel_groups_only() {
+        let subscription = PubNubSubscribeResource {
+            channel_groups: vec!["friends".into()],
+            ..subscription(&[])
+        };
+
+        assert_eq!(
+            subscribe_request(&subscription).path,
+            "v2/subscribe/sub-c-test/,/0"
+        );
+    }
+
+    #[test]
+    fn not_send_channel_groups_by_default() {
+        assert!(!subscribe_request(&subscription(&["lobby"]))
+            .query_parameters
+            .contains_key("channel-group"));
+    }
+
+    #[test]
+    fn send_presence_state() {
+        let subscription = PubNubSubscribeResource {
+            state: Some(r#"{"status":"in-match"}"#.into()),
+            ..subscription(&["lobby"])
+        };
+
+        assert_eq!(
+            subscribe_request(&subscription).query_parameters["state"],
+            r#"{"status":"in-match"}"#
+        );
+    }
+
+    #[test]
+    fn send_filter_expression() {
+        let subscription = PubNubSubscribeResource {
+            filter: Some("context == 'lobby'".into()),
+            ..subscription(&["lobby"])
+        };
+
+        assert_eq!(
+            subscribe_request(&subscription).query_parameters["filter-expr"],
+            "context == 'lobby'"
+        );
+    }
+
+    fn filter_app() -> App {
+        let mut app = App::new();
+        app.insert_resource(subscription(&["lobby"]))
+            .add_event::<SetFilterExpression>()
+            .add_event::<Resubscribe>()
+            .add_system(filter_expression_handler);
+        app
+    }
+
+    #[test]
+    fn resubscribe_when_filter_changes() {
+        let mut app = filter_app();
+
+        app.world
+            .send_event(SetFilterExpression(Some("context == 'in-match'".into())));
+        app.update();
+
+        assert_eq!(
+            app.world.resource::<PubNubSubscribeResource>().filter,
+            Some("context == 'in-match'".into())
+        );
+        assert_eq!(app.world.resource::<Events<Resubscribe>>().len(), 1);
+    }
+
+    #[test]
+    fn ignore_empty_filter() {
+        let mut app = filter_app();
+
+        app.world.send_event(SetFilterExpression(Some("  ".into())));
+        app.update();
+
+        assert_eq!(app.world.resource::<PubNubSubscribeResource>().filter, None);
+        assert!(app.world.resource::<Events<Resubscribe>>().is_empty());
+    }
+
+    fn switch_app(presence: bool) -> App {
+        let config = ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .channel("lobby")
+            .presence(presence)
+            .build()
+            .unwrap()
+            .config;
+
+        let mut app = App::new();
+        app.insert_resource(subscription(&["lobby"]))
+            .insert_resource(ChannelResource(Some("lobby".into())))
+            .insert_resource(ChatConfig(config))
+            .add_event::<SwitchChannel>()
+            .add_event::<ClearChat>()
+            .add_event::<Resubscribe>()
+            .add_system(switch_channel_handler);
+        app
+    }
+
+    #[test]
+    fn switch_to_another_channel() {
+        let mut app = switch_app(false);
+
+        app.world.send_event(SwitchChannel("support".into()));
+        app.update();
+
+        assert_eq!(
+            app.world.resource::<ChannelResource>().name(),
+            Some("support")
+        );
+        let subscription_info = app.world.resource::<PubNubSubscribeResource>();
+        assert_eq!(subscription_info.channels, vec!["support"]);
+        assert_eq!(subscription_info.tt, "0");
+        assert_eq!(subscription_info.tr, "0");
+        assert_eq!(app.world.resource::<Events<ClearChat>>().len(), 1);
+        assert_eq!(app.world.resource::<Events<Resubscribe>>().len(), 1);
+    }
+
+    #[test]
+    fn follow_presence_of_switched_channel() {
+        let mut app = switch_app(true);
+
+        app.world.send_event(SwitchChannel("support".into()));
+        app.update();
+
+        assert_eq!(
+            app.world.resource::<PubNubSubscribeResource>().channels,
+            vec!["support", "support-pnpres"]
+        );
+    }
+
+    #[test]
+    fn ignore_switch_to_empty_channel() {
+        let mut app = switch_app(false);
+
+        app.world.send_event(SwitchChannel(" ".into()));
+        app.update();
+
+        assert_eq!(
+            app.world.resource::<ChannelResource>().name(),
+            Some("lobby")
+        );
+        assert_eq!(
+            app.world.resource::<PubNubSubscribeResource>().tt,
+            "16806024912893745"
+        );
+        assert!(app.world.resource::<Events<Resubscribe>>().is_empty());
+    }
+
+    #[test]
+    fn skip_malformed_messages_in_batch() {
+        let body = r#"{
+            "t": { "t": "16806024912893745", "r": 1 },
+            "m": [
+                { "c": "lobby", "d": "Hello", "i": "john", "p": { "t": "16806024912893744", "r": 1 } },
+                { "c": 5, "d": "Broken", "i": ["john"] },
+                { "c": "lobby", "d": { "text": "World" }, "i": "jane", "p": { "t": "16806024912893745", "r": 1 } }
+            ]
+        }"#;
+
+        let result = serde_json::from_str::<SubscriptionResult>(body).unwrap();
+
+        assert_eq!(result.message_info.tt, "16806024912893745");
+        assert_eq!(result.messages[0].raw["i"], "john");
+        assert!(matches!(
+            result.malformed.as_slice(),
+            [BevyPNError::Deserialize { .. }]
+        ));
+        assert_eq!(
+            result
+                .messages
+                .iter()
+                .map(Message::text)
+                .collect::<Vec<_>>(),
+            vec!["Hello", "World"]
+        );
+    }
+
+    #[test]
+    fn distinguish_signals_from_messages() {
+        let body = r#"{
+            "t": { "t": "16806024912893745", "r": 1 },
+            "m": [
+                { "c": "lobby", "d": "Hello", "i": "john", "p": { "t": "16806024912893744", "r": 1 } },
+                { "c": "lobby", "d": "typing", "e": 1, "i": "jane", "p": { "t": "16806024912893745", "r": 1 } }
+            ]
+        }"#;
+
+        let result = serde_json::from_str::<SubscriptionResult>(body).unwrap();
+
+        assert_eq!(
+            result
+                .messages
+                .iter()
+                .map(Message::is_signal)
+                .collect::<Vec<_>>(),
+            vec![false, true]
+        );
+    }
+
+    #[test]
+    fn parse_publisher_and_timetoken_of_realistic_envelope() {
+        let body = r#"{
+            "t": { "t": "16806024912893745", "r": 12 },
+            "m": [
+                {
+                    "a": "4",
+                    "f": 0,
+                    "i": "john",
+                    "p": { "t": "16806024912893744", "r": 12 },
+                    "k": "sub-c-test",
+                    "c": "lobby",
+                    "d": { "text": "Hello", "sender": "John Doe" },
+                    "b": "lobby"
+                }
+            ]
+        }"#;
+
+        let result = serde_json::from_str::<SubscriptionResult>(body).unwrap();
+        let message = &result.messages[0];
+
+        assert_eq!(message.publisher, "john");
+        assert_eq!(message.timetoken, "16806024912893744");
+        assert_eq!(message.sender(), "John Doe");
+        assert_eq!(message.text(), "Hello");
+    }
+
+    /// How long the test waits for the fake proxy to see the request, instead of hanging.
+    const PROXY_TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn subscribe_with_shared_transport() {
+        let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .proxy(format!("http://{}", proxy.local_addr().unwrap()))
+            .build()
+            .unwrap()
+            .config;
+
+        let (connect, connected) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = proxy.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+            let _ = connect.send(line);
+        });
+
+        AsyncComputeTaskPool::init(TaskPool::default);
+
+        let mut app = App::new();
+        app.insert_resource(subscription(&["lobby"]))
+            .insert_resource(RawTransportResource(ChatTransport::new(&config).unwrap()))
+            .add_startup_system(message_handler);
+        app.update();
+
+        // The fake proxy only sees the request if the shared transport was used.
+        assert!(connected
+            .recv_timeout(PROXY_TIMEOUT)
+            .unwrap()
+            .starts_with("CONNECT ps.pndsn.com:443"));
+    }
 }
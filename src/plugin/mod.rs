@@ -1,31 +1,117 @@
 //! This module describes how the [`ChatPlugin`] is plugged into the Bevy engine.
 
-use crate::{builder::ChatPluginConfig, BevyPNError};
+use crate::{builder::ChatPluginConfig, BevyPNError, RenderMode};
 use bevy::{
-    prelude::{AssetServer, Commands, Plugin, Res, Transform},
-    text::{Text2dBundle, TextStyle},
-};
-use keyboard::keyboard_handler;
-use pubnub::{
-    transport::middleware::PubNubMiddleware, transport::reqwest::blocking::TransportReqwest,
-    Keyset, PubNubClient, PubNubClientBuilder,
+    prelude::{
+        Commands, CoreSet, IntoSystemConfig, Plugin, Rect, Res, StartupSet, TextBundle, Transform,
+        Vec2,
+    },
+    text::{Text, Text2dBundle, TextAlignment, TextStyle},
+    ui::{PositionType, Style, UiRect, Val},
 };
+use keyboard::{keyboard_handler, ModifierState};
+use pubnub::{transport::middleware::PubNubMiddleware, Keyset, PubNubClient, PubNubClientBuilder};
+use serde_json::Value;
 
 use self::{
-    messages::message_handler,
+    background::{background_handler, spawn_background},
+    camera::render_layer_handler,
+    chat_log::chat_log_handler,
+    clipboard::ChatClipboard,
+    connection::{
+        backoff_handler, watchdog_handler, ChatConnected, ConnectionState, PollPacing,
+        SubscribeActivity, SubscribeBackoff,
+    },
+    events::{
+        ChatError, ChatFocusChanged, ClearChat, ConnectionChanged, HighlightMessage, LinkClicked,
+        MentionReceived, MessageReceived, NotifyOnMessage, PresenceChanged, PublishOutcome, React,
+        ScrollBy, ScrollToBottom, ScrollToTop, SendMessage, SendStructuredMessage,
+        SetFilterExpression, SetUsername, SwitchChannel, SystemMessage, TypingChanged,
+        UnreadBelowChanged, UpdateAuthToken,
+    },
+    focus::ChatFocus,
+    fonts::{
+        embedded_font_startup, font_load_handler, font_path_check, font_support_check, ChatFonts,
+    },
+    format::{relative_time_handler, RelativeTimeRefresh},
+    highlight::{highlight_handler, highlight_requests_handler},
+    history::InputHistory,
+    layout::{
+        layout_handler, scroll_wheel_handler, spawn_messages_panel, text_bounds_handler,
+        MESSAGES_ORIGIN,
+    },
+    links::link_click_handler,
+    messages::{
+        filter_expression_handler, message_handler, resubscribe_handler, switch_channel_handler,
+        Resubscribe, SeenMessages,
+    },
+    presence::{
+        heartbeat_handler, here_now_handler, leave_handler, presence_startup, HeartbeatTimer,
+        PRESENCE_CHANNEL_SUFFIX,
+    },
+    publish::send_message_handler,
+    reactions::{react_handler, reaction_render_handler},
+    render::{
+        clear_chat_handler, empty_state_handler, max_messages_handler, render_handler,
+        system_message_handler, PendingMessages,
+    },
     resources::{
-        ChannelResource, ChatMessageStyle, InputBoxStyle, MessageFormat, PubNubClientResource,
-        PubNubSubscribeResource,
+        ChannelResource, ChatBounds, ChatConfig, ChatMessageStyle, ChatTransform, Clock,
+        InputBoxStyle, MessageCounter, MessageFormat, PubNubClientResource,
+        PubNubSubscribeResource, RawTransportResource,
     },
     tasks::tasks_handler,
-    text::InputBox,
+    text::{
+        draft_handler, input_render_handler, selection_highlight_handler, InputBox, InputDraft,
+    },
+    transport::{auth_token_handler, ChatTransport},
+    typing::{typing_expiry_handler, typing_signal_handler, TypingSignal, TypingUsers},
+    username::username_handler,
 };
 
+mod background;
+mod camera;
+mod chat_log;
+mod clipboard;
+mod command;
+mod connection;
+mod crypto;
+mod emoji;
+pub mod events;
+mod focus;
+mod fonts;
+mod format;
+mod highlight;
+mod history;
 mod keyboard;
+mod layout;
+mod links;
+mod lru;
 mod messages;
+mod presence;
+mod publish;
+mod reactions;
+mod render;
 mod resources;
 mod tasks;
 mod text;
+mod transport;
+mod typing;
+mod username;
+mod word_filter;
+
+pub use self::{
+    camera::chat_overlay_camera,
+    chat_log::{ChatLog, ChatLogEntry},
+    connection::{ChatConnected, ConnectionState},
+    focus::ChatFocus,
+    layout::{HasUnreadBelow, ScrollState},
+    presence::OnlineUsers,
+    reactions::Reactions,
+    resources::{ChatPubNubClient, PubNubClientResource},
+    transport::ChatTransport,
+    typing::TypingUsers,
+};
 
 /// This struct is a plugin for Bevy engine.
 ///
@@ -53,75 +139,586 @@ mod text;
 ///
 /// # Ok(())}
 /// ```
+///
+/// # Panics
+///
+/// Only one [`ChatPlugin`] can be added to an app.
+/// Adding a second instance panics with a message naming both channels.
 pub struct ChatPlugin {
     // TODO: it has to be kept in memory because of lack of subscription implementation
     config: ChatPluginConfig,
 
-    pubnub: PubNubClient<PubNubMiddleware<TransportReqwest>>,
+    pubnub: PubNubClient<PubNubMiddleware<ChatTransport>>,
+
+    transport: ChatTransport,
 }
 
 impl TryFrom<ChatPluginConfig> for ChatPlugin {
     type Error = BevyPNError;
 
     fn try_from(config: ChatPluginConfig) -> Result<Self, Self::Error> {
-        let pubnub = PubNubClientBuilder::with_reqwest_blocking_transport()
-            .with_keyset(Keyset {
-                subscribe_key: config.keyset.subscribe_key.clone(),
-                publish_key: Some(config.keyset.publish_key.clone()),
-                secret_key: None,
-            })
-            .with_user_id(config.username.clone())
-            .build()
-            .map_err(|error| BevyPNError::Config {
-                message: error.to_string(),
-            })?;
+        let transport = ChatTransport::new(&config)?;
+        let pubnub = pubnub_client(&config, &transport, &config.username)?;
 
-        Ok(Self { config, pubnub })
+        Ok(Self {
+            config,
+            pubnub,
+            transport,
+        })
     }
 }
 
+/// Builds the PubNub client, the user id can't be changed once it is built.
+fn pubnub_client(
+    config: &ChatPluginConfig,
+    transport: &ChatTransport,
+    user_id: &str,
+) -> Result<PubNubClient<PubNubMiddleware<ChatTransport>>, BevyPNError> {
+    PubNubClientBuilder::with_transport(transport.clone())
+        .with_keyset(sdk_keyset(config))
+        .with_user_id(user_id)
+        .build()
+        .map_err(|error| BevyPNError::Config {
+            message: error.to_string(),
+        })
+}
+
+fn sdk_keyset(config: &ChatPluginConfig) -> Keyset<String> {
+    Keyset {
+        subscribe_key: config.keyset.subscribe_key.clone(),
+        publish_key: Some(config.keyset.publish_key.clone()),
+        secret_key: config.secret_key.clone(),
+    }
+}
+
+/// The chat channels followed by their presence channels if presence is enabled.
+fn subscribe_channels(config: &ChatPluginConfig) -> Vec<String> {
+    let presence = config
+        .channels
+        .iter()
+        .filter(|_| config.presence)
+        .map(|channel| format!("{}{}", channel, PRESENCE_CHANNEL_SUFFIX));
+
+    config.channels.iter().cloned().chain(presence).collect()
+}
+
+fn chat_bounds(config: &ChatPluginConfig) -> Rect {
+    config.chat_bounds.unwrap_or_else(|| {
+        let (width, height) = config.bounds;
+
+        Rect::from_corners(
+            MESSAGES_ORIGIN,
+            MESSAGES_ORIGIN + Vec2::new(width.max(1.0), height.max(1.0)),
+        )
+    })
+}
+
 impl Plugin for ChatPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
+        if let Some(existing) = app.world.get_resource::<ChatConfig>() {
+            panic!(
+                "ChatPlugin for channel `{}` collides with the instance already added for channel `{}`: \
+                 only one ChatPlugin per App is supported, because its resources are global",
+                self.config.channel().unwrap_or_default(),
+                existing.channel().unwrap_or_default()
+            );
+        }
+
         app.insert_resource(InputBoxStyle(self.config.input_style.clone()))
             .insert_resource(ChatMessageStyle(self.config.message_style.clone()))
             .insert_resource(PubNubClientResource(self.pubnub.clone()))
+            .insert_resource(RawTransportResource(self.transport.clone()))
             .insert_resource(PubNubSubscribeResource {
                 subscribe_key: self.config.keyset.subscribe_key.clone(),
-                channel: self.config.channel.clone(),
+                channels: subscribe_channels(&self.config),
+                channel_groups: self.config.channel_groups.clone(),
                 tt: "0".into(),
                 tr: "0".into(),
                 user_id: self.config.username.clone(),
+                filter: self.config.filter_expression.clone(),
+                state: self
+                    .config
+                    .presence_state
+                    .as_ref()
+                    .filter(|_| self.config.presence)
+                    .map(Value::to_string),
             })
             .insert_resource(MessageFormat(self.config.message_format.clone()))
-            .insert_resource(ChannelResource(self.config.channel.clone()))
+            .insert_resource(ChannelResource(self.config.channel().map(str::to_string)))
+            .insert_resource(ChatTransform(self.config.transform))
+            .insert_resource(ChatBounds(chat_bounds(&self.config)))
+            .insert_resource(ChatConfig(self.config.clone()))
+            .insert_resource(OnlineUsers::with_capacity(self.config.tracking_capacity))
+            .insert_resource(SeenMessages::with_capacity(self.config.tracking_capacity))
+            .init_resource::<Clock>()
+            .init_resource::<RelativeTimeRefresh>()
+            .init_resource::<PendingMessages>()
+            .init_resource::<ChatLog>()
+            .init_resource::<MessageCounter>()
+            .init_resource::<ScrollState>()
+            .init_resource::<HasUnreadBelow>()
+            .init_resource::<ChatConnected>()
+            .insert_resource(ConnectionState::Connecting)
+            .init_resource::<SubscribeActivity>()
+            .init_resource::<SubscribeBackoff>()
+            .init_resource::<PollPacing>()
+            .init_resource::<InputDraft>()
+            .init_resource::<ModifierState>()
+            .init_resource::<ChatClipboard>()
+            .insert_resource(ChatFocus::new(self.config.toggle_key))
+            .insert_resource(InputHistory::with_capacity(self.config.history_size))
+            .insert_resource(HeartbeatTimer::new(self.config.heartbeat_period()))
+            .init_resource::<TypingSignal>()
+            .init_resource::<TypingUsers>()
+            .insert_resource(Reactions::with_capacity(self.config.tracking_capacity))
+            .add_event::<HighlightMessage>()
+            .add_event::<React>()
+            .add_event::<ScrollToBottom>()
+            .add_event::<ScrollToTop>()
+            .add_event::<ScrollBy>()
+            .add_event::<UnreadBelowChanged>()
+            .add_event::<ClearChat>()
+            .add_event::<SetFilterExpression>()
+            .add_event::<SwitchChannel>()
+            .add_event::<SetUsername>()
+            .add_event::<SystemMessage>()
+            .add_event::<MessageReceived>()
+            .add_event::<MentionReceived>()
+            .add_event::<LinkClicked>()
+            .add_event::<NotifyOnMessage>()
+            .add_event::<PublishOutcome>()
+            .add_event::<SendMessage>()
+            .add_event::<SendStructuredMessage>()
+            .add_event::<ChatError>()
+            .add_event::<ChatFocusChanged>()
+            .add_event::<ConnectionChanged>()
+            .add_event::<PresenceChanged>()
+            .add_event::<TypingChanged>()
+            .add_event::<Resubscribe>()
+            .add_event::<UpdateAuthToken>()
+            .add_startup_system(font_support_check)
+            .add_startup_system(font_path_check)
+            // The embedded font is registered before the chat text is spawned.
+            .add_startup_system(embedded_font_startup.in_base_set(StartupSet::PreStartup))
             .add_startup_system(plugin_startup)
-            .add_system(keyboard_handler)
+            .add_startup_system(presence_startup);
+
+        if !self.config.read_only {
+            app.add_system(keyboard_handler);
+        }
+
+        app.add_system(draft_handler.after(keyboard_handler))
+            .add_system(input_render_handler.after(draft_handler))
+            .add_system(selection_highlight_handler.after(input_render_handler))
+            .add_system(send_message_handler)
             .add_system(tasks_handler)
+            .add_system(watchdog_handler)
+            .add_system(backoff_handler.before(resubscribe_handler))
+            .add_system(filter_expression_handler.before(resubscribe_handler))
+            .add_system(switch_channel_handler.before(resubscribe_handler))
+            .add_system(username_handler.before(resubscribe_handler))
+            .add_system(resubscribe_handler.before(tasks_handler))
+            .add_system(render_handler.after(tasks_handler))
+            .add_system(system_message_handler.after(render_handler))
+            .add_system(max_messages_handler.after(system_message_handler))
+            .add_system(clear_chat_handler.after(render_handler))
+            .add_system(scroll_wheel_handler.before(layout_handler))
+            .add_system(layout_handler.after(render_handler))
+            .add_system(empty_state_handler.after(render_handler))
+            .add_system(text_bounds_handler.after(empty_state_handler))
+            .add_system(highlight_requests_handler)
+            .add_system(highlight_handler)
+            .add_system(react_handler)
+            .add_system(reaction_render_handler.after(render_handler))
+            .add_system(link_click_handler)
+            .add_system(relative_time_handler)
+            // Runs after the spawn and despawn commands of the frame are applied.
+            .add_system(chat_log_handler.in_base_set(CoreSet::PostUpdate))
+            .add_system(font_load_handler)
+            .add_system(render_layer_handler)
+            .add_system(background_handler)
+            .add_system(here_now_handler)
+            .add_system(heartbeat_handler)
+            .add_system(auth_token_handler)
+            // Runs last, so the exit requested by any system in the frame is seen.
+            .add_system(leave_handler.in_base_set(CoreSet::Last))
+            .add_system(typing_signal_handler.after(keyboard_handler))
+            .add_system(typing_expiry_handler)
             .add_startup_system(message_handler);
     }
+
+    // Collisions are reported by `build` with a message naming both instances.
+    fn is_unique(&self) -> bool {
+        false
+    }
 }
 
 fn plugin_startup(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
+    fonts: ChatFonts,
     style: Res<InputBoxStyle>,
+    config: Res<ChatConfig>,
+    bounds: Res<ChatBounds>,
 ) {
-    let font = asset_server.load(style.font_path.to_str().unwrap_or(""));
-
-    commands.spawn((
-        InputBox::default(),
-        Text2dBundle {
-            text: bevy::text::Text::from_section(
-                "",
-                TextStyle {
-                    font: font.clone(),
-                    font_size: style.font_size,
-                    color: style.color,
-                },
-            )
-            .with_alignment(bevy::text::TextAlignment::Left),
-            transform: Transform::from_xyz(30.0, 30.0, 0.0),
-            ..Default::default()
+    let font = fonts.load(&style);
+
+    let text = Text::from_section(
+        "",
+        TextStyle {
+            font,
+            font_size: style.font_size,
+            color: style.color,
         },
-    ));
+    )
+    .with_alignment(TextAlignment::Left);
+
+    match config.render_mode {
+        RenderMode::Text2d => {
+            spawn_background(
+                &mut commands,
+                config.background_color,
+                &bounds,
+                &config.transform,
+            );
+            if !config.read_only {
+                commands.spawn((
+                    InputBox {
+                        placeholder: config.placeholder.clone(),
+                        ..Default::default()
+                    },
+                    Text2dBundle {
+                        text,
+                        transform: Transform::from_translation(
+                            config.transform.translation + config.input_position.extend(0.0),
+                        ),
+                        ..Default::default()
+                    },
+                ));
+            }
+        }
+        RenderMode::BevyUi => {
+            if !config.read_only {
+                commands.spawn((
+                    InputBox {
+                        placeholder: config.placeholder.clone(),
+                        ..Default::default()
+                    },
+                    TextBundle {
+                        text,
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                left: Val::Px(config.input_position.x),
+                                bottom: Val::Px(config.input_position.y),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                ));
+            }
+            spawn_messages_panel(&mut commands, &bounds, config.background_color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use bevy::{
+        asset::{AddAsset, AssetServer, Assets, FileAssetIo},
+        prelude::{App, Vec3, With},
+        tasks::{IoTaskPool, TaskPool},
+        text::Font,
+    };
+
+    use crate::Keyset;
+
+    use super::*;
+
+    fn chat(channel: &str) -> ChatPlugin {
+        ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .channel(channel)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn forward_secret_key_to_pubnub() {
+        let config = ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .secret_key("sec-c-test")
+            .build()
+            .unwrap()
+            .config;
+
+        assert_eq!(sdk_keyset(&config).secret_key, Some("sec-c-test".into()));
+    }
+
+    #[test]
+    fn not_use_secret_key_by_default() {
+        assert_eq!(sdk_keyset(&chat("lobby").config).secret_key, None);
+    }
+
+    #[test]
+    fn subscribe_to_presence_channels() {
+        let config = ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .channels(["lobby", "team-red"])
+            .presence(true)
+            .build()
+            .unwrap()
+            .config;
+
+        assert_eq!(
+            subscribe_channels(&config),
+            vec!["lobby", "team-red", "lobby-pnpres", "team-red-pnpres"]
+        );
+    }
+
+    #[test]
+    fn not_subscribe_to_presence_channels_by_default() {
+        assert_eq!(subscribe_channels(&chat("lobby").config), vec!["lobby"]);
+    }
+
+    #[test]
+    fn insert_configured_chat_bounds() {
+        let bounds = Rect::new(10.0, 20.0, 310.0, 220.0);
+        let chat = ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .chat_bounds(bounds)
+            .build()
+            .unwrap();
+
+        let mut app = App::new();
+        app.add_plugin(chat);
+
+        assert_eq!(app.world.resource::<ChatBounds>().0, bounds);
+    }
+
+    #[test]
+    fn derive_chat_bounds_from_size() {
+        assert_eq!(
+            chat_bounds(&chat("lobby").config),
+            Rect::new(30.0, 70.0, 530.0, 570.0)
+        );
+    }
+
+    fn headless_startup_app(config: ChatPluginConfig) -> App {
+        let mut app = App::new();
+        app.insert_resource(InputBoxStyle(config.input_style.clone()))
+            .insert_resource(ChatBounds(chat_bounds(&config)))
+            .insert_resource(RawTransportResource(ChatTransport::new(&config).unwrap()))
+            .insert_resource(ChatConfig(config))
+            .add_event::<ChatError>()
+            .add_startup_system(font_support_check)
+            .add_startup_system(font_path_check)
+            .add_startup_system(embedded_font_startup.in_base_set(StartupSet::PreStartup))
+            .add_startup_system(plugin_startup)
+            .add_startup_system(presence_startup);
+        app
+    }
+
+    fn startup_app(config: ChatPluginConfig) -> App {
+        IoTaskPool::init(TaskPool::default);
+
+        let mut app = headless_startup_app(config);
+        app.insert_resource(AssetServer::new(FileAssetIo::new("assets", false)));
+        app.update();
+        app
+    }
+
+    fn words(words: &[u16]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+
+    /// The smallest font accepted by the font parser, with only the `head`, `hhea` and `maxp` tables.
+    fn font_bytes() -> Vec<u8> {
+        let tables: [(&[u8; 4], &[u16]); 3] = [
+            (
+                b"head",
+                &[
+                    1, 0, 1, 0, 0, 0, 0x5F0F, 0x3CF5, 0, 1000, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                    0, 8, 2, 0, 0,
+                ],
+            ),
+            (
+                b"hhea",
+                &[
+                    1,
+                    0,
+                    800,
+                    (-200i16) as u16,
+                    0,
+                    500,
+                    0,
+                    0,
+                    0,
+                    1,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    1,
+                ],
+            ),
+            (b"maxp", &[0, 0x5000, 1]),
+        ];
+
+        let mut offset = 12 + 16 * tables.len();
+        let mut directory = words(&[1, 0, tables.len() as u16, 32, 1, 16]);
+        let mut data = Vec::new();
+        tables.into_iter().for_each(|(tag, table)| {
+            let length = 2 * table.len();
+            directory.extend(tag);
+            directory.extend(0u32.to_be_bytes());
+            directory.extend((offset as u32).to_be_bytes());
+            directory.extend((length as u32).to_be_bytes());
+            data.extend(words(table));
+            offset += length;
+        });
+
+        [directory, data].concat()
+    }
+
+    #[test]
+    fn spawn_input_box_with_embedded_font() {
+        IoTaskPool::init(TaskPool::default);
+
+        let mut app = headless_startup_app(
+            ChatPlugin::builder()
+                .keyset(Keyset {
+                    publish_key: "pub-c-test",
+                    subscribe_key: "sub-c-test",
+                })
+                .font_bytes(font_bytes())
+                .build()
+                .unwrap()
+                .config,
+        );
+        app.insert_resource(AssetServer::new(FileAssetIo::new("assets", false)))
+            .add_asset::<Font>();
+        app.update();
+
+        let embedded = app.world.resource::<fonts::EmbeddedFont>().0.clone();
+        assert!(app.world.resource::<Assets<Font>>().contains(&embedded));
+
+        let text = app
+            .world
+            .query_filtered::<&Text, With<InputBox>>()
+            .single(&app.world);
+        assert_eq!(text.sections[0].style.font, embedded);
+    }
+
+    #[test]
+    fn start_without_asset_server() {
+        let mut app = headless_startup_app(chat("lobby").config);
+        app.update();
+
+        assert_eq!(
+            app.world
+                .query_filtered::<(), With<InputBox>>()
+                .iter(&app.world)
+                .count(),
+            1
+        );
+        assert!(!app.world.contains_resource::<fonts::PendingFonts>());
+    }
+
+    #[test]
+    fn spawn_input_box_at_configured_position() {
+        let mut app = startup_app(
+            ChatPlugin::builder()
+                .keyset(Keyset {
+                    publish_key: "pub-c-test",
+                    subscribe_key: "sub-c-test",
+                })
+                .transform(Transform::from_xyz(100.0, 0.0, 1.0))
+                .input_position(Vec2::new(10.0, 20.0))
+                .build()
+                .unwrap()
+                .config,
+        );
+
+        let transform = app
+            .world
+            .query_filtered::<&Transform, With<InputBox>>()
+            .single(&app.world);
+        assert_eq!(transform.translation, Vec3::new(110.0, 20.0, 1.0));
+    }
+
+    #[test]
+    fn not_spawn_input_box_when_read_only() {
+        let mut app = startup_app(
+            ChatPlugin::builder()
+                .keyset(Keyset {
+                    publish_key: "pub-c-test",
+                    subscribe_key: "sub-c-test",
+                })
+                .read_only(true)
+                .build()
+                .unwrap()
+                .config,
+        );
+
+        assert_eq!(
+            app.world
+                .query_filtered::<(), With<InputBox>>()
+                .iter(&app.world)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn share_transport_between_raw_requests() {
+        let mut app = App::new();
+        app.add_plugin(chat("lobby"));
+
+        assert!(app.world.contains_resource::<RawTransportResource>());
+    }
+
+    #[test]
+    fn subscribe_only_to_channel_groups() {
+        let chat = ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .channel_group("g")
+            .build()
+            .unwrap();
+        let mut app = App::new();
+        app.add_plugin(chat);
+
+        let request = messages::subscribe_request(app.world.resource::<PubNubSubscribeResource>());
+
+        assert_eq!(request.path, "v2/subscribe/sub-c-test/,/0");
+        assert_eq!(request.query_parameters["channel-group"], "g");
+        assert_eq!(app.world.resource::<ChannelResource>().name(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "only one ChatPlugin per App is supported")]
+    fn report_colliding_instances() {
+        App::new()
+            .add_plugin(chat("lobby"))
+            .add_plugin(chat("support"));
+    }
 }
@@ -0,0 +1,794 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::{
+    app::AppExit,
+    prelude::{
+        Commands, Component, Entity, EventReader, EventWriter, Query, Res, ResMut, Resource,
+    },
+    tasks::Task,
+    time::{Time, Timer, TimerMode},
+};
+use futures_lite::future;
+use pubnub::core::{Transport, TransportMethod, TransportRequest};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{builder::PresenceMode, error::Result, BevyPNError};
+
+use super::{
+    events::{ChatError, PresenceChanged},
+    lru::LruSet,
+    messages::{insert_channel_groups, path_channels},
+    resources::{ChannelResource, ChatConfig, PubNubSubscribeResource, RawTransportResource},
+    tasks::spawn_request,
+    transport::ChatTransport,
+};
+
+/// Suffix of the channels PubNub uses to deliver presence events.
+pub const PRESENCE_CHANNEL_SUFFIX: &str = "-pnpres";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum PresenceEvent {
+    Join {
+        uuid: String,
+        occupancy: usize,
+    },
+    Leave {
+        uuid: String,
+        occupancy: usize,
+    },
+    Timeout {
+        uuid: String,
+        occupancy: usize,
+    },
+    StateChange {
+        uuid: String,
+        occupancy: usize,
+
+        #[serde(default)]
+        data: Value,
+    },
+    Interval {
+        occupancy: usize,
+
+        #[serde(default)]
+        join: Vec<String>,
+
+        #[serde(default)]
+        leave: Vec<String>,
+
+        #[serde(default)]
+        timeout: Vec<String>,
+
+        #[serde(default)]
+        here_now_refresh: bool,
+    },
+}
+
+impl PresenceEvent {
+    pub fn parse(payload: &Value) -> Result<Self> {
+        PresenceEvent::deserialize(payload).map_err(Into::into)
+    }
+}
+
+pub fn is_presence_channel(channel: &str) -> bool {
+    channel.ends_with(PRESENCE_CHANNEL_SUFFIX)
+}
+
+/// Users currently present in the chat channel.
+///
+/// It is only filled when [`presence`] is enabled.
+///
+/// At most `tracking_capacity` users are kept.
+/// When the limit is reached, the user with the oldest presence activity is dropped.
+///
+/// [`presence`]: crate::builder::ChatPluginConfigBuilder::presence
+#[derive(Debug, Clone, Default, Resource)]
+pub struct OnlineUsers {
+    users: LruSet<String>,
+    states: HashMap<String, Value>,
+    occupancy: usize,
+    refresh_needed: bool,
+}
+
+impl OnlineUsers {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            users: LruSet::new(capacity),
+            ..Default::default()
+        }
+    }
+
+    /// Returns `true` if the user with the given id is online.
+    pub fn contains(&self, user_id: &str) -> bool {
+        self.users.contains(&user_id.to_string())
+    }
+
+    /// The presence state the user attached with [`presence_state`].
+    ///
+    /// [`presence_state`]: crate::builder::ChatPluginConfigBuilder::presence_state
+    pub fn state(&self, user_id: &str) -> Option<&Value> {
+        self.states.get(user_id)
+    }
+
+    /// Iterates over the ids of the online users.
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.users.iter()
+    }
+
+    /// The channel occupancy reported by PubNub.
+    ///
+    /// In [`PresenceMode::Interval`] it can be greater than the number of known users,
+    /// because large channels are not fully enumerated.
+    pub fn occupancy(&self) -> usize {
+        self.occupancy
+    }
+
+    pub(crate) fn apply(&mut self, event: PresenceEvent, mode: PresenceMode) {
+        match event {
+            PresenceEvent::Join { uuid, occupancy } => {
+                self.users.insert(uuid);
+                self.occupancy = occupancy;
+            }
+            PresenceEvent::StateChange {
+                uuid,
+                occupancy,
+                data,
+            } => {
+                self.users.insert(uuid.clone());
+                self.states.insert(uuid, data);
+                self.occupancy = occupancy;
+            }
+            PresenceEvent::Leave { uuid, occupancy }
+            | PresenceEvent::Timeout { uuid, occupancy } => {
+                self.users.remove(&uuid);
+                self.occupancy = occupancy;
+            }
+            PresenceEvent::Interval {
+                occupancy,
+                join,
+                leave,
+                timeout,
+                here_now_refresh,
+            } => {
+                self.users.extend(join);
+                leave.iter().chain(timeout.iter()).for_each(|uuid| {
+                    self.users.remove(uuid);
+                });
+                self.occupancy = occupancy;
+                self.refresh_needed = mode == PresenceMode::Interval && here_now_refresh;
+            }
+        }
+
+        if mode == PresenceMode::Events {
+            self.occupancy = self.occupancy.max(self.users.len());
+        }
+        self.forget_states();
+    }
+
+    /// Drops the states of the users who are gone, including the evicted ones.
+    fn forget_states(&mut self) {
+        let users = &self.users;
+        self.states.retain(|uuid, _| users.contains(uuid));
+    }
+
+    /// Replaces the users with a `here_now` snapshot.
+    pub(crate) fn replace(&mut self, uuids: Vec<String>, occupancy: usize) {
+        let gone = self
+            .users
+            .iter()
+            .filter(|uuid| !uuids.contains(uuid))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        gone.iter().for_each(|uuid| {
+            self.users.remove(uuid);
+        });
+        self.users.extend(uuids);
+        self.occupancy = occupancy;
+        self.refresh_needed = false;
+        self.forget_states();
+    }
+
+    /// Returns `true` if PubNub asked for a full `here_now` refresh of the users list.
+    ///
+    /// It can only happen in [`PresenceMode::Interval`].
+    pub fn refresh_needed(&self) -> bool {
+        self.refresh_needed
+    }
+}
+
+/// Describes what changed between two states of the online users.
+pub fn presence_changes(before: &OnlineUsers, after: &OnlineUsers) -> Option<PresenceChanged> {
+    let difference = |from: &OnlineUsers, to: &OnlineUsers| {
+        let mut users = from
+            .iter()
+            .filter(|uuid| !to.contains(uuid))
+            .cloned()
+            .collect::<Vec<_>>();
+        users.sort();
+        users
+    };
+
+    let changes = PresenceChanged {
+        joined: difference(after, before),
+        left: difference(before, after),
+        occupancy: after.occupancy,
+    };
+
+    (!changes.joined.is_empty() || !changes.left.is_empty() || before.occupancy != after.occupancy)
+        .then_some(changes)
+}
+
+/// Users present in a channel as reported by `here_now`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HereNowResult {
+    pub occupancy: usize,
+
+    #[serde(default)]
+    pub uuids: Vec<String>,
+}
+
+#[derive(Component)]
+pub struct HereNowTask(pub Task<Result<HereNowResult>>);
+
+pub fn here_now_request(
+    subscription_info: &PubNubSubscribeResource,
+    channel: &str,
+) -> TransportRequest {
+    TransportRequest {
+        path: format!(
+            "v2/presence/sub-key/{}/channel/{}",
+            subscription_info.subscribe_key, channel
+        ),
+        query_parameters: HashMap::from([("uuid".into(), subscription_info.user_id.clone())]),
+        method: TransportMethod::Get,
+        headers: [].into(),
+        body: None,
+    }
+}
+
+pub async fn here_now<T: Transport>(
+    transport: &T,
+    request: TransportRequest,
+) -> Result<HereNowResult> {
+    let response = transport.send(request).await;
+
+    response.map_err(Into::into).and_then(|response| {
+        response
+            .body
+            .ok_or_else(|| BevyPNError::EmptyBody {
+                on: "HereNow".into(),
+            })
+            .and_then(|body| serde_json::from_slice::<HereNowResult>(&body).map_err(Into::into))
+    })
+}
+
+pub fn spawn_here_now(
+    commands: &mut Commands,
+    subscription_info: &PubNubSubscribeResource,
+    channel: &str,
+    transport: &ChatTransport,
+) {
+    let request = here_now_request(subscription_info, channel);
+    let transport = transport.clone();
+    let task = spawn_request(async move { here_now(&transport, request).await });
+
+    commands.spawn(HereNowTask(task));
+}
+
+/// The subscribed channels without the presence ones, joined for a request path.
+fn chat_channels(subscription_info: &PubNubSubscribeResource) -> String {
+    let channels = subscription_info
+        .channels
+        .iter()
+        .filter(|channel| !is_presence_channel(channel))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    path_channels(&channels)
+}
+
+/// The query parameters with the local user and the subscribed channel groups.
+fn presence_parameters(subscription_info: &PubNubSubscribeResource) -> HashMap<String, String> {
+    let mut query_parameters = HashMap::from([("uuid".into(), subscription_info.user_id.clone())]);
+    insert_channel_groups(&mut query_parameters, &subscription_info.channel_groups);
+    query_parameters
+}
+
+/// Request leaving the subscribed chat channels and channel groups.
+pub fn leave_request(subscription_info: &PubNubSubscribeResource) -> TransportRequest {
+    TransportRequest {
+        path: format!(
+            "v2/presence/sub-key/{}/channel/{}/leave",
+            subscription_info.subscribe_key,
+            chat_channels(subscription_info)
+        ),
+        query_parameters: presence_parameters(subscription_info),
+        method: TransportMethod::Get,
+        headers: [].into(),
+        body: None,
+    }
+}
+
+/// Request attaching the presence state to the local user in the subscribed chat channels.
+pub fn set_state_request(
+    subscription_info: &PubNubSubscribeResource,
+    state: &Value,
+) -> TransportRequest {
+    TransportRequest {
+        path: format!(
+            "v2/presence/sub-key/{}/channel/{}/uuid/{}/data",
+            subscription_info.subscribe_key,
+            chat_channels(subscription_info),
+            subscription_info.user_id
+        ),
+        query_parameters: presence_parameters(subscription_info)
+            .into_iter()
+            .chain([("state".into(), state.to_string())])
+            .collect(),
+        method: TransportMethod::Get,
+        headers: [].into(),
+        body: None,
+    }
+}
+
+/// Request keeping the local user online in the subscribed chat channels and channel groups.
+pub fn heartbeat_request(
+    subscription_info: &PubNubSubscribeResource,
+    presence_timeout: Duration,
+) -> TransportRequest {
+    let mut query_parameters = presence_parameters(subscription_info);
+    query_parameters.insert("heartbeat".into(), presence_timeout.as_secs().to_string());
+
+    if let Some(state) = &subscription_info.state {
+        query_parameters.insert("state".into(), state.clone());
+    }
+
+    TransportRequest {
+        path: format!(
+            "v2/presence/sub-key/{}/channel/{}/heartbeat",
+            subscription_info.subscribe_key,
+            chat_channels(subscription_info)
+        ),
+        query_parameters,
+        method: TransportMethod::Get,
+        headers: [].into(),
+        body: None,
+    }
+}
+
+/// Schedules the presence heartbeats.
+#[derive(Debug, Clone, Resource)]
+pub struct HeartbeatTimer(pub Timer);
+
+impl HeartbeatTimer {
+    pub fn new(interval: Duration) -> Self {
+        Self(Timer::new(interval, TimerMode::Repeating))
+    }
+}
+
+pub fn heartbeat_handler(
+    time: Res<Time>,
+    config: Res<ChatConfig>,
+    subscription_info: Res<PubNubSubscribeResource>,
+    transport: Res<RawTransportResource>,
+    mut timer: ResMut<HeartbeatTimer>,
+) {
+    if !config.presence || !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let request = heartbeat_request(&subscription_info, config.presence_timeout);
+    let transport = transport.0.clone();
+
+    // A single lost heartbeat is covered by the next one well before the timeout.
+    spawn_request(async move {
+        if let Err(err) = transport.send(request).await {
+            log::warn!("Presence heartbeat failed: {:?}", err);
+        }
+    })
+    .detach();
+}
+
+/// Leaves the chat channels when the app exits,
+/// so other clients see the user go offline without waiting for the presence timeout.
+pub fn leave_handler(
+    mut exits: EventReader<AppExit>,
+    subscription_info: Res<PubNubSubscribeResource>,
+    transport: Res<RawTransportResource>,
+) {
+    if exits.iter().count() == 0 {
+        return;
+    }
+
+    let request = leave_request(&subscription_info);
+    let transport = transport.0.clone();
+    let leave = async move { transport.send(request).await };
+
+    // The app is about to exit, a detached request would be dropped with the task pools.
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(err) = future::block_on(spawn_request(leave)) {
+        log::warn!("Leave request failed: {:?}", err);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    spawn_request(leave).detach();
+}
+
+pub fn presence_startup(
+    mut commands: Commands,
+    config: Res<ChatConfig>,
+    subscription_info: Res<PubNubSubscribeResource>,
+    channel: Res<ChannelResource>,
+    transport: Res<RawTransportResource>,
+) {
+    if !config.presence {
+        return;
+    }
+
+    if let Some(channel) = channel.name() {
+        spawn_here_now(&mut commands, &subscription_info, channel, &transport);
+    }
+
+    if let Some(state) = &config.presence_state {
+        let request = set_state_request(&subscription_info, state);
+        let transport = transport.0.clone();
+
+        // The state is sent with every subscribe as well, a failure here only delays it.
+        spawn_request(async move {
+            if let Err(err) = transport.send(request).await {
+                log::warn!("Setting the presence state failed: {:?}", err);
+            }
+        })
+        .detach();
+    }
+}
+
+pub fn here_now_handler(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut HereNowTask)>,
+    mut online_users: ResMut<OnlineUsers>,
+    mut changes: EventWriter<PresenceChanged>,
+    mut errors: EventWriter<ChatError>,
+) {
+    tasks.iter_mut().for_each(|(entity, mut task)| {
+        if let Some(res) = future::block_on(future::poll_once(&mut task.0)) {
+            match res {
+                Ok(result) => {
+                    let before = online_users.clone();
+                    online_users.replace(result.uuids, result.occupancy);
+                    changes.send_batch(presence_changes(&before, &online_users));
+                }
+                Err(err) => {
+                    log::error!("Error occurred in async here_now task: {:?}", err);
+                    errors.send(ChatError::new("here_now", &err));
+                }
+            }
+            commands.entity(entity).despawn();
+        }
+    });
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    use serde_json::json;
+
+    fn subscription_info() -> PubNubSubscribeResource {
+        PubNubSubscribeResource {
+            tt: "16806024912893745".into(),
+            tr: "1".into(),
+            ..PubNubSubscribeResource::for_tests()
+        }
+    }
+
+    #[test]
+    fn parse_event_payload() {
+        let payload = json!({
+            "action": "join",
+            "uuid": "john",
+            "occupancy": 2,
+            "timestamp": 1680602491
+        });
+
+        assert_eq!(
+            PresenceEvent::parse(&payload).unwrap(),
+            PresenceEvent::Join {
+                uuid: "john".into(),
+                occupancy: 2
+            }
+        );
+    }
+
+    #[test]
+    fn parse_state_change_payload() {
+        let payload = json!({
+            "action": "state-change",
+            "uuid": "john",
+            "occupancy": 2,
+            "timestamp": 1680602491,
+            "data": { "status": "in-match", "level": 3 }
+        });
+
+        let mut users = OnlineUsers::default();
+        users.apply(
+            PresenceEvent::parse(&payload).unwrap(),
+            PresenceMode::Events,
+        );
+
+        assert!(users.contains("john"));
+        assert_eq!(
+            users.state("john"),
+            Some(&json!({ "status": "in-match", "level": 3 }))
+        );
+    }
+
+    #[test]
+    fn forget_state_of_users_who_left() {
+        let mut users = OnlineUsers::default();
+        users.apply(
+            PresenceEvent::StateChange {
+                uuid: "john".into(),
+                occupancy: 1,
+                data: json!({ "status": "in-match" }),
+            },
+            PresenceMode::Events,
+        );
+        users.apply(
+            PresenceEvent::Leave {
+                uuid: "john".into(),
+                occupancy: 0,
+            },
+            PresenceMode::Events,
+        );
+
+        assert_eq!(users.state("john"), None);
+    }
+
+    #[test]
+    fn serialize_state_into_set_state_request() {
+        let request = set_state_request(
+            &PubNubSubscribeResource {
+                channels: vec!["lobby".into(), "lobby-pnpres".into()],
+                ..subscription_info()
+            },
+            &json!({ "status": "in-match" }),
+        );
+
+        assert_eq!(
+            request.path,
+            "v2/presence/sub-key/sub-c-test/channel/lobby/uuid/john/data"
+        );
+        assert_eq!(
+            request.query_parameters["state"],
+            r#"{"status":"in-match"}"#
+        );
+    }
+
+    #[test]
+    fn parse_interval_payload() {
+        let payload = json!({
+            "action": "interval",
+            "occupancy": 120,
+            "timestamp": 1680602491,
+            "join": ["john", "jane"],
+            "timeout": ["bob"]
+        });
+
+        assert_eq!(
+            PresenceEvent::parse(&payload).unwrap(),
+            PresenceEvent::Interval {
+                occupancy: 120,
+                join: vec!["john".into(), "jane".into()],
+                leave: vec![],
+                timeout: vec!["bob".into()],
+                here_now_refresh: false,
+            }
+        );
+    }
+
+    #[test]
+    fn apply_events_incrementally() {
+        let mut users = OnlineUsers::default();
+
+        users.apply(
+            PresenceEvent::Join {
+                uuid: "john".into(),
+                occupancy: 1,
+            },
+            PresenceMode::Events,
+        );
+        users.apply(
+            PresenceEvent::Join {
+                uuid: "jane".into(),
+                occupancy: 2,
+            },
+            PresenceMode::Events,
+        );
+        users.apply(
+            PresenceEvent::Leave {
+                uuid: "john".into(),
+                occupancy: 1,
+            },
+            PresenceMode::Events,
+        );
+
+        assert!(!users.contains("john"));
+        assert!(users.contains("jane"));
+        assert_eq!(users.occupancy(), 1);
+    }
+
+    #[test]
+    fn drop_oldest_user_over_capacity() {
+        let mut users = OnlineUsers::with_capacity(2);
+
+        ["john", "jane", "bob"].iter().for_each(|uuid| {
+            users.apply(
+                PresenceEvent::Join {
+                    uuid: uuid.to_string(),
+                    occupancy: 3,
+                },
+                PresenceMode::Events,
+            )
+        });
+
+        assert!(!users.contains("john"));
+        assert!(users.contains("bob"));
+        assert_eq!(users.occupancy(), 3);
+    }
+
+    #[test]
+    fn apply_interval_snapshot() {
+        let mut users = OnlineUsers::default();
+
+        users.apply(
+            PresenceEvent::Interval {
+                occupancy: 120,
+                join: vec!["john".into(), "jane".into()],
+                leave: vec![],
+                timeout: vec!["jane".into()],
+                here_now_refresh: true,
+            },
+            PresenceMode::Interval,
+        );
+
+        assert!(users.contains("john"));
+        assert!(!users.contains("jane"));
+        assert_eq!(users.occupancy(), 120);
+        assert!(users.refresh_needed());
+    }
+
+    #[test]
+    fn parse_here_now_response() {
+        let body = r#"{
+            "status": 200,
+            "message": "OK",
+            "occupancy": 2,
+            "uuids": ["john", "jane"],
+            "service": "Presence"
+        }"#;
+
+        assert_eq!(
+            serde_json::from_str::<HereNowResult>(body).unwrap(),
+            HereNowResult {
+                occupancy: 2,
+                uuids: vec!["john".into(), "jane".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn replace_users_with_here_now_snapshot() {
+        let mut users = OnlineUsers::default();
+        users.apply(
+            PresenceEvent::Join {
+                uuid: "bob".into(),
+                occupancy: 1,
+            },
+            PresenceMode::Events,
+        );
+        let before = users.clone();
+
+        users.replace(vec!["john".into(), "jane".into()], 2);
+
+        assert_eq!(
+            presence_changes(&before, &users),
+            Some(PresenceChanged {
+                joined: vec!["jane".into(), "john".into()],
+                left: vec!["bob".into()],
+                occupancy: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn not_report_unchanged_users() {
+        let users = OnlineUsers::default();
+
+        assert_eq!(presence_changes(&users, &users.clone()), None);
+    }
+
+    #[test]
+    fn send_presence_timeout_with_heartbeat() {
+        let request = heartbeat_request(&subscription_info(), Duration::from_secs(60));
+
+        assert_eq!(
+            request.path,
+            "v2/presence/sub-key/sub-c-test/channel/lobby/heartbeat"
+        );
+        assert_eq!(request.query_parameters["heartbeat"], "60");
+    }
+
+    #[test]
+    fn fire_heartbeat_at_configured_cadence() {
+        let mut timer = HeartbeatTimer::new(Duration::from_secs(29));
+
+        let fired = (0..120)
+            .filter(|_| timer.0.tick(Duration::from_millis(500)).just_finished())
+            .count();
+
+        assert_eq!(fired, 2);
+    }
+
+    #[test]
+    fn leave_subscribed_chat_channels() {
+        let request = leave_request(&PubNubSubscribeResource {
+            channels: vec!["lobby".into(), "team".into(), "lobby-pnpres".into()],
+            ..subscription_info()
+        });
+
+        assert_eq!(
+            request.path,
+            "v2/presence/sub-key/sub-c-test/channel/lobby,team/leave"
+        );
+        assert_eq!(
+            request.query_parameters,
+            HashMap::from([("uuid".into(), "john".into())])
+        );
+        assert!(matches!(request.method, TransportMethod::Get));
+    }
+
+    fn groups_only() -> PubNubSubscribeResource {
+        PubNubSubscribeResource {
+            channels: Vec::new(),
+            channel_groups: vec!["friends".into(), "guild".into()],
+            ..subscription_info()
+        }
+    }
+
+    #[test]
+    fn send_heartbeat_for_channel_groups() {
+        let request = heartbeat_request(&groups_only(), Duration::from_secs(60));
+
+        assert_eq!(
+            request.path,
+            "v2/presence/sub-key/sub-c-test/channel/,/heartbeat"
+        );
+        assert_eq!(request.query_parameters["channel-group"], "friends,guild");
+    }
+
+    #[test]
+    fn leave_channel_groups() {
+        let request = leave_request(&PubNubSubscribeResource {
+            channels: vec!["lobby".into(), "lobby-pnpres".into()],
+            ..groups_only()
+        });
+
+        assert_eq!(
+            request.path,
+            "v2/presence/sub-key/sub-c-test/channel/lobby/leave"
+        );
+        assert_eq!(request.query_parameters["channel-group"], "friends,guild");
+    }
+
+    #[test]
+    fn leave_only_channel_groups() {
+        assert_eq!(
+            leave_request(&groups_only()).path,
+            "v2/presence/sub-key/sub-c-test/channel/,/leave"
+        );
+    }
+}
@@ -0,0 +1,464 @@
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::{Commands, EventReader, EventWriter, Res, ResMut},
+    time::Time,
+};
+use serde_json::{json, Value};
+
+use crate::{builder::ChatPluginConfig, error::Result, BevyPNError};
+
+use super::{
+    crypto::encrypt,
+    events::{ChatError, SendMessage, SendStructuredMessage},
+    format::system_time_to_timetoken,
+    messages::ChatMessage,
+    render::{LocalMessages, PendingMessages},
+    resources::{
+        ChannelResource, ChatConfig, Clock, PubNubClientResource, PubNubSubscribeResource,
+    },
+    tasks::{spawn_request, PublishTask},
+    word_filter::filter_outgoing,
+};
+
+/// Messages longer than this are published with POST to avoid URL length limits.
+const POST_THRESHOLD: usize = 1024;
+
+/// Characters that are awkward to encode in a URL path.
+const URL_UNFRIENDLY_CHARACTERS: &[char] = &['#', '%', '?', '/', '\\', '&', '+', '"'];
+
+pub fn send_message_handler(
+    mut commands: Commands,
+    mut messages: EventReader<SendMessage>,
+    mut structured: EventReader<SendStructuredMessage>,
+    mut publisher: Publisher,
+    config: Res<ChatConfig>,
+    mut local_messages: LocalMessages,
+    mut errors: EventWriter<ChatError>,
+) {
+    messages
+        .iter()
+        .map(|SendMessage(message)| (message, None))
+        .chain(
+            structured
+                .iter()
+                .map(|message| (&message.text, Some(message.meta.clone()))),
+        )
+        .for_each(|(message, meta)| {
+            // The same order as the messages typed in the input box.
+            let message = match filter_outgoing(
+                message.clone(),
+                &config.word_filter,
+                config.word_filter_mode,
+            ) {
+                Ok(message) => message,
+                Err(err) => {
+                    log::warn!("Message not published: {}", err);
+                    errors.send(ChatError::new("publish", &err));
+                    return;
+                }
+            };
+
+            if !config.can_publish(&message) {
+                log::warn!("Message rejected by the publish predicate: {}", message);
+                local_messages.show(&mut commands, config.strings.publish_rejected.clone());
+                return;
+            }
+
+            if let Err(err) = validate_length(&message, config.max_message_length) {
+                log::warn!("Message not published: {}", err);
+                errors.send(ChatError::new("publish", &err));
+                return;
+            }
+
+            if let Err(err) = publisher.publish(&mut commands, message, meta) {
+                log::warn!("Message not published: {}", err);
+                errors.send(ChatError::new("publish", &err));
+            }
+        });
+}
+
+/// Resources needed to publish a message to the chat channel.
+#[derive(SystemParam)]
+pub struct Publisher<'w> {
+    time: Res<'w, Time>,
+    clock: Res<'w, Clock>,
+    pubnub: Res<'w, PubNubClientResource>,
+    channel: Res<'w, ChannelResource>,
+    subscription_info: Res<'w, PubNubSubscribeResource>,
+    config: Res<'w, ChatConfig>,
+    pending: ResMut<'w, PendingMessages>,
+}
+
+impl Publisher<'_> {
+    /// Publishes the message and, with `local_echo` enabled, renders it right away.
+    ///
+    /// Fails without a channel to publish to, when only channel groups are subscribed.
+    pub fn publish(
+        &mut self,
+        commands: &mut Commands,
+        message: String,
+        meta: Option<Value>,
+    ) -> Result<()> {
+        let channel = self
+            .channel
+            .name()
+            .ok_or(BevyPNError::NoPublishChannel)?
+            .to_string();
+
+        if self.config.local_echo {
+            let echo = ChatMessage {
+                user_id: self.subscription_info.user_id.clone(),
+                channel: channel.clone(),
+                ..ChatMessage::local(message.clone(), system_time_to_timetoken(self.clock.now()))
+            };
+            self.pending.push_echo(echo, self.time.elapsed());
+        }
+
+        spawn_publish(
+            commands,
+            &self.pubnub,
+            channel,
+            message,
+            meta,
+            &self.subscription_info.user_id,
+            &self.config,
+        );
+        Ok(())
+    }
+}
+
+/// Publishes the message as configured.
+///
+/// `meta` forces a structured payload even if `structured_messages` is disabled.
+pub fn spawn_publish(
+    commands: &mut Commands,
+    pubnub: &PubNubClientResource,
+    channel: String,
+    message: String,
+    meta: Option<Value>,
+    user_id: &str,
+    config: &ChatPluginConfig,
+) {
+    let text = message.clone();
+    let sender = config.embed_sender_in_payload.then_some(user_id);
+    let meta = meta.or_else(|| config.structured_messages.then_some(Value::Null));
+    let via_post = config.publish_via_post;
+
+    let (message, use_post) = match &config.cipher_key {
+        Some(cipher_key) => {
+            let encrypted = encrypt(
+                cipher_key,
+                &outgoing_payload(message, sender, meta).to_string(),
+            );
+            let use_post = should_use_post(&encrypted, via_post);
+
+            (Value::String(encrypted), use_post)
+        }
+        None => {
+            let use_post = should_use_post(&message, via_post);
+
+            (outgoing_payload(message, sender, meta), use_post)
+        }
+    };
+
+    let pubnub = pubnub.clone();
+    let task = spawn_request(async move {
+        pubnub
+            .publish_message(message)
+            .channel(channel)
+            .use_post(use_post)
+            .execute()
+            .await
+            .map(|_| ())
+            .map_err(Into::into)
+    });
+
+    commands.spawn(PublishTask {
+        message: text,
+        task,
+    });
+}
+
+/// Checks that the message fits in the configured limit.
+pub fn validate_length(message: &str, max: usize) -> Result<()> {
+    match message.len() {
+        length if length > max => Err(BevyPNError::MessageTooLong { length, max }),
+        _ => Ok(()),
+    }
+}
+
+/// Builds the published payload.
+///
+/// When the sender is embedded or the message is structured, the payload is an object
+/// with `text` and the optional `sender` and `meta` fields,
+/// otherwise it is the plain message string.
+pub fn outgoing_payload(message: String, sender: Option<&str>, meta: Option<Value>) -> Value {
+    if sender.is_none() && meta.is_none() {
+        return Value::String(message);
+    }
+
+    let mut payload = json!({ "text": message });
+    if let Some(sender) = sender {
+        payload["sender"] = sender.into();
+    }
+    if let Some(meta) = meta {
+        payload["meta"] = meta;
+    }
+    payload
+}
+
+pub fn should_use_post(message: &str, via_post: bool) -> bool {
+    via_post
+        || message.len() > POST_THRESHOLD
+        || message
+            .chars()
+            .any(|c| !c.is_ascii() || c.is_control() || URL_UNFRIENDLY_CHARACTERS.contains(&c))
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    use std::time::Duration;
+
+    use bevy::{
+        asset::{AssetServer, FileAssetIo},
+        prelude::{App, With},
+        tasks::{AsyncComputeTaskPool, IoTaskPool, TaskPool},
+    };
+
+    use crate::{
+        plugin::{
+            messages::{Message, StructuredPayload},
+            render::LocalMessage,
+            resources::{ChatMessageStyle, MessageCounter},
+        },
+        ChatPlugin, FilterMode, Keyset,
+    };
+
+    use test_case::test_case;
+
+    #[test_case("hello", false => false)]
+    #[test_case("hello", true => true)]
+    #[test_case("what?", false => true)]
+    #[test_case("zażółć", false => true)]
+    #[test_case("100%", false => true)]
+    fn select_publish_method(message: &str, via_post: bool) -> bool {
+        should_use_post(message, via_post)
+    }
+
+    fn received(payload: Value, publisher: &str) -> Message {
+        serde_json::from_value(json!({
+            "c": "lobby",
+            "d": payload,
+            "i": publisher,
+            "p": { "t": "16806024912893744", "r": 1 }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trip_embedded_sender() {
+        let message = received(outgoing_payload("Hello".into(), Some("John Doe"), None), "");
+
+        assert_eq!(message.sender(), "John Doe");
+        assert_eq!(message.text(), "Hello");
+    }
+
+    #[test]
+    fn fall_back_to_publisher_without_embedded_sender() {
+        let message = received(outgoing_payload("Hello".into(), None, None), "john");
+
+        assert_eq!(message.sender(), "john");
+        assert_eq!(message.text(), "Hello");
+    }
+
+    #[test]
+    fn round_trip_plain_payload() {
+        let message = received(outgoing_payload("Hello".into(), None, None), "john");
+
+        assert_eq!(
+            message.structured(),
+            StructuredPayload {
+                text: "Hello".into(),
+                meta: Value::Null,
+            }
+        );
+    }
+
+    #[test]
+    fn round_trip_structured_payload() {
+        let meta = json!({ "client": "1.2.0", "reply_to": "16806024912893744" });
+        let message = received(
+            outgoing_payload("Hello".into(), Some("John Doe"), Some(meta.clone())),
+            "john",
+        );
+
+        assert_eq!(message.sender(), "John Doe");
+        assert_eq!(
+            message.structured(),
+            StructuredPayload {
+                text: "Hello".into(),
+                meta,
+            }
+        );
+    }
+
+    #[test]
+    fn accept_message_at_length_limit() {
+        assert!(validate_length(&"a".repeat(32), 32).is_ok());
+    }
+
+    #[test]
+    fn reject_message_over_length_limit() {
+        assert!(matches!(
+            validate_length(&"a".repeat(33), 32),
+            Err(BevyPNError::MessageTooLong {
+                length: 33,
+                max: 32
+            })
+        ));
+    }
+
+    #[test]
+    fn measure_length_in_bytes() {
+        assert!(validate_length("zażółć", 6).is_err());
+    }
+
+    #[test]
+    fn use_post_for_long_messages() {
+        assert!(!should_use_post(&"a".repeat(POST_THRESHOLD), false));
+        assert!(should_use_post(&"a".repeat(POST_THRESHOLD + 1), false));
+    }
+
+    fn app() -> App {
+        let chat = ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .channel("lobby")
+            .can_publish(|text| !text.contains("http"))
+            .word_filter(vec!["heck".into()])
+            .word_filter_mode(FilterMode::Block)
+            .build()
+            .unwrap();
+
+        AsyncComputeTaskPool::init(TaskPool::default);
+        IoTaskPool::init(TaskPool::default);
+
+        let mut app = App::new();
+        app.insert_resource(PubNubClientResource(chat.pubnub.clone()))
+            .insert_resource(ChannelResource(Some("lobby".into())))
+            .insert_resource(PubNubSubscribeResource {
+                ..PubNubSubscribeResource::for_tests()
+            })
+            .insert_resource(ChatMessageStyle(chat.config.message_style.clone()))
+            .insert_resource(ChatConfig(chat.config))
+            .insert_resource(AssetServer::new(FileAssetIo::new("assets", false)))
+            .init_resource::<Time>()
+            .init_resource::<Clock>()
+            .init_resource::<MessageCounter>()
+            .init_resource::<PendingMessages>()
+            .add_event::<SendMessage>()
+            .add_event::<SendStructuredMessage>()
+            .add_event::<ChatError>()
+            .add_system(send_message_handler);
+        app
+    }
+
+    fn publish_tasks(app: &mut App) -> Vec<String> {
+        app.world
+            .query::<&PublishTask>()
+            .iter(&app.world)
+            .map(|task| task.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn publish_sent_message() {
+        let mut app = app();
+
+        app.world.send_event(SendMessage("Hello".into()));
+        app.update();
+
+        assert_eq!(publish_tasks(&mut app), vec!["Hello"]);
+    }
+
+    #[test]
+    fn not_publish_rejected_message() {
+        let mut app = app();
+
+        app.world
+            .send_event(SendMessage("https://example.com".into()));
+        app.update();
+
+        assert!(publish_tasks(&mut app).is_empty());
+        let rejected = app
+            .world
+            .resource::<ChatConfig>()
+            .strings
+            .publish_rejected
+            .clone();
+        assert_eq!(
+            app.world
+                .query_filtered::<&ChatMessage, With<LocalMessage>>()
+                .iter(&app.world)
+                .map(|message| message.payload.clone())
+                .collect::<Vec<_>>(),
+            vec![rejected]
+        );
+    }
+
+    #[test]
+    fn not_publish_blocked_message() {
+        let mut app = app();
+
+        app.world.send_event(SendMessage("What the heck".into()));
+        app.update();
+
+        assert!(publish_tasks(&mut app).is_empty());
+        assert_eq!(
+            app.world
+                .resource::<bevy::ecs::event::Events<ChatError>>()
+                .iter_current_update_events()
+                .map(|error| error.context.as_str())
+                .collect::<Vec<_>>(),
+            vec!["publish: blocked word"]
+        );
+    }
+
+    #[test]
+    fn echo_sent_message_locally() {
+        let mut app = app();
+        app.world.resource_mut::<ChatConfig>().0.local_echo = true;
+
+        app.world.send_event(SendMessage("Hello".into()));
+        app.update();
+
+        assert_eq!(publish_tasks(&mut app), vec!["Hello"]);
+        assert_eq!(
+            app.world
+                .resource_mut::<PendingMessages>()
+                .take_ready(Duration::ZERO, None)
+                .into_iter()
+                .map(|message| (message.user_id, message.payload))
+                .collect::<Vec<_>>(),
+            vec![("john".to_string(), "Hello".to_string())]
+        );
+    }
+
+    #[test]
+    fn not_echo_sent_message_by_default() {
+        let mut app = app();
+
+        app.world.send_event(SendMessage("Hello".into()));
+        app.update();
+
+        assert!(app
+            .world
+            .resource_mut::<PendingMessages>()
+            .take_ready(Duration::ZERO, None)
+            .is_empty());
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+
+use bevy::{
+    prelude::{Added, Commands, Component, Entity, EventReader, Query, Res, Resource},
+    text::{Text, TextSection, TextStyle},
+};
+use percent_encoding::utf8_percent_encode;
+use pubnub::core::{Transport, TransportMethod, TransportRequest};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    events::React,
+    lru::LruSet,
+    messages::ChatMessage,
+    render::dimmed,
+    resources::{ChannelResource, ChatConfig, PubNubSubscribeResource, RawTransportResource},
+    tasks::spawn_request,
+    transport::QUERY_ENCODE_SET,
+};
+
+/// Font size multiplier of the reaction row.
+const REACTION_FONT_SCALE: f32 = 0.8;
+
+/// Payload of the reaction signal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReactionSignal {
+    /// The reaction emoji.
+    pub reaction: String,
+
+    /// The timetoken of the message the reaction is for.
+    pub to: String,
+}
+
+impl ReactionSignal {
+    /// Parses the signal payload, `None` if it is not a reaction.
+    pub fn parse(payload: &Value) -> Option<Self> {
+        ReactionSignal::deserialize(payload).ok()
+    }
+}
+
+/// Reactions to the chat messages.
+///
+/// It maps the message timetoken to the reaction emoji and their counts.
+/// Every reaction signal counts, so a user reacting twice is counted twice.
+/// Only the reactions to the latest `tracking_capacity` reacted messages are kept.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct Reactions {
+    counts: HashMap<String, HashMap<String, u32>>,
+    tracked: LruSet<String>,
+}
+
+impl Reactions {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            counts: HashMap::new(),
+            tracked: LruSet::new(capacity),
+        }
+    }
+
+    /// The reaction counts of the message with the given timetoken.
+    pub fn of(&self, timetoken: &str) -> Option<&HashMap<String, u32>> {
+        self.counts.get(timetoken)
+    }
+
+    pub(crate) fn add(&mut self, signal: ReactionSignal) {
+        self.tracked.insert(signal.to.clone());
+        *self
+            .counts
+            .entry(signal.to)
+            .or_default()
+            .entry(signal.reaction)
+            .or_default() += 1;
+
+        // The least recently reacted message was evicted.
+        if self.counts.len() > self.tracked.len() {
+            let tracked = &self.tracked;
+            self.counts
+                .retain(|timetoken, _| tracked.contains(timetoken));
+        }
+    }
+}
+
+/// Index of the text section holding the reaction row of a message.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ReactionRow(usize);
+
+pub fn reaction_signal_request(
+    subscription_info: &PubNubSubscribeResource,
+    publish_key: &str,
+    channel: &str,
+    signal: &ReactionSignal,
+) -> TransportRequest {
+    let payload = serde_json::to_string(signal).unwrap_or_default();
+
+    TransportRequest {
+        path: format!(
+            "v1/signal/{}/{}/0/{}/0/{}",
+            publish_key,
+            subscription_info.subscribe_key,
+            channel,
+            utf8_percent_encode(&payload, QUERY_ENCODE_SET)
+        ),
+        query_parameters: HashMap::from([("uuid".into(), subscription_info.user_id.clone())]),
+        method: TransportMethod::Get,
+        headers: [].into(),
+        body: None,
+    }
+}
+
+/// Sends the reactions as signals, they are counted when they come back from PubNub.
+pub fn react_handler(
+    mut reactions: EventReader<React>,
+    config: Res<ChatConfig>,
+    channel: Res<ChannelResource>,
+    subscription_info: Res<PubNubSubscribeResource>,
+    transport: Res<RawTransportResource>,
+) {
+    let Some(channel) = channel.name() else {
+        return;
+    };
+
+    reactions.iter().for_each(|reaction| {
+        let request = reaction_signal_request(
+            &subscription_info,
+            &config.keyset.publish_key,
+            channel,
+            &ReactionSignal {
+                reaction: reaction.emoji.clone(),
+                to: reaction.message_tt.clone(),
+            },
+        );
+        let transport = transport.0.clone();
+
+        spawn_request(async move {
+            if let Err(err) = transport.send(request).await {
+                log::warn!("Reaction not sent: {:?}", err);
+            }
+        })
+        .detach();
+    });
+}
+
+/// The reaction row shown under a message, the most frequent reactions first.
+pub fn reaction_row(counts: &HashMap<String, u32>) -> String {
+    let mut counts = counts.iter().collect::<Vec<_>>();
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+    counts
+        .into_iter()
+        .map(|(reaction, count)| format!("{} {}", reaction, count))
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+/// Shows the reactions under the messages they are for.
+///
+/// The row is a new line of the message text, the layout makes room for it with the text height.
+/// Messages spawned after their reactions were received get the row too.
+pub fn reaction_render_handler(
+    mut commands: Commands,
+    reactions: Res<Reactions>,
+    mut messages: Query<(Entity, &ChatMessage, &mut Text, Option<&ReactionRow>)>,
+    added: Query<Entity, Added<ChatMessage>>,
+) {
+    if !reactions.is_changed() && added.is_empty() {
+        return;
+    }
+
+    messages
+        .iter_mut()
+        .filter(|(entity, ..)| reactions.is_changed() || added.contains(*entity))
+        .for_each(|(entity, message, mut text, row)| {
+            let Some(counts) = reactions.of(&message.timetoken) else {
+                return;
+            };
+            let value = format!("\n{}", reaction_row(counts));
+
+            match row {
+                Some(ReactionRow(index)) => text.sections[*index].value = value,
+                None => {
+                    let style = &text.sections[0].style;
+                    let section = TextSection::new(
+                        value,
+                        TextStyle {
+                            font: style.font.clone(),
+                            font_size: style.font_size * REACTION_FONT_SCALE,
+                            color: dimmed(style.color),
+                        },
+                    );
+
+                    commands
+                        .entity(entity)
+                        .insert(ReactionRow(text.sections.len()));
+                    text.sections.push(section);
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod should {
+    use bevy::prelude::App;
+    use serde_json::json;
+
+    use super::*;
+
+    fn signal(reaction: &str, to: &str) -> ReactionSignal {
+        ReactionSignal {
+            reaction: reaction.into(),
+            to: to.into(),
+        }
+    }
+
+    #[test]
+    fn aggregate_reactions_to_one_message() {
+        let mut reactions = Reactions::default();
+
+        reactions.add(signal("👍", "16806024912893744"));
+        reactions.add(signal("🎉", "16806024912893744"));
+        reactions.add(signal("👍", "16806024912893744"));
+        reactions.add(signal("👍", "16806024912893745"));
+
+        assert_eq!(
+            reactions.of("16806024912893744"),
+            Some(&HashMap::from([("👍".into(), 2), ("🎉".into(), 1)]))
+        );
+        assert_eq!(
+            reactions.of("16806024912893745"),
+            Some(&HashMap::from([("👍".into(), 1)]))
+        );
+        assert_eq!(reactions.of("16806024912893746"), None);
+    }
+
+    #[test]
+    fn forget_reactions_to_least_recently_reacted_messages() {
+        let mut reactions = Reactions::with_capacity(2);
+
+        reactions.add(signal("👍", "16806024912893744"));
+        reactions.add(signal("👍", "16806024912893745"));
+        reactions.add(signal("🎉", "16806024912893744"));
+        reactions.add(signal("👍", "16806024912893746"));
+
+        assert!(reactions.of("16806024912893744").is_some());
+        assert_eq!(reactions.of("16806024912893745"), None);
+        assert!(reactions.of("16806024912893746").is_some());
+    }
+
+    #[test]
+    fn parse_reaction_signal() {
+        assert_eq!(
+            ReactionSignal::parse(&json!({ "reaction": "👍", "to": "16806024912893744" })),
+            Some(signal("👍", "16806024912893744"))
+        );
+        assert_eq!(ReactionSignal::parse(&json!("typing")), None);
+    }
+
+    #[test]
+    fn sort_reaction_row_by_count() {
+        assert_eq!(
+            reaction_row(&HashMap::from([
+                ("🎉".into(), 1),
+                ("👍".into(), 3),
+                ("😂".into(), 1),
+            ])),
+            "👍 3  🎉 1  😂 1"
+        );
+    }
+
+    #[test]
+    fn encode_reaction_in_signal_path() {
+        let subscription_info = PubNubSubscribeResource {
+            ..PubNubSubscribeResource::for_tests()
+        };
+
+        assert_eq!(
+            reaction_signal_request(
+                &subscription_info,
+                "pub-c-test",
+                "lobby",
+                &signal("👍", "16806024912893744")
+            )
+            .path,
+            "v1/signal/pub-c-test/sub-c-test/0/lobby/0/\
+             %7B%22reaction%22%3A%22%F0%9F%91%8D%22%2C%22to%22%3A%2216806024912893744%22%7D"
+        );
+    }
+
+    #[test]
+    fn render_reaction_row_under_message() {
+        let mut app = App::new();
+        app.init_resource::<Reactions>()
+            .add_system(reaction_render_handler);
+        let entity = app
+            .world
+            .spawn((
+                ChatMessage::local("Hello", "16806024912893744"),
+                Text::from_section("john: Hello", TextStyle::default()),
+            ))
+            .id();
+
+        app.world
+            .resource_mut::<Reactions>()
+            .add(signal("👍", "16806024912893744"));
+        app.update();
+        app.world
+            .resource_mut::<Reactions>()
+            .add(signal("👍", "16806024912893744"));
+        app.update();
+
+        let text = app.world.get::<Text>(entity).unwrap();
+        assert_eq!(
+            text.sections
+                .iter()
+                .map(|section| section.value.as_str())
+                .collect::<Vec<_>>(),
+            vec!["john: Hello", "\n👍 2"]
+        );
+    }
+
+    #[test]
+    fn render_reaction_row_under_message_spawned_later() {
+        let mut app = App::new();
+        app.init_resource::<Reactions>()
+            .add_system(reaction_render_handler);
+        app.world
+            .resource_mut::<Reactions>()
+            .add(signal("👍", "16806024912893744"));
+        app.update();
+
+        let entity = app
+            .world
+            .spawn((
+                ChatMessage::local("Hello", "16806024912893744"),
+                Text::from_section("john: Hello", TextStyle::default()),
+            ))
+            .id();
+        app.update();
+
+        let text = app.world.get::<Text>(entity).unwrap();
+        assert_eq!(
+            text.sections
+                .iter()
+                .map(|section| section.value.as_str())
+                .collect::<Vec<_>>(),
+            vec!["john: Hello", "\n👍 1"]
+        );
+    }
+}
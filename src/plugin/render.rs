@@ -0,0 +1,880 @@
+use std::{collections::VecDeque, mem, time::Duration};
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::{
+        BuildChildren, Bundle, Color, Commands, Component, DespawnRecursiveExt, Entity,
+        EventReader, EventWriter, Handle, Query, Res, ResMut, Resource, TextBundle, Transform,
+        With,
+    },
+    sprite::Anchor,
+    text::{Font, Text, Text2dBundle, TextAlignment, TextSection, TextStyle},
+    time::Time,
+    ui::{AlignSelf, Style},
+};
+
+use super::{
+    chat_log::ChatLog,
+    events::{ClearChat, MentionReceived, SystemMessage},
+    fonts::ChatFonts,
+    format::{format_message, system_time_to_timetoken},
+    highlight::{mentions, Highlight, MENTION_HIGHLIGHT_DURATION},
+    layout::{message_position, MESSAGES_ORIGIN},
+    links::split_links,
+    messages::ChatMessage,
+    resources::{
+        ChatBounds, ChatConfig, ChatMessageStyle, ChatTransform, Clock, MessageCounter,
+        MessageFormat, PubNubSubscribeResource,
+    },
+};
+
+/// Marks messages that were created locally and never went through PubNub.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct LocalMessage;
+
+/// Column holding the messages when rendering with [`RenderMode::BevyUi`].
+///
+/// [`RenderMode::BevyUi`]: crate::RenderMode::BevyUi
+#[derive(Component, Debug, Clone, Copy)]
+pub struct MessagesColumn;
+
+/// Marks the placeholder shown while there are no messages.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct EmptyState;
+
+/// Color multiplier applied to locally generated lines.
+const LOCAL_MESSAGE_DIMMING: f32 = 0.6;
+
+/// Font size multiplier of the raw payload debug output.
+const DEBUG_FONT_SCALE: f32 = 0.75;
+
+/// Sent messages remembered until their copies come back from PubNub.
+const MAX_LOCAL_ECHOES: usize = 64;
+
+/// Golden ratio conjugate, it spreads the hues of the users around the color wheel.
+const GOLDEN_RATIO_CONJUGATE: f64 = 0.618_033_988_749_895;
+
+/// Saturation and lightness of the user colors.
+const USER_COLOR_SATURATION: f32 = 0.65;
+const USER_COLOR_LIGHTNESS: f32 = 0.65;
+
+/// Messages received from PubNub and waiting to be rendered.
+///
+/// It also remembers the local echoes of the sent messages,
+/// so their copies coming back from PubNub are rendered only once.
+#[derive(Debug, Default, Resource)]
+pub struct PendingMessages {
+    messages: Vec<ChatMessage>,
+    first_received_at: Option<Duration>,
+    echoes: VecDeque<String>,
+}
+
+impl PendingMessages {
+    pub fn push(&mut self, message: ChatMessage, now: Duration) {
+        self.first_received_at.get_or_insert(now);
+        self.messages.push(message);
+    }
+
+    /// Queues the local echo of a sent message.
+    pub fn push_echo(&mut self, message: ChatMessage, now: Duration) {
+        if self.echoes.len() == MAX_LOCAL_ECHOES {
+            self.echoes.pop_front();
+        }
+        self.echoes.push_back(message.payload.clone());
+        self.push(message, now);
+    }
+
+    /// Returns `true` if the payload was already rendered as a local echo.
+    ///
+    /// Every echo matches a single copy, so the same message sent twice is rendered twice.
+    pub fn take_echo(&mut self, payload: &str) -> bool {
+        self.echoes
+            .iter()
+            .position(|echo| echo == payload)
+            .and_then(|index| self.echoes.remove(index))
+            .is_some()
+    }
+
+    /// Takes the whole batch once the oldest pending message waited for the debounce window.
+    pub fn take_ready(&mut self, now: Duration, debounce: Option<Duration>) -> Vec<ChatMessage> {
+        let ready = self
+            .first_received_at
+            .map(|first| debounce.map_or(true, |window| now.saturating_sub(first) >= window))
+            .unwrap_or(false);
+
+        if !ready {
+            return Vec::new();
+        }
+
+        self.first_received_at = None;
+        mem::take(&mut self.messages)
+    }
+
+    /// Drops the messages waiting to be rendered.
+    ///
+    /// The local echoes are kept, so their copies coming back from PubNub are still skipped.
+    pub fn discard(&mut self) {
+        self.first_received_at = None;
+        self.messages.clear();
+    }
+}
+
+pub fn render_handler(
+    mut commands: Commands,
+    time: Res<Time>,
+    clock: Res<Clock>,
+    config: Res<ChatConfig>,
+    mut pending: ResMut<PendingMessages>,
+    mut counter: ResMut<MessageCounter>,
+    fonts: ChatFonts,
+    message_style: Res<ChatMessageStyle>,
+    message_format: Res<MessageFormat>,
+    subscription_info: Res<PubNubSubscribeResource>,
+    columns: Query<Entity, With<MessagesColumn>>,
+    mut mentions_received: EventWriter<MentionReceived>,
+) {
+    let batch = pending.take_ready(time.elapsed(), config.render_debounce);
+
+    if batch.is_empty() {
+        return;
+    }
+
+    let font = fonts.load(&message_style);
+    let own_style = config
+        .own_message_style
+        .as_ref()
+        .map(|style| (style, fonts.load(style)));
+    let column = columns.get_single().ok();
+    let now = clock.now();
+
+    // The oldest messages of a batch over the limit would be despawned right away.
+    let mut trimmed = config
+        .max_messages
+        .map_or(0, |max| batch.len().saturating_sub(max));
+    let mut plain_lines = Vec::with_capacity(batch.len() - trimmed);
+
+    batch.into_iter().for_each(|mut message| {
+        message.index = counter.next();
+        let mentioned = message.user_id != subscription_info.user_id
+            && mentions(
+                &message.payload,
+                &subscription_info.user_id,
+                config.mention_requires_at,
+            );
+        let own = own_style
+            .as_ref()
+            .filter(|_| message.user_id == subscription_info.user_id);
+        let (base_style, font) =
+            own.map_or((&message_style.0, &font), |(style, font)| (*style, font));
+        let style = crate::TextStyle {
+            color: if mentioned {
+                config.mention_color
+            } else if config.color_by_user && own.is_none() {
+                user_color(&message.user_id)
+            } else {
+                base_style.color
+            },
+            ..base_style.clone()
+        };
+
+        if mentioned {
+            mentions_received.send(MentionReceived {
+                user_id: message.user_id.clone(),
+                payload: message.payload.clone(),
+                channel: message.channel.clone(),
+                timetoken: message.timetoken.clone(),
+            });
+        }
+
+        if trimmed > 0 {
+            trimmed -= 1;
+            return;
+        }
+
+        let alignment = message_alignment(
+            &message.user_id,
+            &subscription_info.user_id,
+            config.align_own_messages,
+        );
+        let mut text = message_text(
+            format_message(&message_format, &message, now, &config),
+            &style,
+            font.clone(),
+        )
+        .with_alignment(alignment);
+        let links = split_links(&mut text, config.link_color);
+
+        if let Some(raw) = &message.raw {
+            text.sections
+                .push(debug_section(raw, &message_style, font.clone()));
+        }
+
+        let highlight = mentioned.then(|| Highlight::new(MENTION_HIGHLIGHT_DURATION, &text));
+
+        // Plain world space lines are spawned together after the whole batch is laid out.
+        if column.is_none() && !mentioned && links.is_none() {
+            plain_lines.push((world_line(text), message));
+            return;
+        }
+
+        let entity = spawn_line(&mut commands, column, text, message);
+
+        // Text2d lines are aligned within their bounds, the UI ones are moved in the column.
+        if alignment == TextAlignment::Right && column.is_some() {
+            commands.entity(entity).insert(Style {
+                align_self: AlignSelf::FlexEnd,
+                ..Default::default()
+            });
+        }
+
+        if let Some(highlight) = highlight {
+            commands.entity(entity).insert(highlight);
+        }
+
+        if let Some(links) = links {
+            commands.entity(entity).insert(links);
+        }
+    });
+
+    commands.spawn_batch(plain_lines);
+}
+
+/// Right-aligns the messages of the local user with `align_own_messages` enabled.
+pub fn message_alignment(user_id: &str, local_user_id: &str, align_own: bool) -> TextAlignment {
+    if align_own && user_id == local_user_id {
+        TextAlignment::Right
+    } else {
+        TextAlignment::Left
+    }
+}
+
+pub fn message_text(
+    value: impl Into<String>,
+    style: &crate::TextStyle,
+    font: Handle<Font>,
+) -> Text {
+    Text::from_section(
+        value,
+        TextStyle {
+            font,
+            font_size: style.font_size,
+            color: style.color,
+        },
+    )
+    .with_alignment(TextAlignment::Left)
+}
+
+/// Spawns a chat line as a child of the UI column or, without one, in the world space.
+pub fn spawn_line(
+    commands: &mut Commands,
+    column: Option<Entity>,
+    text: Text,
+    components: impl Bundle,
+) -> Entity {
+    match column {
+        Some(column) => {
+            let line = commands
+                .spawn((
+                    TextBundle {
+                        text,
+                        ..Default::default()
+                    },
+                    components,
+                ))
+                .id();
+            commands.entity(column).add_child(line);
+            line
+        }
+        None => commands.spawn((world_line(text), components)).id(),
+    }
+}
+
+/// World space chat line, the layout moves it into place.
+///
+/// The line is anchored at its bottom left corner, so the wrapped lines grow up and to the right.
+/// Right-aligned lines are anchored at the bottom right corner, at the right edge of the bounds.
+fn world_line(text: Text) -> Text2dBundle {
+    let text_anchor = match text.alignment {
+        TextAlignment::Right => Anchor::BottomRight,
+        _ => Anchor::BottomLeft,
+    };
+
+    Text2dBundle {
+        text,
+        text_anchor,
+        transform: Transform::from_translation(MESSAGES_ORIGIN.extend(0.0)),
+        ..Default::default()
+    }
+}
+
+/// Section rendering the raw envelope under the formatted message.
+pub fn debug_section(raw: &str, style: &crate::TextStyle, font: Handle<Font>) -> TextSection {
+    TextSection::new(
+        format!("\n[debug] {}", raw),
+        TextStyle {
+            font,
+            font_size: style.font_size * DEBUG_FONT_SCALE,
+            color: dimmed(style.color),
+        },
+    )
+}
+
+pub fn spawn_local_message(
+    commands: &mut Commands,
+    column: Option<Entity>,
+    counter: &mut MessageCounter,
+    mut message: ChatMessage,
+    style: &crate::TextStyle,
+    font: Handle<Font>,
+) {
+    message.index = counter.next();
+
+    let style = crate::TextStyle {
+        color: dimmed(style.color),
+        ..style.clone()
+    };
+
+    spawn_line(
+        commands,
+        column,
+        message_text(message.payload.clone(), &style, font),
+        (message, LocalMessage),
+    );
+}
+
+/// Resources needed to show local notices in the chat.
+#[derive(SystemParam)]
+pub struct LocalMessages<'w, 's> {
+    config: Res<'w, ChatConfig>,
+    clock: Res<'w, Clock>,
+    counter: ResMut<'w, MessageCounter>,
+    fonts: ChatFonts<'w>,
+    message_style: Res<'w, ChatMessageStyle>,
+    columns: Query<'w, 's, Entity, With<MessagesColumn>>,
+}
+
+impl LocalMessages<'_, '_> {
+    /// Shows a dimmed notice that is never published.
+    pub fn show(&mut self, commands: &mut Commands, text: impl Into<String>) {
+        let font = self.fonts.load(&self.message_style);
+
+        spawn_local_message(
+            commands,
+            self.columns.get_single().ok(),
+            &mut self.counter,
+            ChatMessage::local(text, system_time_to_timetoken(self.clock.now())),
+            &self.message_style,
+            font,
+        );
+    }
+
+    /// Shows a system message with the `system_message_style`, it is never published.
+    pub fn show_system(&mut self, commands: &mut Commands, text: impl Into<String>) {
+        let Some(style) = self.config.system_message_style.clone() else {
+            return self.show(commands, text);
+        };
+        let font = self.fonts.load(&style);
+        let mut message = ChatMessage::local(text, system_time_to_timetoken(self.clock.now()));
+        message.index = self.counter.next();
+
+        spawn_line(
+            commands,
+            self.columns.get_single().ok(),
+            message_text(message.payload.clone(), &style, font),
+            (message, LocalMessage),
+        );
+    }
+}
+
+pub fn system_message_handler(
+    mut commands: Commands,
+    mut notices: EventReader<SystemMessage>,
+    mut local_messages: LocalMessages,
+) {
+    notices
+        .iter()
+        .for_each(|SystemMessage(text)| local_messages.show_system(&mut commands, text.clone()));
+}
+
+pub fn clear_chat_handler(
+    mut commands: Commands,
+    mut clears: EventReader<ClearChat>,
+    mut pending: ResMut<PendingMessages>,
+    mut log: ResMut<ChatLog>,
+    messages: Query<Entity, With<ChatMessage>>,
+) {
+    if clears.iter().count() == 0 {
+        return;
+    }
+
+    messages
+        .iter()
+        .for_each(|entity| commands.entity(entity).despawn_recursive());
+    pending.discard();
+    log.clear();
+}
+
+/// Despawns the oldest messages over the `max_messages` limit.
+pub fn max_messages_handler(
+    mut commands: Commands,
+    config: Res<ChatConfig>,
+    messages: Query<(Entity, &ChatMessage)>,
+) {
+    let Some(max_messages) = config.max_messages else {
+        return;
+    };
+
+    let mut messages = messages.iter().collect::<Vec<_>>();
+    if messages.len() <= max_messages {
+        return;
+    }
+
+    messages.sort_by_key(|(_, message)| message.index);
+    messages[..messages.len() - max_messages]
+        .iter()
+        .for_each(|(entity, _)| commands.entity(*entity).despawn_recursive());
+}
+
+pub fn empty_state_handler(
+    mut commands: Commands,
+    config: Res<ChatConfig>,
+    fonts: ChatFonts,
+    message_style: Res<ChatMessageStyle>,
+    chat_transform: Res<ChatTransform>,
+    bounds: Res<ChatBounds>,
+    messages: Query<(), With<ChatMessage>>,
+    placeholders: Query<Entity, With<EmptyState>>,
+    columns: Query<Entity, With<MessagesColumn>>,
+) {
+    let Some(text) = &config.empty_state_text else {
+        return;
+    };
+
+    match (messages.is_empty(), placeholders.is_empty()) {
+        (true, true) => {
+            let style = config
+                .system_message_style
+                .clone()
+                .unwrap_or_else(|| crate::TextStyle {
+                    color: dimmed(message_style.color),
+                    ..message_style.0.clone()
+                });
+            let font = fonts.load(&style);
+            let column = columns.get_single().ok();
+
+            let placeholder = spawn_line(
+                &mut commands,
+                column,
+                message_text(text.clone(), &style, font),
+                EmptyState,
+            );
+
+            // Without messages the layout doesn't move it, so it is placed at the newest row.
+            if column.is_none() {
+                commands
+                    .entity(placeholder)
+                    .insert(Transform::from_translation(message_position(
+                        chat_transform.translation,
+                        bounds.min,
+                        0.0,
+                    )));
+            }
+        }
+        (false, false) => placeholders
+            .iter()
+            .for_each(|entity| commands.entity(entity).despawn_recursive()),
+        _ => {}
+    }
+}
+
+/// Picks a stable color for the user.
+///
+/// The user id is hashed with FNV-1a, which unlike the std hasher
+/// gives the same result in every session.
+pub fn user_color(user_id: &str) -> Color {
+    let hash = user_id
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+    let hue = ((hash % 1_000_003) as f64 * GOLDEN_RATIO_CONJUGATE).fract() * 360.0;
+
+    Color::hsl(hue as f32, USER_COLOR_SATURATION, USER_COLOR_LIGHTNESS)
+}
+
+pub fn dimmed(color: Color) -> Color {
+    let [r, g, b, a] = color.as_rgba_f32();
+
+    Color::rgba(r, g, b, a * LOCAL_MESSAGE_DIMMING)
+}
+
+#[cfg(test)]
+mod should {
+    use bevy::{
+        asset::{AssetServer, FileAssetIo},
+        ecs::event::Events,
+        prelude::App,
+        tasks::{IoTaskPool, TaskPool},
+    };
+
+    use crate::{builder::ChatPluginConfig, plugin::events::SendMessage, ChatPlugin, Keyset};
+
+    use super::*;
+
+    const WINDOW: Option<Duration> = Some(Duration::from_millis(50));
+
+    fn message(payload: &str) -> ChatMessage {
+        ChatMessage::local(payload, "16806024910000000")
+    }
+
+    #[test]
+    fn render_immediately_without_debounce() {
+        let mut pending = PendingMessages::default();
+
+        pending.push(message("first"), Duration::ZERO);
+
+        assert_eq!(pending.take_ready(Duration::ZERO, None).len(), 1);
+    }
+
+    #[test]
+    fn render_messages_within_window_together() {
+        let mut pending = PendingMessages::default();
+
+        pending.push(message("first"), Duration::from_millis(0));
+        pending.push(message("second"), Duration::from_millis(20));
+
+        assert!(pending
+            .take_ready(Duration::from_millis(30), WINDOW)
+            .is_empty());
+
+        pending.push(message("third"), Duration::from_millis(40));
+
+        let batch = pending.take_ready(Duration::from_millis(50), WINDOW);
+        assert_eq!(
+            batch
+                .iter()
+                .map(|message| message.payload.as_str())
+                .collect::<Vec<_>>(),
+            vec!["first", "second", "third"]
+        );
+        assert!(pending
+            .take_ready(Duration::from_millis(200), WINDOW)
+            .is_empty());
+    }
+
+    #[test]
+    fn match_every_local_echo_once() {
+        let mut pending = PendingMessages::default();
+
+        pending.push_echo(message("Hello"), Duration::ZERO);
+        pending.push_echo(message("Hello"), Duration::ZERO);
+
+        assert!(pending.take_echo("Hello"));
+        assert!(pending.take_echo("Hello"));
+        assert!(!pending.take_echo("Hello"));
+        assert_eq!(pending.take_ready(Duration::ZERO, None).len(), 2);
+    }
+
+    #[test]
+    fn align_own_messages_right() {
+        assert_eq!(
+            message_alignment("john", "john", true),
+            TextAlignment::Right
+        );
+        assert_eq!(message_alignment("jane", "john", true), TextAlignment::Left);
+    }
+
+    #[test]
+    fn align_all_messages_left_by_default() {
+        assert_eq!(
+            message_alignment("john", "john", false),
+            TextAlignment::Left
+        );
+        assert_eq!(
+            message_alignment("jane", "john", false),
+            TextAlignment::Left
+        );
+    }
+
+    #[test]
+    fn anchor_right_aligned_world_lines_at_their_right_corner() {
+        let right = world_line(Text::default().with_alignment(TextAlignment::Right));
+        let left = world_line(Text::default().with_alignment(TextAlignment::Left));
+
+        assert!(matches!(right.text_anchor, Anchor::BottomRight));
+        assert!(matches!(left.text_anchor, Anchor::BottomLeft));
+    }
+
+    #[test]
+    fn keep_the_color_of_a_user() {
+        assert_eq!(user_color("john"), user_color("john"));
+    }
+
+    #[test]
+    fn give_users_different_colors() {
+        assert_ne!(user_color("john"), user_color("jane"));
+        assert_ne!(user_color("john"), user_color("bob"));
+        assert_ne!(user_color("jane"), user_color("bob"));
+    }
+
+    #[test]
+    fn despawn_oldest_messages_over_limit() {
+        let config = ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .max_messages(3)
+            .build()
+            .unwrap()
+            .config;
+
+        let mut app = App::new();
+        app.insert_resource(ChatConfig(config))
+            .add_system(max_messages_handler);
+
+        [2, 4, 1, 3].into_iter().for_each(|index| {
+            app.world.spawn(ChatMessage {
+                index,
+                ..message("Hello")
+            });
+        });
+        app.update();
+
+        let mut indices = app
+            .world
+            .query::<&ChatMessage>()
+            .iter(&app.world)
+            .map(|message| message.index)
+            .collect::<Vec<_>>();
+        indices.sort();
+        assert_eq!(indices, vec![2, 3, 4]);
+    }
+
+    fn render_app(max_messages: Option<usize>) -> App {
+        let mut builder = ChatPlugin::builder().keyset(Keyset {
+            publish_key: "pub-c-test",
+            subscribe_key: "sub-c-test",
+        });
+        if let Some(max_messages) = max_messages {
+            builder = builder.max_messages(max_messages);
+        }
+
+        render_app_with(builder.build().unwrap().config)
+    }
+
+    fn render_app_with(config: ChatPluginConfig) -> App {
+        IoTaskPool::init(TaskPool::default);
+
+        let mut app = App::new();
+        app.insert_resource(AssetServer::new(FileAssetIo::new("assets", false)))
+            .insert_resource(ChatMessageStyle(config.message_style.clone()))
+            .insert_resource(MessageFormat(config.message_format.clone()))
+            .insert_resource(PubNubSubscribeResource {
+                channels: config.channels.clone(),
+                user_id: config.username.clone(),
+                ..PubNubSubscribeResource::for_tests()
+            })
+            .insert_resource(ChatConfig(config))
+            .init_resource::<Time>()
+            .init_resource::<Clock>()
+            .init_resource::<PendingMessages>()
+            .init_resource::<MessageCounter>()
+            .add_event::<MentionReceived>()
+            .add_system(render_handler);
+        app
+    }
+
+    fn render_batch(app: &mut App, count: usize) -> Vec<String> {
+        (0..count).for_each(|number| {
+            app.world
+                .resource_mut::<PendingMessages>()
+                .push(message(&format!("message {}", number)), Duration::ZERO);
+        });
+        app.update();
+
+        let mut messages = app
+            .world
+            .query_filtered::<&ChatMessage, With<Text>>()
+            .iter(&app.world)
+            .cloned()
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|message| message.index);
+        messages
+            .into_iter()
+            .map(|message| message.payload)
+            .collect()
+    }
+
+    #[test]
+    fn spawn_whole_batch_in_order() {
+        let mut app = render_app(None);
+
+        assert_eq!(
+            render_batch(&mut app, 20),
+            (0..20)
+                .map(|number| format!("message {}", number))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn spawn_only_newest_messages_of_batch_over_limit() {
+        let mut app = render_app(Some(3));
+
+        assert_eq!(
+            render_batch(&mut app, 20),
+            vec!["message 17", "message 18", "message 19"]
+        );
+    }
+
+    #[test]
+    fn remove_all_messages_on_clear() {
+        let mut app = App::new();
+        app.init_resource::<PendingMessages>()
+            .init_resource::<ChatLog>()
+            .add_event::<ClearChat>()
+            .add_system(clear_chat_handler);
+
+        app.world.spawn(message("Hello"));
+        app.world.spawn((message("Reconnecting..."), LocalMessage));
+        app.world
+            .resource_mut::<PendingMessages>()
+            .push(message("Still pending"), Duration::ZERO);
+        app.update();
+
+        app.world.send_event(ClearChat);
+        app.update();
+
+        assert_eq!(
+            app.world.query::<&ChatMessage>().iter(&app.world).count(),
+            0
+        );
+        assert!(app
+            .world
+            .resource_mut::<PendingMessages>()
+            .take_ready(Duration::ZERO, None)
+            .is_empty());
+    }
+
+    #[test]
+    fn render_own_messages_with_own_style() {
+        let own_color = Color::rgb(0.2, 0.8, 0.2);
+        let mut app = render_app_with(
+            ChatPlugin::builder()
+                .keyset(Keyset {
+                    publish_key: "pub-c-test",
+                    subscribe_key: "sub-c-test",
+                })
+                .username("john")
+                .own_message_style(crate::TextStyle {
+                    color: own_color,
+                    ..Default::default()
+                })
+                .build()
+                .unwrap()
+                .config,
+        );
+
+        ["john", "jane"].into_iter().for_each(|user_id| {
+            app.world.resource_mut::<PendingMessages>().push(
+                ChatMessage {
+                    user_id: user_id.into(),
+                    ..message("Hello")
+                },
+                Duration::ZERO,
+            );
+        });
+        app.update();
+
+        let mut colors = app
+            .world
+            .query::<(&ChatMessage, &Text)>()
+            .iter(&app.world)
+            .map(|(message, text)| (message.user_id.clone(), text.sections[0].style.color))
+            .collect::<Vec<_>>();
+        colors.sort_by(|(a, _), (b, _)| a.cmp(b));
+        assert_eq!(
+            colors,
+            vec![
+                ("jane".to_string(), crate::TextStyle::default().color),
+                ("john".to_string(), own_color),
+            ]
+        );
+    }
+
+    #[test]
+    fn spawn_system_message_with_system_style_without_publishing() {
+        let system_color = Color::GRAY;
+        let mut app = render_app_with(
+            ChatPlugin::builder()
+                .keyset(Keyset {
+                    publish_key: "pub-c-test",
+                    subscribe_key: "sub-c-test",
+                })
+                .system_message_style(crate::TextStyle {
+                    color: system_color,
+                    ..Default::default()
+                })
+                .build()
+                .unwrap()
+                .config,
+        );
+        app.add_event::<SystemMessage>()
+            .add_event::<SendMessage>()
+            .add_system(system_message_handler);
+
+        app.world
+            .send_event(SystemMessage("You joined the channel".into()));
+        app.update();
+
+        let (message, text) = app
+            .world
+            .query_filtered::<(&ChatMessage, &Text), With<LocalMessage>>()
+            .single(&app.world);
+        assert_eq!(message.payload, "You joined the channel");
+        assert_eq!(text.sections[0].style.color, system_color);
+        assert!(app.world.resource::<Events<SendMessage>>().is_empty());
+    }
+
+    #[test]
+    fn place_empty_state_at_newest_row_with_system_style() {
+        let system_color = Color::GRAY;
+        let config = ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .empty_state_text("No messages yet")
+            .system_message_style(crate::TextStyle {
+                color: system_color,
+                ..Default::default()
+            })
+            .build()
+            .unwrap()
+            .config;
+
+        IoTaskPool::init(TaskPool::default);
+
+        let mut app = App::new();
+        app.insert_resource(AssetServer::new(FileAssetIo::new("assets", false)))
+            .insert_resource(ChatMessageStyle(config.message_style.clone()))
+            .insert_resource(ChatTransform(Transform::from_xyz(100.0, 0.0, 1.0)))
+            .insert_resource(ChatBounds(bevy::prelude::Rect::new(
+                30.0, 70.0, 330.0, 270.0,
+            )))
+            .insert_resource(ChatConfig(config))
+            .add_system(empty_state_handler);
+        app.update();
+
+        let (text, transform) = app
+            .world
+            .query_filtered::<(&Text, &Transform), With<EmptyState>>()
+            .single(&app.world);
+        assert_eq!(text.sections[0].style.color, system_color);
+        assert_eq!(
+            transform.translation,
+            bevy::prelude::Vec3::new(130.0, 70.0, 1.0)
+        );
+    }
+}
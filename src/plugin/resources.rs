@@ -1,11 +1,10 @@
-use std::ops::Deref;
+use std::{ops::Deref, time::SystemTime};
 
-use crate::TextStyle;
+use crate::{builder::ChatPluginConfig, TextStyle};
 use bevy::prelude::{Rect, Resource, Transform};
-use pubnub::{
-    transport::{middleware::PubNubMiddleware, reqwest::blocking::TransportReqwest},
-    PubNubClient,
-};
+use pubnub::{transport::middleware::PubNubMiddleware, PubNubClient};
+
+use super::transport::ChatTransport;
 
 #[derive(Debug, Clone, Resource)]
 pub struct InputBoxStyle(pub TextStyle);
@@ -29,11 +28,64 @@ impl Deref for ChatMessageStyle {
     }
 }
 
+/// The PubNub client used by the chat.
+pub type ChatPubNubClient = PubNubClient<PubNubMiddleware<ChatTransport>>;
+
+/// The PubNub client of the chat, to call other PubNub APIs from the app.
+///
+/// The client is replaced when the username changes,
+/// so get it from the resource instead of keeping a clone around.
+///
+/// # Example
+///
+/// ```rust
+/// use bevy::{prelude::Res, tasks::IoTaskPool};
+/// use bevy_pn_chat::PubNubClientResource;
+///
+/// fn announce(pubnub: Res<PubNubClientResource>) {
+///     let pubnub = pubnub.client().clone();
+///
+///     IoTaskPool::get()
+///         .spawn(async move {
+///             let result = pubnub
+///                 .publish_message("Server restarts in 5 minutes")
+///                 .channel("announcements")
+///                 .execute()
+///                 .await;
+///
+///             if let Err(err) = result {
+///                 eprintln!("Announcement not sent: {}", err);
+///             }
+///         })
+///         .detach();
+/// }
+/// ```
 #[derive(Clone, Resource)]
-pub struct PubNubClientResource(pub PubNubClient<PubNubMiddleware<TransportReqwest>>);
+pub struct PubNubClientResource(pub(crate) ChatPubNubClient);
+
+impl PubNubClientResource {
+    /// The PubNub client of the chat.
+    pub fn client(&self) -> &ChatPubNubClient {
+        &self.0
+    }
+}
 
 impl Deref for PubNubClientResource {
-    type Target = PubNubClient<PubNubMiddleware<TransportReqwest>>;
+    type Target = ChatPubNubClient;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Transport of the raw requests, like subscribe.
+///
+/// It shares the connection pool and the configuration with the PubNub client.
+#[derive(Debug, Clone, Resource)]
+pub struct RawTransportResource(pub ChatTransport);
+
+impl Deref for RawTransportResource {
+    type Target = ChatTransport;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -46,18 +98,39 @@ pub struct PubNubSubscribeResource {
     pub tt: String,
     pub tr: String,
     pub subscribe_key: String,
-    pub channel: String,
+    pub channels: Vec<String>,
+    pub channel_groups: Vec<String>,
     pub user_id: String,
+    pub filter: Option<String>,
+
+    /// Presence state of the local user serialized to JSON.
+    pub state: Option<String>,
 }
 
-#[derive(Resource)]
-pub struct ChannelResource(pub String);
+#[cfg(test)]
+impl PubNubSubscribeResource {
+    /// The subscription of `john` to the `lobby` channel, override the fields the test needs.
+    pub fn for_tests() -> Self {
+        Self {
+            tt: "0".into(),
+            tr: "0".into(),
+            subscribe_key: "sub-c-test".into(),
+            channels: vec!["lobby".into()],
+            channel_groups: Vec::new(),
+            user_id: "john".into(),
+            filter: None,
+            state: None,
+        }
+    }
+}
 
-impl Deref for ChannelResource {
-    type Target = String;
+/// The channel messages are published to, `None` if only channel groups are subscribed.
+#[derive(Resource)]
+pub struct ChannelResource(pub Option<String>);
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+impl ChannelResource {
+    pub fn name(&self) -> Option<&str> {
+        self.0.as_deref()
     }
 }
 
@@ -93,3 +166,48 @@ impl Deref for ChatBounds {
         &self.0
     }
 }
+
+#[derive(Debug, Clone, Resource)]
+pub struct ChatConfig(pub ChatPluginConfig);
+
+impl Deref for ChatConfig {
+    type Target = ChatPluginConfig;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct Clock(pub fn() -> SystemTime);
+
+impl Clock {
+    pub fn now(&self) -> SystemTime {
+        (self.0)()
+    }
+}
+
+impl Default for Clock {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn default() -> Self {
+        Self(SystemTime::now)
+    }
+
+    // `SystemTime::now` panics in the browser.
+    #[cfg(target_arch = "wasm32")]
+    fn default() -> Self {
+        Self(|| {
+            std::time::UNIX_EPOCH + std::time::Duration::from_millis(js_sys::Date::now() as u64)
+        })
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct MessageCounter(u64);
+
+impl MessageCounter {
+    pub fn next(&mut self) -> u64 {
+        self.0 += 1;
+        self.0
+    }
+}
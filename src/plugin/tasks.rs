@@ -1,82 +1,836 @@
+use std::{future::Future, time::Duration};
+
 use bevy::{
-    prelude::{AssetServer, Commands, Component, Entity, Query, Res, Transform},
+    ecs::system::SystemParam,
+    prelude::{Commands, Component, Entity, EventWriter, Query, Res, ResMut},
     tasks::{AsyncComputeTaskPool, Task},
-    text::{Text2dBounds, Text2dBundle, TextStyle},
+    time::Time,
 };
 use futures_lite::future;
 
-use crate::error::Result;
+use crate::{builder::ChatPluginConfig, error::Result, BevyPNError};
 
 use super::{
-    messages::{subscribe, ChatMessage, SubscriptionResult},
-    resources::{ChatMessageStyle, MessageFormat, PubNubSubscribeResource},
+    connection::SubscribeStatus,
+    events::{
+        ChatError, MessageReceived, NotifyOnMessage, PresenceChanged, PublishOutcome, TypingChanged,
+    },
+    messages::{ChatMessage, Message, Resubscribe, SeenMessages, SubscriptionResult},
+    presence::{is_presence_channel, presence_changes, spawn_here_now, OnlineUsers, PresenceEvent},
+    reactions::{ReactionSignal, Reactions},
+    render::PendingMessages,
+    resources::{ChatConfig, PubNubSubscribeResource, RawTransportResource},
+    typing::{TypingUsers, TYPING_SIGNAL},
+    word_filter::mask_words,
 };
 
 #[derive(Component)]
-pub struct PublishTask(pub Task<Result<()>>);
+pub struct PublishTask {
+    pub message: String,
+    pub task: Task<Result<()>>,
+}
 
 #[derive(Component)]
-pub struct SubscribeTask(pub Task<Result<SubscriptionResult>>);
+pub struct SubscribeTask {
+    /// The channels the request was sent for.
+    pub channels: Vec<String>,
+    pub task: Task<Result<SubscriptionResult>>,
+}
+
+/// Spawns a network request on the async compute pool.
+///
+/// Native reqwest needs a tokio reactor, which the Bevy task pools don't provide.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn spawn_request<T: Send + 'static>(
+    request: impl Future<Output = T> + Send + 'static,
+) -> Task<T> {
+    AsyncComputeTaskPool::get().spawn(async_compat::Compat::new(request))
+}
+
+/// Spawns a network request on the async compute pool.
+///
+/// In the browser reqwest uses `fetch` and runs on the main thread executor.
+#[cfg(target_arch = "wasm32")]
+pub fn spawn_request<T: 'static>(request: impl Future<Output = T> + 'static) -> Task<T> {
+    AsyncComputeTaskPool::get().spawn(request)
+}
 
 pub fn tasks_handler(
     mut commands: Commands,
-    subscription_info: Res<PubNubSubscribeResource>,
+    mut subscription_info: ResMut<PubNubSubscribeResource>,
     mut publish_tasks: Query<(Entity, &mut PublishTask)>,
     mut subscribe_tasks: Query<(Entity, &mut SubscribeTask)>,
-    asset_server: Res<AssetServer>,
-    message_style: Res<ChatMessageStyle>,
-    message_format: Res<MessageFormat>,
+    time: Res<Time>,
+    config: Res<ChatConfig>,
+    transport: Res<RawTransportResource>,
+    mut inbox: Inbox,
+    mut online_users: ResMut<OnlineUsers>,
+    mut status: SubscribeStatus,
+    mut resubscribe: EventWriter<Resubscribe>,
+    mut publish_outcomes: EventWriter<PublishOutcome>,
+    mut errors: EventWriter<ChatError>,
+    mut presence_changed: EventWriter<PresenceChanged>,
+    mut signal_inbox: SignalInbox,
 ) {
     publish_tasks.iter_mut().for_each(|(entity, mut task)| {
-        future::block_on(future::poll_once(&mut task.0)).map(|res| {
-            res.map_err(|err| log::error!("Error occurred in async publish task: {:?}", err))
-                .ok();
+        future::block_on(future::poll_once(&mut task.task)).map(|res| {
+            let message = task.message.clone();
+
+            publish_outcomes.send(match res {
+                Ok(()) => PublishOutcome::Sent { message },
+                Err(err) => {
+                    log::error!("Error occurred in async publish task: {:?}", err);
+                    errors.send(ChatError::new("publish", &err));
+                    PublishOutcome::Failed {
+                        message,
+                        error: err.to_string(),
+                    }
+                }
+            });
             commands.entity(entity).despawn()
         });
     });
 
     subscribe_tasks.iter_mut().for_each(|(entity, mut task)| {
-        future::block_on(future::poll_once(&mut task.0)).map(|res| {
-            res.map_err(|err| log::error!("Error occurred in async subscribe task: {:?}", err))
-                .map(|result| {
-                    let subscribe_key = subscription_info.subscribe_key.clone();
-                    let channel = subscription_info.channel.clone();
-                    let user_id = subscription_info.user_id.clone();
-                    let tt = result.message_info.tt;
-                    let tr = result.message_info.tr.to_string();
-
-                    let thread_pool = AsyncComputeTaskPool::get();
-                    let task = thread_pool
-                        .spawn(async move { subscribe(subscribe_key, channel, tt, tr, user_id) });
-
-                    commands.spawn(SubscribeTask(task));
-
-                    let font = asset_server.load(message_style.font_path.to_str().unwrap_or(""));
-                    result.messages.iter().for_each(|message| {
-                        commands.spawn((
-                            ChatMessage,
-                            Text2dBundle {
-                                text: bevy::text::Text::from_section(
-                                    message_format
-                                        .clone()
-                                        .replace("{username}", &message.user_id)
-                                        .replace("{message}", &message.payload)
-                                        .replace("{channel}", &message.channel),
-                                    TextStyle {
-                                        font: font.clone(),
-                                        font_size: message_style.font_size,
-                                        color: message_style.color,
-                                    },
-                                )
-                                .with_alignment(bevy::text::TextAlignment::Left),
-                                transform: Transform::from_xyz(30.0, 70.0, 0.0),
-                                ..Default::default()
-                            },
-                        ));
+        // The channel was switched, the new subscription is already on its way.
+        if task.channels != subscription_info.channels {
+            commands.entity(entity).despawn();
+            return;
+        }
+
+        future::block_on(future::poll_once(&mut task.task)).map(|res| {
+            status.report(res.is_ok(), time.elapsed(), config.disconnect_grace);
+
+            res.map_err(|err| {
+                log::error!("Error occurred in async subscribe task: {:?}", err);
+                errors.send(ChatError::new("subscribe", &err));
+            })
+            .map(|result| {
+                subscription_info.tt = result.message_info.tt.clone();
+                subscription_info.tr = result.message_info.tr.to_string();
+                result.malformed.iter().for_each(|err| {
+                    errors.send(ChatError::new("subscribe", err));
+                });
+                status.poll_again(
+                    result.messages.is_empty(),
+                    time.elapsed(),
+                    config.min_poll_delay,
+                    &mut resubscribe,
+                );
+
+                // A long-poll returned without messages only advances the timetoken.
+                if result.messages.is_empty() {
+                    return;
+                }
+
+                let (presence, messages): (Vec<_>, Vec<_>) = result
+                    .messages
+                    .iter()
+                    .partition(|message| is_presence_channel(&message.channel));
+                let (signals, messages): (Vec<_>, Vec<_>) = messages
+                    .into_iter()
+                    .partition(|message| message.is_signal());
+
+                signal_inbox.receive(&signals, &subscription_info.user_id, time.elapsed());
+
+                if !presence.is_empty() {
+                    let before = online_users.clone();
+
+                    presence.iter().for_each(|message| {
+                        PresenceEvent::parse(&message.payload)
+                            .map_err(|err| log::warn!("Invalid presence event: {:?}", err))
+                            .map(|event| online_users.apply(event, config.presence_mode))
+                            .ok();
                     });
-                })
-                .ok();
+                    presence_changed.send_batch(presence_changes(&before, &online_users));
+
+                    if let Some(channel) =
+                        config.channel().filter(|_| online_users.refresh_needed())
+                    {
+                        spawn_here_now(&mut commands, &subscription_info, channel, &transport);
+                    }
+                }
+
+                let (received, undecrypted) = queue_messages(
+                    &mut inbox.pending,
+                    &mut inbox.seen,
+                    &messages,
+                    &config,
+                    &subscription_info.user_id,
+                    time.elapsed(),
+                );
+                inbox.notify.send_batch(notifications(
+                    &received,
+                    &subscription_info.user_id,
+                    config.notify_on_own,
+                ));
+                inbox.received.send_batch(received);
+                undecrypted.iter().for_each(|err| {
+                    errors.send(ChatError::new("decrypt", err));
+                });
+            })
+            .ok();
             commands.entity(entity).despawn();
         });
     });
 }
+
+/// Resources the received chat messages are delivered to.
+#[derive(SystemParam)]
+pub struct Inbox<'w> {
+    pending: ResMut<'w, PendingMessages>,
+    seen: ResMut<'w, SeenMessages>,
+    received: EventWriter<'w, MessageReceived>,
+    notify: EventWriter<'w, NotifyOnMessage>,
+}
+
+/// Resources the received signals are delivered to.
+#[derive(SystemParam)]
+pub struct SignalInbox<'w> {
+    typing: ResMut<'w, TypingUsers>,
+    typing_changed: EventWriter<'w, TypingChanged>,
+    reactions: ResMut<'w, Reactions>,
+}
+
+impl SignalInbox<'_> {
+    /// Applies the typing and reaction signals.
+    ///
+    /// The local user's own typing signals are skipped, their reactions are counted.
+    pub fn receive(&mut self, signals: &[&Message], user_id: &str, now: Duration) {
+        signals.iter().for_each(|signal| {
+            if signal.payload == TYPING_SIGNAL {
+                if signal.publisher != user_id && self.typing.signal(&signal.publisher, now) {
+                    self.typing_changed.send(TypingChanged {
+                        user_id: signal.publisher.clone(),
+                        typing: true,
+                    });
+                }
+            } else if let Some(reaction) = ReactionSignal::parse(&signal.payload) {
+                self.reactions.add(reaction);
+            }
+        });
+    }
+}
+
+/// Queues the messages for rendering and returns the events announcing them,
+/// together with the errors of the messages that couldn't be decrypted.
+///
+/// Messages that were already received are skipped.
+pub fn queue_messages(
+    pending: &mut PendingMessages,
+    seen: &mut SeenMessages,
+    messages: &[&Message],
+    config: &ChatPluginConfig,
+    user_id: &str,
+    now: Duration,
+) -> (Vec<MessageReceived>, Vec<BevyPNError>) {
+    let mut undecrypted = Vec::new();
+    let received = messages
+        .iter()
+        .filter(|message| seen.first_seen(message))
+        .filter(|message| !(config.suppress_own_echo && message.publisher == user_id))
+        .filter_map(|message| match &config.cipher_key {
+            Some(cipher_key) => message
+                .decrypted(cipher_key)
+                .map_err(|err| {
+                    log::error!("Skipping message {}: {}", message.timetoken, err);
+                    undecrypted.push(err);
+                })
+                .ok(),
+            None => Some((*message).clone()),
+        })
+        .map(|message| {
+            let mut chat_message = ChatMessage::from(&message);
+            if !config.word_filter.is_empty() {
+                chat_message.payload = mask_words(&chat_message.payload, &config.word_filter);
+            }
+
+            if config.debug_raw_payloads {
+                log::trace!("Raw message envelope: {}", message.raw);
+                chat_message.raw = Some(message.raw.to_string());
+            }
+
+            // The message is announced, but its local echo is already rendered.
+            let echoed = config.local_echo
+                && chat_message.user_id == user_id
+                && pending.take_echo(&chat_message.payload);
+
+            let event = MessageReceived {
+                user_id: chat_message.user_id.clone(),
+                payload: chat_message.payload.clone(),
+                channel: chat_message.channel.clone(),
+                timetoken: chat_message.timetoken.clone(),
+                meta: config
+                    .structured_messages
+                    .then(|| message.structured().meta)
+                    .filter(|meta| !meta.is_null()),
+            };
+            if !echoed {
+                pending.push(chat_message, now);
+            }
+
+            event
+        })
+        .collect();
+
+    (received, undecrypted)
+}
+
+/// The notifications about the received messages.
+///
+/// Messages of the local user are skipped unless `notify_on_own` is set.
+pub fn notifications(
+    received: &[MessageReceived],
+    user_id: &str,
+    notify_on_own: bool,
+) -> Vec<NotifyOnMessage> {
+    received
+        .iter()
+        .filter(|message| notify_on_own || message.user_id != user_id)
+        .map(|message| NotifyOnMessage {
+            user_id: message.user_id.clone(),
+            payload: message.payload.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod should {
+    use bevy::{
+        ecs::event::{Events, ManualEventReader},
+        prelude::{App, IntoSystemConfig},
+        tasks::{AsyncComputeTaskPool, TaskPool},
+    };
+    use serde_json::{json, Value};
+
+    use crate::{
+        plugin::{
+            connection::{
+                ChatConnected, ConnectionState, PollPacing, SubscribeActivity, SubscribeBackoff,
+            },
+            events::{ConnectionChanged, MentionReceived},
+            messages::SubscriptionInfo,
+            render::render_handler,
+            resources::{ChatMessageStyle, Clock, MessageCounter, MessageFormat},
+            transport::ChatTransport,
+        },
+        BevyPNError,
+    };
+
+    use crate::{
+        builder::ChatPluginConfigBuilder, plugin::crypto::encrypt, ChatPlugin, FilterMode, Keyset,
+    };
+
+    use super::*;
+
+    fn builder() -> ChatPluginConfigBuilder {
+        ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .username("john")
+    }
+
+    fn config(suppress_own_echo: bool) -> ChatPluginConfig {
+        builder()
+            .suppress_own_echo(suppress_own_echo)
+            .build()
+            .unwrap()
+            .config
+    }
+
+    fn received(publisher: &str, text: &str) -> Message {
+        received_payload(publisher, json!(text))
+    }
+
+    fn received_payload(publisher: &str, payload: Value) -> Message {
+        serde_json::from_value(json!({
+            "c": "lobby",
+            "d": payload,
+            "i": publisher,
+            "p": { "t": "16806024912893744", "r": 1 }
+        }))
+        .unwrap()
+    }
+
+    fn queued(config: ChatPluginConfig) -> Vec<String> {
+        let own = received("john", "Hello");
+        let other = received("jane", "Hi");
+        let mut pending = PendingMessages::default();
+
+        queue_messages(
+            &mut pending,
+            &mut SeenMessages::default(),
+            &[&own, &other],
+            &config,
+            "john",
+            Duration::ZERO,
+        );
+
+        pending
+            .take_ready(Duration::ZERO, None)
+            .into_iter()
+            .map(|message| message.payload)
+            .collect()
+    }
+
+    #[test]
+    fn skip_own_echo_when_suppressed() {
+        assert_eq!(queued(config(true)), vec!["Hi"]);
+    }
+
+    #[test]
+    fn keep_own_echo_by_default() {
+        assert_eq!(queued(config(false)), vec!["Hello", "Hi"]);
+    }
+
+    #[test]
+    fn render_server_copy_of_local_echo_once() {
+        let config = builder().local_echo(true).build().unwrap().config;
+        let mut pending = PendingMessages::default();
+        pending.push_echo(
+            ChatMessage {
+                user_id: "john".into(),
+                ..ChatMessage::local("Hello", "16806024910000000")
+            },
+            Duration::ZERO,
+        );
+
+        let (received, _) = queue_messages(
+            &mut pending,
+            &mut SeenMessages::default(),
+            &[&received("john", "Hello"), &received("jane", "Hello")],
+            &config,
+            "john",
+            Duration::ZERO,
+        );
+
+        assert_eq!(received.len(), 2);
+        assert_eq!(
+            pending
+                .take_ready(Duration::ZERO, None)
+                .into_iter()
+                .map(|message| message.user_id)
+                .collect::<Vec<_>>(),
+            vec!["john", "jane"]
+        );
+        assert!(!pending.take_echo("Hello"));
+    }
+
+    #[test]
+    fn skip_already_received_messages() {
+        let config = config(false);
+        let message = received("jane", "Hi");
+        let mut pending = PendingMessages::default();
+        let mut seen = SeenMessages::default();
+
+        let (first, _) = queue_messages(
+            &mut pending,
+            &mut seen,
+            &[&message, &message],
+            &config,
+            "john",
+            Duration::ZERO,
+        );
+        let (overlapping, _) = queue_messages(
+            &mut pending,
+            &mut seen,
+            &[&message],
+            &config,
+            "john",
+            Duration::ZERO,
+        );
+
+        assert_eq!(first.len(), 1);
+        assert!(overlapping.is_empty());
+        assert_eq!(pending.take_ready(Duration::ZERO, None).len(), 1);
+    }
+
+    #[test]
+    fn mask_filtered_words_in_received_messages() {
+        let config = builder()
+            .word_filter(vec!["hello".into()])
+            .word_filter_mode(FilterMode::Block)
+            .build()
+            .unwrap()
+            .config;
+
+        assert_eq!(queued(config), vec!["*****", "Hi"]);
+    }
+
+    #[test]
+    fn decrypt_messages_with_cipher_key() {
+        let config = builder().cipher_key("enigma").build().unwrap().config;
+        let encrypted = received_payload("jane", json!(encrypt("enigma", "\"Hello\"")));
+        let garbage = received("jane", "not encrypted");
+        let mut pending = PendingMessages::default();
+
+        let (_, undecrypted) = queue_messages(
+            &mut pending,
+            &mut SeenMessages::default(),
+            &[&encrypted, &garbage],
+            &config,
+            "john",
+            Duration::ZERO,
+        );
+
+        assert_eq!(
+            pending
+                .take_ready(Duration::ZERO, None)
+                .into_iter()
+                .map(|message| message.payload)
+                .collect::<Vec<_>>(),
+            vec!["Hello"]
+        );
+        assert!(matches!(
+            undecrypted.as_slice(),
+            [BevyPNError::Decrypt { .. }]
+        ));
+    }
+
+    fn message_received(user_id: &str, payload: &str) -> MessageReceived {
+        MessageReceived {
+            user_id: user_id.into(),
+            payload: payload.into(),
+            channel: "lobby".into(),
+            timetoken: "16806024912893744".into(),
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn notify_only_about_messages_of_others() {
+        assert_eq!(
+            notifications(
+                &[
+                    message_received("john", "Hello"),
+                    message_received("jane", "Hi")
+                ],
+                "john",
+                false
+            ),
+            vec![NotifyOnMessage {
+                user_id: "jane".into(),
+                payload: "Hi".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn notify_about_own_messages_when_enabled() {
+        assert_eq!(
+            notifications(
+                &[
+                    message_received("john", "Hello"),
+                    message_received("jane", "Hi")
+                ],
+                "john",
+                true
+            )
+            .into_iter()
+            .map(|notification| notification.user_id)
+            .collect::<Vec<_>>(),
+            vec!["john", "jane"]
+        );
+    }
+
+    fn app() -> App {
+        let config = config(false);
+
+        let mut app = App::new();
+        app.insert_resource(PubNubSubscribeResource {
+            channels: config.channels.clone(),
+            user_id: config.username.clone(),
+            ..PubNubSubscribeResource::for_tests()
+        })
+        .insert_resource(RawTransportResource(ChatTransport::new(&config).unwrap()))
+        .insert_resource(ChatConfig(config))
+        .insert_resource(OnlineUsers::default())
+        .init_resource::<Time>()
+        .init_resource::<PendingMessages>()
+        .init_resource::<SeenMessages>()
+        .init_resource::<SubscribeActivity>()
+        .init_resource::<SubscribeBackoff>()
+        .init_resource::<PollPacing>()
+        .init_resource::<ChatConnected>()
+        .init_resource::<ConnectionState>()
+        .add_event::<Resubscribe>()
+        .add_event::<MessageReceived>()
+        .add_event::<NotifyOnMessage>()
+        .add_event::<PublishOutcome>()
+        .add_event::<ChatError>()
+        .add_event::<ConnectionChanged>()
+        .add_event::<PresenceChanged>()
+        .add_event::<TypingChanged>()
+        .init_resource::<TypingUsers>()
+        .init_resource::<Reactions>()
+        .add_system(tasks_handler);
+        app
+    }
+
+    /// Spawns the subscribe task for the currently subscribed channels.
+    fn spawn_subscribe_task(app: &mut App, task: Task<Result<SubscriptionResult>>) {
+        let channels = app
+            .world
+            .resource::<PubNubSubscribeResource>()
+            .channels
+            .clone();
+        app.world.spawn(SubscribeTask { channels, task });
+    }
+
+    /// Updates the app until the spawned tasks finish and collects the sent events.
+    fn collect_events<E: bevy::ecs::event::Event + Clone>(app: &mut App) -> Vec<E> {
+        let mut reader = ManualEventReader::<E>::default();
+        let mut collected = Vec::new();
+
+        for _ in 0..100 {
+            app.update();
+            collected.extend(reader.iter(app.world.resource::<Events<E>>()).cloned());
+
+            if !collected.is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        collected
+    }
+
+    #[test]
+    fn emit_received_message_event() {
+        let mut app = app();
+        let result = SubscriptionResult {
+            message_info: SubscriptionInfo {
+                tt: "16806024912893745".into(),
+                tr: 1,
+            },
+            messages: vec![received("jane", "Hello")],
+            malformed: Vec::new(),
+        };
+        let task = AsyncComputeTaskPool::init(TaskPool::default).spawn(async move { Ok(result) });
+        spawn_subscribe_task(&mut app, task);
+
+        assert_eq!(
+            collect_events::<MessageReceived>(&mut app),
+            vec![MessageReceived {
+                user_id: "jane".into(),
+                payload: "Hello".into(),
+                channel: "lobby".into(),
+                timetoken: "16806024912893744".into(),
+                meta: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn render_only_well_formed_messages_of_batch() {
+        let config = config(false);
+        let mut app = app();
+        app.insert_resource(ChatMessageStyle(config.message_style.clone()))
+            .insert_resource(MessageFormat(config.message_format.clone()))
+            .init_resource::<Clock>()
+            .init_resource::<MessageCounter>()
+            .add_event::<MentionReceived>()
+            .add_system(render_handler.after(tasks_handler));
+
+        let body = r#"{
+            "t": { "t": "16806024912893745", "r": 1 },
+            "m": [
+                { "c": "lobby", "d": "Hello", "i": "jane", "p": { "t": "16806024912893744", "r": 1 } },
+                { "c": 5, "d": "Broken", "i": ["jane"] }
+            ]
+        }"#;
+        let result = serde_json::from_str::<SubscriptionResult>(body).unwrap();
+        let task = AsyncComputeTaskPool::init(TaskPool::default).spawn(async move { Ok(result) });
+        spawn_subscribe_task(&mut app, task);
+
+        let errors = collect_events::<ChatError>(&mut app);
+
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.context.as_str())
+                .collect::<Vec<_>>(),
+            vec!["subscribe: malformed response"]
+        );
+        assert_eq!(
+            app.world
+                .query::<&ChatMessage>()
+                .iter(&app.world)
+                .map(|message| message.payload.clone())
+                .collect::<Vec<_>>(),
+            vec!["Hello"]
+        );
+    }
+
+    #[test]
+    fn notify_about_received_message() {
+        let mut app = app();
+        let result = SubscriptionResult {
+            message_info: SubscriptionInfo {
+                tt: "16806024912893745".into(),
+                tr: 1,
+            },
+            messages: vec![received("john", "Hello"), received("jane", "Hi")],
+            malformed: Vec::new(),
+        };
+        let task = AsyncComputeTaskPool::init(TaskPool::default).spawn(async move { Ok(result) });
+        spawn_subscribe_task(&mut app, task);
+
+        assert_eq!(
+            collect_events::<NotifyOnMessage>(&mut app),
+            vec![NotifyOnMessage {
+                user_id: "jane".into(),
+                payload: "Hi".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn advance_timetoken_on_empty_result() {
+        let mut app = app();
+        let result = SubscriptionResult {
+            message_info: SubscriptionInfo {
+                tt: "16806024912893746".into(),
+                tr: 3,
+            },
+            messages: vec![],
+            malformed: Vec::new(),
+        };
+        let task = AsyncComputeTaskPool::init(TaskPool::default).spawn(async move { Ok(result) });
+        spawn_subscribe_task(&mut app, task);
+
+        assert_eq!(collect_events::<Resubscribe>(&mut app).len(), 1);
+
+        let subscription_info = app.world.resource::<PubNubSubscribeResource>();
+        assert_eq!(subscription_info.tt, "16806024912893746");
+        assert_eq!(subscription_info.tr, "3");
+        assert!(app.world.resource::<Events<MessageReceived>>().is_empty());
+        assert!(app
+            .world
+            .resource_mut::<PendingMessages>()
+            .take_ready(Duration::ZERO, None)
+            .is_empty());
+        assert_eq!(
+            app.world.query::<&ChatMessage>().iter(&app.world).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn count_received_reactions() {
+        let mut app = app();
+        let reaction = |publisher: &str| {
+            let mut signal = received_payload(
+                publisher,
+                json!({ "reaction": "👍", "to": "16806024912893700" }),
+            );
+            signal.message_type = Some(1);
+            signal
+        };
+        let result = SubscriptionResult {
+            message_info: SubscriptionInfo {
+                tt: "16806024912893745".into(),
+                tr: 1,
+            },
+            messages: vec![reaction("jane"), reaction("john")],
+            malformed: Vec::new(),
+        };
+        let task = AsyncComputeTaskPool::init(TaskPool::default).spawn(async move { Ok(result) });
+        spawn_subscribe_task(&mut app, task);
+
+        collect_events::<Resubscribe>(&mut app);
+
+        assert_eq!(
+            app.world
+                .resource::<Reactions>()
+                .of("16806024912893700")
+                .and_then(|counts| counts.get("👍")),
+            Some(&2)
+        );
+        assert!(app.world.resource::<Events<MessageReceived>>().is_empty());
+    }
+
+    #[test]
+    fn ignore_result_for_switched_channel() {
+        let mut app = app();
+        let result = SubscriptionResult {
+            message_info: SubscriptionInfo {
+                tt: "16806024912893745".into(),
+                tr: 1,
+            },
+            messages: vec![received("jane", "Hello")],
+            malformed: Vec::new(),
+        };
+        let task = AsyncComputeTaskPool::init(TaskPool::default).spawn(async move { Ok(result) });
+        spawn_subscribe_task(&mut app, task);
+        app.world.resource_mut::<PubNubSubscribeResource>().channels = vec!["support".into()];
+
+        assert!(collect_events::<MessageReceived>(&mut app).is_empty());
+        assert_eq!(app.world.resource::<PubNubSubscribeResource>().tt, "0");
+        assert_eq!(
+            app.world.query::<&SubscribeTask>().iter(&app.world).count(),
+            0
+        );
+    }
+
+    fn spawn_publish_task(app: &mut App, result: Result<()>) {
+        let task = AsyncComputeTaskPool::init(TaskPool::default).spawn(async move { result });
+        app.world.spawn(PublishTask {
+            message: "Hello".into(),
+            task,
+        });
+    }
+
+    #[test]
+    fn report_sent_message() {
+        let mut app = app();
+        spawn_publish_task(&mut app, Ok(()));
+
+        assert_eq!(
+            collect_events::<PublishOutcome>(&mut app),
+            vec![PublishOutcome::Sent {
+                message: "Hello".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn report_failed_message() {
+        let mut app = app();
+        spawn_publish_task(
+            &mut app,
+            Err(BevyPNError::EmptyBody {
+                on: "Publish".into(),
+            }),
+        );
+
+        assert_eq!(
+            collect_events::<PublishOutcome>(&mut app),
+            vec![PublishOutcome::Failed {
+                message: "Hello".into(),
+                error: "Empty body received on Publish!".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn emit_error_when_subscribe_fails() {
+        let mut app = app();
+        let task = AsyncComputeTaskPool::init(TaskPool::default).spawn(async move {
+            Err(BevyPNError::EmptyBody {
+                on: "Subscribe".into(),
+            })
+        });
+        spawn_subscribe_task(&mut app, task);
+
+        assert_eq!(
+            collect_events::<ChatError>(&mut app),
+            vec![ChatError {
+                context: "subscribe: empty response".into(),
+                message: "Empty body received on Subscribe!".into(),
+            }]
+        );
+    }
+}
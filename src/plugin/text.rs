@@ -1,16 +1,671 @@
 use bevy::{
-    prelude::{Component, Resource, Transform},
-    text::{Text, Text2dBundle, TextAlignment, TextStyle},
+    prelude::{
+        BuildChildren, Changed, Children, Color, Commands, Component, DetectChanges, Entity, Query,
+        Rect, Res, ResMut, Resource, Transform, Visibility, With, Without,
+    },
+    sprite::{Anchor, Sprite, SpriteBundle},
+    text::{PositionedGlyph, Text, TextLayoutInfo, TextSection, TextStyle},
 };
 
-#[derive(Component, Default)]
+use super::{render::dimmed, resources::ChatConfig};
+
+/// Character rendered at the cursor position.
+const CARET: &str = "|";
+
+/// Background color of the selected text.
+const SELECTION_COLOR: Color = Color::rgba(0.3, 0.5, 1.0, 0.5);
+
+/// Index of the text section holding the selected text.
+///
+/// The input is rendered as: before, caret, selection, caret, after.
+/// Only one of the caret sections is non-empty.
+const SELECTION_SECTION: usize = 2;
+
+/// Text typed by the user.
+///
+/// The cursor is a character index into the value.
+/// The selection is the character index where the selection is anchored,
+/// it spans from the anchor to the cursor.
+/// The placeholder is only rendered, it is never part of the value.
+#[derive(Component, Debug, Default)]
 pub struct InputBox {
+    pub value: String,
     pub cursor: usize,
     pub selection: Option<usize>,
+    pub placeholder: Option<String>,
 }
 
 impl InputBox {
     pub fn new() -> Self {
         Self::default()
     }
+
+    pub fn set_value(&mut self, value: impl Into<String>) {
+        self.value = value.into();
+        self.cursor = self.value.chars().count();
+        self.selection = None;
+    }
+
+    /// Takes the typed text and clears the input.
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        self.selection = None;
+        std::mem::take(&mut self.value)
+    }
+
+    /// The selected `(start, end)` character range, if anything is selected.
+    pub fn selection_range(&self) -> Option<(usize, usize)> {
+        self.selection
+            .filter(|anchor| *anchor != self.cursor)
+            .map(|anchor| (anchor.min(self.cursor), anchor.max(self.cursor)))
+    }
+
+    pub fn selected_text(&self) -> Option<&str> {
+        self.selection_range()
+            .map(|(start, end)| &self.value[self.byte_index(start)..self.byte_index(end)])
+    }
+
+    /// Inserts the character at the cursor, replacing the selection.
+    pub fn insert(&mut self, character: char) {
+        self.delete_selection();
+        self.value.insert(self.byte_index(self.cursor), character);
+        self.cursor += 1;
+    }
+
+    /// Removes the selection or the character before the cursor.
+    pub fn backspace(&mut self) {
+        if self.delete_selection() || self.cursor == 0 {
+            return;
+        }
+
+        self.cursor -= 1;
+        self.value.remove(self.byte_index(self.cursor));
+    }
+
+    /// Moves the cursor left, collapsing the selection to its start.
+    pub fn move_left(&mut self) {
+        self.cursor = match self.selection_range() {
+            Some((start, _)) => start,
+            None => self.cursor.saturating_sub(1),
+        };
+        self.selection = None;
+    }
+
+    /// Moves the cursor right, collapsing the selection to its end.
+    pub fn move_right(&mut self) {
+        self.cursor = match self.selection_range() {
+            Some((_, end)) => end,
+            None => (self.cursor + 1).min(self.len()),
+        };
+        self.selection = None;
+    }
+
+    /// Moves the cursor to the start of the previous word.
+    pub fn move_word_left(&mut self) {
+        self.cursor = previous_word_boundary(&self.value, self.cursor);
+        self.selection = None;
+    }
+
+    /// Moves the cursor to the end of the next word.
+    pub fn move_word_right(&mut self) {
+        self.cursor = next_word_boundary(&self.value, self.cursor);
+        self.selection = None;
+    }
+
+    /// Removes the selection or the word before the cursor.
+    pub fn delete_word(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+
+        let start = previous_word_boundary(&self.value, self.cursor);
+        self.value
+            .replace_range(self.byte_index(start)..self.byte_index(self.cursor), "");
+        self.cursor = start;
+    }
+
+    pub fn select_left(&mut self) {
+        self.selection.get_or_insert(self.cursor);
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn select_right(&mut self) {
+        self.selection.get_or_insert(self.cursor);
+        self.cursor = (self.cursor + 1).min(self.len());
+    }
+
+    /// Moves the cursor to the start of the current line.
+    pub fn move_home(&mut self) {
+        self.cursor = line_start(&self.value, self.cursor);
+        self.selection = None;
+    }
+
+    /// Moves the cursor to the end of the current line.
+    pub fn move_end(&mut self) {
+        self.cursor = line_end(&self.value, self.cursor);
+        self.selection = None;
+    }
+
+    pub fn select_home(&mut self) {
+        self.selection.get_or_insert(self.cursor);
+        self.cursor = line_start(&self.value, self.cursor);
+    }
+
+    pub fn select_end(&mut self) {
+        self.selection.get_or_insert(self.cursor);
+        self.cursor = line_end(&self.value, self.cursor);
+    }
+
+    /// Removes the selected text, returns `true` if anything was selected.
+    pub fn delete_selection(&mut self) -> bool {
+        let range = self.selection_range();
+        self.selection = None;
+
+        range
+            .map(|(start, end)| {
+                self.value
+                    .replace_range(self.byte_index(start)..self.byte_index(end), "");
+                self.cursor = start;
+            })
+            .is_some()
+    }
+
+    fn len(&self) -> usize {
+        self.value.chars().count()
+    }
+
+    fn byte_index(&self, cursor: usize) -> usize {
+        self.value
+            .char_indices()
+            .nth(cursor)
+            .map(|(index, _)| index)
+            .unwrap_or(self.value.len())
+    }
+}
+
+/// Character index of the start of the line the cursor is on.
+pub fn line_start(value: &str, cursor: usize) -> usize {
+    value
+        .chars()
+        .take(cursor)
+        .collect::<Vec<_>>()
+        .iter()
+        .rposition(|character| *character == '\n')
+        .map_or(0, |index| index + 1)
+}
+
+/// Character index of the end of the line the cursor is on.
+pub fn line_end(value: &str, cursor: usize) -> usize {
+    value
+        .chars()
+        .skip(cursor)
+        .position(|character| character == '\n')
+        .map_or_else(|| value.chars().count(), |index| cursor + index)
+}
+
+/// Character index of the start of the word before the cursor.
+///
+/// Words are delimited by whitespace, so punctuation belongs to the word.
+pub fn previous_word_boundary(value: &str, cursor: usize) -> usize {
+    let characters = value.chars().take(cursor).collect::<Vec<_>>();
+
+    let word_end = characters
+        .iter()
+        .rposition(|character| !character.is_whitespace())
+        .map_or(0, |index| index + 1);
+
+    characters[..word_end]
+        .iter()
+        .rposition(|character| character.is_whitespace())
+        .map_or(0, |index| index + 1)
+}
+
+/// Character index of the end of the word after the cursor.
+///
+/// Words are delimited by whitespace, so punctuation belongs to the word.
+pub fn next_word_boundary(value: &str, cursor: usize) -> usize {
+    let mut characters = value.chars().enumerate().skip(cursor).peekable();
+
+    while characters
+        .next_if(|(_, character)| character.is_whitespace())
+        .is_some()
+    {}
+    while characters
+        .next_if(|(_, character)| !character.is_whitespace())
+        .is_some()
+    {}
+
+    characters
+        .next()
+        .map_or_else(|| value.chars().count(), |(index, _)| index)
+}
+
+/// Marks the sprite drawn behind the selected text.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SelectionHighlight;
+
+/// Renders the input value with the caret at the cursor position.
+pub fn input_render_handler(mut input: Query<(&InputBox, &mut Text), Changed<InputBox>>) {
+    input.iter_mut().for_each(|(input_box, mut text)| {
+        // The style of the first section is the input style, the placeholder is only appended.
+        let style = text.sections[0].style.clone();
+        let (start, end) = input_box
+            .selection_range()
+            .unwrap_or((input_box.cursor, input_box.cursor));
+        let (start_index, end_index) = (input_box.byte_index(start), input_box.byte_index(end));
+        let caret_at_start = input_box.cursor == start;
+
+        let value = &input_box.value;
+        text.sections = [
+            &value[..start_index],
+            if caret_at_start { CARET } else { "" },
+            &value[start_index..end_index],
+            if caret_at_start { "" } else { CARET },
+            &value[end_index..],
+        ]
+        .into_iter()
+        .map(|section| TextSection::new(section, style.clone()))
+        .collect();
+
+        if let Some(placeholder) = input_box.placeholder.as_ref().filter(|_| value.is_empty()) {
+            text.sections.push(TextSection::new(
+                placeholder,
+                TextStyle {
+                    color: dimmed(style.color),
+                    ..style
+                },
+            ));
+        }
+    });
+}
+
+/// Draws a highlighted rectangle behind the selected text of the world space input box.
+pub fn selection_highlight_handler(
+    mut commands: Commands,
+    input: Query<
+        (
+            Entity,
+            &InputBox,
+            &TextLayoutInfo,
+            &Anchor,
+            Option<&Children>,
+        ),
+        Without<SelectionHighlight>,
+    >,
+    mut highlights: Query<(&mut Transform, &mut Sprite, &mut Visibility), With<SelectionHighlight>>,
+) {
+    input
+        .iter()
+        .for_each(|(entity, input_box, layout, anchor, children)| {
+            let highlight = children.and_then(|children| {
+                children
+                    .iter()
+                    .find(|child| highlights.contains(**child))
+                    .copied()
+            });
+
+            let Some(highlight) = highlight else {
+                commands.entity(entity).with_children(|parent| {
+                    parent.spawn((
+                        SpriteBundle {
+                            sprite: Sprite {
+                                color: SELECTION_COLOR,
+                                ..Default::default()
+                            },
+                            visibility: Visibility::Hidden,
+                            ..Default::default()
+                        },
+                        SelectionHighlight,
+                    ));
+                });
+                return;
+            };
+
+            let Ok((mut transform, mut sprite, mut visibility)) = highlights.get_mut(highlight)
+            else {
+                return;
+            };
+
+            match input_box
+                .selection_range()
+                .and_then(|_| section_rect(&layout.glyphs, SELECTION_SECTION))
+            {
+                Some(rect) => {
+                    // Glyphs are positioned from the bottom left corner of the text block.
+                    let offset = layout.size * -(anchor.as_vec() + 0.5);
+
+                    transform.translation = (offset + rect.center()).extend(-0.1);
+                    sprite.custom_size = Some(rect.size());
+                    *visibility = Visibility::Inherited;
+                }
+                None => *visibility = Visibility::Hidden,
+            }
+        });
+}
+
+/// The rectangle covering all glyphs of the given text section.
+fn section_rect(glyphs: &[PositionedGlyph], section: usize) -> Option<Rect> {
+    glyphs
+        .iter()
+        .filter(|glyph| glyph.section_index == section)
+        .map(|glyph| Rect::from_center_size(glyph.position, glyph.size))
+        .reduce(|rect, glyph| rect.union(glyph))
+}
+
+/// Input typed but not sent yet.
+///
+/// It outlives the input box entity, so the text is not lost
+/// when the chat UI is torn down and spawned again.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct InputDraft(pub String);
+
+pub fn draft_handler(
+    config: Res<ChatConfig>,
+    mut draft: ResMut<InputDraft>,
+    mut input: Query<&mut InputBox>,
+) {
+    if !config.preserve_draft {
+        return;
+    }
+
+    input.iter_mut().for_each(|mut input_box| {
+        if input_box.is_added() {
+            input_box.set_value(draft.0.clone());
+        } else if input_box.is_changed() && input_box.value != draft.0 {
+            draft.0 = input_box.value.clone();
+        }
+    });
+}
+
+#[cfg(test)]
+mod should {
+    use bevy::prelude::App;
+
+    use crate::{ChatPlugin, Keyset};
+
+    use super::*;
+
+    use test_case::test_case;
+
+    fn app() -> App {
+        let config = ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .preserve_draft(true)
+            .build()
+            .unwrap()
+            .config;
+
+        let mut app = App::new();
+        app.insert_resource(ChatConfig(config))
+            .init_resource::<InputDraft>()
+            .add_system(draft_handler);
+        app
+    }
+
+    #[test]
+    fn restore_draft_in_respawned_input_box() {
+        let mut app = app();
+
+        let input = app.world.spawn(InputBox::new()).id();
+        app.update();
+
+        app.world
+            .get_mut::<InputBox>(input)
+            .unwrap()
+            .set_value("Hello");
+        app.update();
+
+        app.world.despawn(input);
+        app.world.spawn(InputBox::new());
+        app.update();
+
+        let mut inputs = app.world.query::<&InputBox>();
+        let input_box = inputs.single(&app.world);
+        assert_eq!(input_box.value, "Hello");
+        assert_eq!(input_box.cursor, 5);
+    }
+
+    fn input(value: &str, cursor: usize) -> InputBox {
+        InputBox {
+            value: value.into(),
+            cursor,
+            selection: None,
+            ..InputBox::new()
+        }
+    }
+
+    #[test]
+    fn insert_at_cursor() {
+        let mut input_box = input("Hllo", 1);
+
+        input_box.insert('e');
+
+        assert_eq!(input_box.value, "Hello");
+        assert_eq!(input_box.cursor, 2);
+    }
+
+    #[test]
+    fn insert_after_multibyte_characters() {
+        let mut input_box = input("zżć", 2);
+
+        input_box.insert('ó');
+
+        assert_eq!(input_box.value, "zżóć");
+    }
+
+    #[test]
+    fn delete_before_cursor() {
+        let mut input_box = input("Helllo", 4);
+
+        input_box.backspace();
+
+        assert_eq!(input_box.value, "Hello");
+        assert_eq!(input_box.cursor, 3);
+    }
+
+    #[test]
+    fn not_delete_at_start() {
+        let mut input_box = input("Hello", 0);
+
+        input_box.backspace();
+
+        assert_eq!(input_box.value, "Hello");
+        assert_eq!(input_box.cursor, 0);
+    }
+
+    #[test]
+    fn keep_cursor_within_value() {
+        let mut input_box = input("Hi", 0);
+
+        input_box.move_left();
+        assert_eq!(input_box.cursor, 0);
+
+        (0..5).for_each(|_| input_box.move_right());
+        assert_eq!(input_box.cursor, 2);
+    }
+
+    fn selected(value: &str, anchor: usize, cursor: usize) -> InputBox {
+        InputBox {
+            value: value.into(),
+            cursor,
+            selection: Some(anchor),
+            ..InputBox::new()
+        }
+    }
+
+    #[test_case(selected("Hello world", 5, 11) => "Hello" ; "selected forwards")]
+    #[test_case(selected("Hello world", 11, 5) => "Hello" ; "selected backwards")]
+    #[test_case(selected("Hello world", 0, 6) => "world" ; "selected from the start")]
+    #[test_case(selected("Hello world", 3, 3) => "Helo world" ; "empty selection")]
+    fn delete_selection_on_backspace(mut input_box: InputBox) -> String {
+        input_box.backspace();
+        input_box.value
+    }
+
+    #[test]
+    fn replace_selection_on_insert() {
+        let mut input_box = selected("Hello world", 6, 11);
+
+        input_box.insert('!');
+
+        assert_eq!(input_box.value, "Hello !");
+        assert_eq!(input_box.cursor, 7);
+        assert_eq!(input_box.selection_range(), None);
+    }
+
+    #[test]
+    fn extend_selection_from_anchor() {
+        let mut input_box = input("Hello", 2);
+
+        input_box.select_right();
+        input_box.select_right();
+        assert_eq!(input_box.selection_range(), Some((2, 4)));
+
+        input_box.select_left();
+        input_box.select_left();
+        input_box.select_left();
+        assert_eq!(input_box.selection_range(), Some((1, 2)));
+    }
+
+    #[test]
+    fn collapse_selection_on_move() {
+        let mut input_box = selected("Hello", 1, 4);
+
+        input_box.move_left();
+
+        assert_eq!(input_box.cursor, 1);
+        assert_eq!(input_box.selection_range(), None);
+    }
+
+    #[test_case("Hello world", 11 => 6 ; "from the end")]
+    #[test_case("Hello world", 8 => 6 ; "from inside a word")]
+    #[test_case("Hello   world", 8 => 0 ; "over multiple spaces")]
+    #[test_case("Hi, there!", 10 => 4 ; "with punctuation")]
+    #[test_case("Hello", 0 => 0 ; "from the start")]
+    fn find_previous_word_boundary(value: &str, cursor: usize) -> usize {
+        previous_word_boundary(value, cursor)
+    }
+
+    #[test_case("Hello world", 0 => 5 ; "from the start")]
+    #[test_case("Hello world", 5 => 11 ; "from the end of a word")]
+    #[test_case("Hello   world", 5 => 13 ; "over multiple spaces")]
+    #[test_case("Hi, there!", 0 => 3 ; "with punctuation")]
+    #[test_case("Hello", 5 => 5 ; "from the end")]
+    fn find_next_word_boundary(value: &str, cursor: usize) -> usize {
+        next_word_boundary(value, cursor)
+    }
+
+    #[test]
+    fn delete_previous_word() {
+        let mut input_box = input("Hello big  world", 11);
+
+        input_box.delete_word();
+
+        assert_eq!(input_box.value, "Hello world");
+        assert_eq!(input_box.cursor, 6);
+    }
+
+    #[test]
+    fn move_to_start_and_end() {
+        let mut input_box = input("zażółć", 3);
+
+        input_box.move_end();
+        assert_eq!(input_box.cursor, 6);
+
+        input_box.move_home();
+        assert_eq!(input_box.cursor, 0);
+    }
+
+    #[test_case("first\nsecond", 9 => (6, 12) ; "on the last line")]
+    #[test_case("first\nsecond", 2 => (0, 5) ; "on the first line")]
+    #[test_case("first\nsecond", 6 => (6, 12) ; "at the line start")]
+    #[test_case("one\n\nthree", 4 => (4, 4) ; "on an empty line")]
+    fn move_within_current_line(value: &str, cursor: usize) -> (usize, usize) {
+        let mut input_box = input(value, cursor);
+        input_box.move_home();
+        let home = input_box.cursor;
+        input_box.move_end();
+
+        (home, input_box.cursor)
+    }
+
+    #[test]
+    fn select_to_start_and_end() {
+        let mut input_box = input("Hello world", 6);
+
+        input_box.select_end();
+        assert_eq!(input_box.selected_text(), Some("world"));
+
+        input_box.select_home();
+        assert_eq!(input_box.cursor, 0);
+        assert_eq!(input_box.selected_text(), Some("Hello "));
+    }
+
+    #[test]
+    fn render_caret_at_cursor() {
+        let mut app = App::new();
+        app.add_system(input_render_handler);
+        let entity = app
+            .world
+            .spawn((
+                input("Hello", 2),
+                Text::from_section("", Default::default()),
+            ))
+            .id();
+
+        app.update();
+
+        let text = app.world.get::<Text>(entity).unwrap();
+        assert_eq!(
+            text.sections
+                .iter()
+                .map(|section| section.value.as_str())
+                .collect::<String>(),
+            "He|llo"
+        );
+    }
+
+    fn rendered(input_box: InputBox) -> String {
+        let mut app = App::new();
+        app.add_system(input_render_handler);
+        let entity = app
+            .world
+            .spawn((input_box, Text::from_section("", Default::default())))
+            .id();
+
+        app.update();
+
+        app.world
+            .get::<Text>(entity)
+            .unwrap()
+            .sections
+            .iter()
+            .map(|section| section.value.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn show_placeholder_in_empty_input() {
+        assert_eq!(
+            rendered(InputBox {
+                placeholder: Some("Press Enter to chat".into()),
+                ..InputBox::new()
+            }),
+            "|Press Enter to chat"
+        );
+    }
+
+    #[test]
+    fn hide_placeholder_after_typing() {
+        assert_eq!(
+            rendered(InputBox {
+                placeholder: Some("Press Enter to chat".into()),
+                ..input("Hi", 2)
+            }),
+            "Hi|"
+        );
+    }
 }
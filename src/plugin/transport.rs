@@ -0,0 +1,460 @@
+use std::{
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bevy::prelude::{EventReader, Res};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use pubnub::core::{PubNubError, Transport, TransportMethod, TransportRequest, TransportResponse};
+
+use crate::{
+    builder::{ChatPluginConfig, CustomTransport},
+    error::Result,
+    BevyPNError,
+};
+
+use super::{events::UpdateAuthToken, resources::RawTransportResource};
+
+/// Characters encoded in the query and the path payloads, everything except the unreserved ones.
+pub(crate) const QUERY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// How long PubNub holds a subscribe request open when there are no messages.
+const LONG_POLL_WINDOW: Duration = Duration::from_secs(280);
+
+/// HTTP transport shared by the PubNub client and the raw requests of the plugin.
+///
+/// Unlike `TransportReqwest` it is configured from the [`ChatPluginConfig`].
+/// Clones share the connection pool and the auth token.
+///
+/// Requests are handed over to the `custom_transport` if there is one,
+/// the origin, proxy and timeout options are then up to it.
+#[derive(Debug, Clone)]
+pub struct ChatTransport {
+    client: reqwest::Client,
+    custom: Option<CustomTransport>,
+    origin: String,
+    timeout: Duration,
+    auth_token: Arc<RwLock<Option<String>>>,
+}
+
+impl ChatTransport {
+    pub(crate) fn new(config: &ChatPluginConfig) -> Result<Self> {
+        let client = client_builder(config)?
+            .build()
+            .map_err(|error| BevyPNError::Config {
+                message: error.to_string(),
+            })?;
+
+        Ok(Self {
+            client,
+            custom: config.custom_transport.clone(),
+            origin: config.origin.clone(),
+            timeout: config.request_timeout,
+            auth_token: Arc::new(RwLock::new(config.auth_token.clone())),
+        })
+    }
+
+    /// Replaces the auth token attached to the next requests.
+    pub(crate) fn set_auth_token(&self, token: String) {
+        *self
+            .auth_token
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(token);
+    }
+
+    /// The same transport waiting long enough for subscribe long-poll responses.
+    pub(crate) fn long_poll(self) -> Self {
+        Self {
+            timeout: subscribe_timeout(self.timeout),
+            ..self
+        }
+    }
+
+    /// The request with the current auth token attached.
+    fn authorized(&self, mut request: TransportRequest) -> TransportRequest {
+        if let Some(token) = &*self
+            .auth_token
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+        {
+            request
+                .query_parameters
+                .insert("auth".into(), token.clone());
+        }
+
+        request
+    }
+
+    fn prepare(&self, request: TransportRequest) -> reqwest::Result<reqwest::Request> {
+        let request = self.authorized(request);
+        let url = format!(
+            "https://{}/{}?{}",
+            self.origin,
+            request.path.trim_start_matches('/'),
+            encode_query(request.query_parameters.into_iter().collect())
+        );
+        let method = match request.method {
+            TransportMethod::Get => reqwest::Method::GET,
+            TransportMethod::Post => reqwest::Method::POST,
+        };
+
+        let builder = request.headers.iter().fold(
+            self.client.request(method, url),
+            |builder, (name, value)| builder.header(name, value),
+        );
+
+        let builder = match request.body {
+            Some(body) => builder.body(body),
+            None => builder,
+        };
+
+        // Browsers apply their own timeouts to `fetch`.
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = builder.timeout(self.timeout);
+
+        builder.build()
+    }
+}
+
+/// Percent-encodes the query like the PubNub SDKs, with `%20` for spaces.
+///
+/// Form encoding used by `reqwest` turns spaces into `+`,
+/// which breaks filter expressions and signatures.
+fn encode_query(mut query: Vec<(String, String)>) -> String {
+    query.sort();
+    query
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(name, QUERY_ENCODE_SET),
+                utf8_percent_encode(value, QUERY_ENCODE_SET)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Applies the latest [`UpdateAuthToken`] to the requests sent from now on.
+///
+/// Requests already in flight, like the pending subscribe, keep the previous token.
+pub fn auth_token_handler(
+    mut updates: EventReader<UpdateAuthToken>,
+    transport: Res<RawTransportResource>,
+) {
+    if let Some(UpdateAuthToken(token)) = updates.iter().last() {
+        transport.set_auth_token(token.clone());
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn client_builder(config: &ChatPluginConfig) -> Result<reqwest::ClientBuilder> {
+    let builder = reqwest::Client::builder();
+
+    match &config.proxy {
+        Some(proxy) => reqwest::Proxy::all(proxy)
+            .map(|proxy| builder.proxy(proxy))
+            .map_err(|error| BevyPNError::Config {
+                message: format!("Invalid proxy: {}", error),
+            }),
+        None => Ok(builder),
+    }
+}
+
+// Browsers route `fetch` through the system proxy themselves.
+#[cfg(target_arch = "wasm32")]
+fn client_builder(_config: &ChatPluginConfig) -> Result<reqwest::ClientBuilder> {
+    Ok(reqwest::Client::builder())
+}
+
+/// Subscribe requests are held open by PubNub, so they wait for the long-poll window on top.
+fn subscribe_timeout(request_timeout: Duration) -> Duration {
+    request_timeout + LONG_POLL_WINDOW
+}
+
+fn transport_error(error: reqwest::Error) -> PubNubError {
+    PubNubError::TransportError(error.to_string())
+}
+
+#[async_trait]
+impl Transport for ChatTransport {
+    async fn send(
+        &self,
+        request: TransportRequest,
+    ) -> std::result::Result<TransportResponse, PubNubError> {
+        if let Some(custom) = &self.custom {
+            return custom.send(self.authorized(request)).await;
+        }
+
+        let request = self.prepare(request).map_err(transport_error)?;
+        let response = self
+            .client
+            .execute(request)
+            .await
+            .map_err(transport_error)?;
+
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|value| (name.to_string(), value.to_string()))
+            })
+            .collect();
+        let body = response.bytes().await.map_err(transport_error)?;
+
+        Ok(TransportResponse {
+            status,
+            headers,
+            body: (!body.is_empty()).then(|| body.to_vec()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+    };
+
+    use bevy::prelude::App;
+    use futures_lite::future;
+
+    use crate::{
+        plugin::{
+            messages::{subscribe, subscribe_request},
+            resources::PubNubSubscribeResource,
+        },
+        ChatPlugin, Keyset,
+    };
+
+    fn config() -> ChatPluginConfig {
+        ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .origin("chat.example.com")
+            .request_timeout(Duration::from_secs(3))
+            .build()
+            .unwrap()
+            .config
+    }
+
+    fn request() -> TransportRequest {
+        TransportRequest {
+            path: "v2/subscribe/sub-c-test/lobby/0".into(),
+            query_parameters: [("tt".into(), "0".into())].into(),
+            method: TransportMethod::Get,
+            headers: [].into(),
+            body: None,
+        }
+    }
+
+    fn subscription_info() -> PubNubSubscribeResource {
+        PubNubSubscribeResource {
+            ..PubNubSubscribeResource::for_tests()
+        }
+    }
+
+    #[test]
+    fn apply_request_timeout() {
+        let transport = ChatTransport::new(&config()).unwrap();
+
+        let request = transport.prepare(request()).unwrap();
+
+        assert_eq!(request.timeout(), Some(&Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn wait_longer_for_subscribe() {
+        let transport = ChatTransport::new(&config()).unwrap().long_poll();
+
+        let request = transport.prepare(request()).unwrap();
+
+        assert_eq!(
+            request.timeout(),
+            Some(&(Duration::from_secs(3) + LONG_POLL_WINDOW))
+        );
+    }
+
+    #[test]
+    fn send_subscribe_to_configured_origin() {
+        let transport = ChatTransport::new(&config()).unwrap().long_poll();
+
+        let request = transport
+            .prepare(subscribe_request(&subscription_info()))
+            .unwrap();
+
+        assert_eq!(request.url().host_str(), Some("chat.example.com"));
+        assert_eq!(request.url().path(), "/v2/subscribe/sub-c-test/lobby/0");
+    }
+
+    fn query(request: &reqwest::Request, name: &str) -> Option<String> {
+        request
+            .url()
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    #[test]
+    fn percent_encode_filter_expression() {
+        let transport = ChatTransport::new(&config()).unwrap().long_poll();
+        let subscription_info = PubNubSubscribeResource {
+            filter: Some("context == 'lobby' && level > 3".into()),
+            ..subscription_info()
+        };
+
+        let request = transport
+            .prepare(subscribe_request(&subscription_info))
+            .unwrap();
+
+        assert!(request
+            .url()
+            .query()
+            .unwrap()
+            .contains("filter-expr=context%20%3D%3D%20%27lobby%27%20%26%26%20level%20%3E%203"));
+        assert_eq!(
+            query(&request, "filter-expr"),
+            Some("context == 'lobby' && level > 3".into())
+        );
+    }
+
+    #[test]
+    fn attach_auth_token_to_subscribe() {
+        let mut config = config();
+        config.auth_token = Some("p0F2AkF0GmQ".into());
+        let transport = ChatTransport::new(&config).unwrap().long_poll();
+
+        let request = transport
+            .prepare(subscribe_request(&subscription_info()))
+            .unwrap();
+
+        assert_eq!(query(&request, "auth"), Some("p0F2AkF0GmQ".into()));
+    }
+
+    #[test]
+    fn not_attach_auth_token_by_default() {
+        let transport = ChatTransport::new(&config()).unwrap();
+
+        let request = transport.prepare(request()).unwrap();
+
+        assert_eq!(query(&request, "auth"), None);
+    }
+
+    #[test]
+    fn replace_auth_token_on_update() {
+        let mut config = config();
+        config.auth_token = Some("expired".into());
+        let transport = ChatTransport::new(&config).unwrap();
+
+        let mut app = App::new();
+        app.insert_resource(RawTransportResource(transport.clone()))
+            .add_event::<UpdateAuthToken>()
+            .add_system(auth_token_handler);
+        app.world.send_event(UpdateAuthToken("refreshed".into()));
+        app.update();
+
+        let request = transport.prepare(request()).unwrap();
+
+        assert_eq!(query(&request, "auth"), Some("refreshed".into()));
+    }
+
+    struct FakeTransport(Arc<RwLock<Vec<TransportRequest>>>);
+
+    #[async_trait]
+    impl Transport for FakeTransport {
+        async fn send(
+            &self,
+            request: TransportRequest,
+        ) -> std::result::Result<TransportResponse, PubNubError> {
+            self.0.write().unwrap().push(request);
+
+            Ok(TransportResponse {
+                status: 200,
+                headers: [].into(),
+                body: Some(
+                    br#"{"t":{"t":"16806024912893746","r":1},"m":[{"c":"lobby","d":"Hello","i":"jane","p":{"t":"16806024912893745","r":1}}]}"#
+                        .to_vec(),
+                ),
+            })
+        }
+    }
+
+    #[test]
+    fn send_requests_with_custom_transport() {
+        let sent = Arc::new(RwLock::new(Vec::new()));
+        let config = ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .auth_token("p0F2AkF0GmQ")
+            .custom_transport(FakeTransport(sent.clone()))
+            .build()
+            .unwrap()
+            .config;
+        let transport = ChatTransport::new(&config).unwrap().long_poll();
+
+        let result = future::block_on(subscribe(&transport, &subscription_info())).unwrap();
+
+        assert_eq!(result.message_info.tt, "16806024912893746");
+        assert_eq!(result.messages[0].text(), "Hello");
+
+        let sent = sent.read().unwrap();
+        assert_eq!(sent[0].path, "v2/subscribe/sub-c-test/lobby/0");
+        assert_eq!(sent[0].query_parameters["auth"], "p0F2AkF0GmQ");
+    }
+
+    /// How long the test waits for the fake proxy to see the request, instead of hanging.
+    const PROXY_TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn send_requests_through_proxy() {
+        let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = config();
+        config.proxy = Some(format!("http://{}", proxy.local_addr().unwrap()));
+
+        let (connect, connected) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = proxy.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+            let _ = connect.send(line);
+        });
+
+        let transport = ChatTransport::new(&config).unwrap();
+        // The fake proxy hangs up after the first line, so the request itself fails.
+        let _ = future::block_on(async_compat::Compat::new(transport.send(request())));
+
+        assert!(connected
+            .recv_timeout(PROXY_TIMEOUT)
+            .unwrap()
+            .starts_with("CONNECT chat.example.com:443"));
+    }
+
+    #[test]
+    fn reject_invalid_proxy() {
+        let mut config = config();
+        config.proxy = Some("not a proxy".into());
+
+        assert!(matches!(
+            ChatTransport::new(&config),
+            Err(BevyPNError::Config { .. })
+        ));
+    }
+}
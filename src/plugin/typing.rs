@@ -0,0 +1,203 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::{
+    prelude::{Changed, EventWriter, Query, Res, ResMut, Resource},
+    time::Time,
+};
+use pubnub::core::{Transport, TransportMethod, TransportRequest};
+
+use super::{
+    events::TypingChanged,
+    resources::{ChannelResource, ChatConfig, PubNubSubscribeResource, RawTransportResource},
+    tasks::spawn_request,
+    text::InputBox,
+};
+
+/// Payload of the typing signal.
+pub const TYPING_SIGNAL: &str = "typing";
+
+/// The typing signal payload serialized to JSON and encoded for the URL path.
+const TYPING_SIGNAL_PATH: &str = "%22typing%22";
+
+/// The shortest interval between two typing signals of the local user.
+const TYPING_SIGNAL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// How long a user is shown as typing after their last signal.
+const TYPING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Debounces the typing signals of the local user.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct TypingSignal {
+    last_value: String,
+    last_sent: Option<Duration>,
+}
+
+impl TypingSignal {
+    /// Returns `true` if a signal should be sent for the new input value.
+    pub fn should_send(&mut self, value: &str, now: Duration) -> bool {
+        if value == self.last_value {
+            return false;
+        }
+        self.last_value = value.into();
+
+        let due = !value.is_empty()
+            && self.last_sent.map_or(true, |sent| {
+                now.saturating_sub(sent) >= TYPING_SIGNAL_INTERVAL
+            });
+
+        if due {
+            self.last_sent = Some(now);
+        }
+        due
+    }
+}
+
+/// Users who are typing in the chat channel.
+///
+/// It is only filled with [`typing_indicators`] enabled.
+/// A user stops typing a few seconds after their last typing signal.
+///
+/// [`typing_indicators`]: crate::builder::ChatPluginConfigBuilder::typing_indicators
+#[derive(Debug, Clone, Default, Resource)]
+pub struct TypingUsers {
+    users: HashMap<String, Duration>,
+}
+
+impl TypingUsers {
+    /// Returns `true` if the user with the given id is typing.
+    pub fn contains(&self, user_id: &str) -> bool {
+        self.users.contains_key(user_id)
+    }
+
+    /// Iterates over the ids of the typing users.
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.users.keys()
+    }
+
+    /// Records a typing signal and returns `true` if the user just started typing.
+    pub(crate) fn signal(&mut self, user_id: &str, now: Duration) -> bool {
+        self.users.insert(user_id.into(), now).is_none()
+    }
+
+    /// Removes the users whose last signal expired and returns them.
+    pub(crate) fn expire(&mut self, now: Duration) -> Vec<String> {
+        let mut expired = self
+            .users
+            .iter()
+            .filter(|(_, signaled)| now.saturating_sub(**signaled) >= TYPING_TIMEOUT)
+            .map(|(user_id, _)| user_id.clone())
+            .collect::<Vec<_>>();
+        expired.sort();
+
+        expired.iter().for_each(|user_id| {
+            self.users.remove(user_id);
+        });
+        expired
+    }
+}
+
+pub fn typing_signal_request(
+    subscription_info: &PubNubSubscribeResource,
+    publish_key: &str,
+    channel: &str,
+) -> TransportRequest {
+    TransportRequest {
+        path: format!(
+            "v1/signal/{}/{}/0/{}/0/{}",
+            publish_key, subscription_info.subscribe_key, channel, TYPING_SIGNAL_PATH
+        ),
+        query_parameters: HashMap::from([("uuid".into(), subscription_info.user_id.clone())]),
+        method: TransportMethod::Get,
+        headers: [].into(),
+        body: None,
+    }
+}
+
+pub fn typing_signal_handler(
+    time: Res<Time>,
+    config: Res<ChatConfig>,
+    channel: Res<ChannelResource>,
+    subscription_info: Res<PubNubSubscribeResource>,
+    transport: Res<RawTransportResource>,
+    mut signal: ResMut<TypingSignal>,
+    input: Query<&InputBox, Changed<InputBox>>,
+) {
+    let Some(channel) = channel.name().filter(|_| config.typing_indicators) else {
+        return;
+    };
+
+    input.iter().for_each(|input| {
+        if !signal.should_send(&input.value, time.elapsed()) {
+            return;
+        }
+
+        let request =
+            typing_signal_request(&subscription_info, &config.keyset.publish_key, channel);
+        let transport = transport.0.clone();
+
+        // A lost typing signal is not worth reporting, the next keystroke sends another one.
+        spawn_request(async move {
+            if let Err(err) = transport.send(request).await {
+                log::debug!("Typing signal failed: {:?}", err);
+            }
+        })
+        .detach();
+    });
+}
+
+pub fn typing_expiry_handler(
+    time: Res<Time>,
+    mut typing: ResMut<TypingUsers>,
+    mut changes: EventWriter<TypingChanged>,
+) {
+    changes.send_batch(
+        typing
+            .expire(time.elapsed())
+            .into_iter()
+            .map(|user_id| TypingChanged {
+                user_id,
+                typing: false,
+            }),
+    );
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn debounce_typing_signals() {
+        let mut signal = TypingSignal::default();
+
+        assert!(signal.should_send("H", Duration::ZERO));
+        assert!(!signal.should_send("He", SECOND));
+        assert!(!signal.should_send("Hel", 2 * SECOND));
+        assert!(signal.should_send("Hell", 3 * SECOND));
+    }
+
+    #[test]
+    fn not_signal_without_text_changes() {
+        let mut signal = TypingSignal::default();
+
+        assert!(!signal.should_send("", Duration::ZERO));
+        assert!(signal.should_send("Hi", Duration::ZERO));
+        assert!(!signal.should_send("Hi", 10 * SECOND));
+        assert!(!signal.should_send("", 20 * SECOND));
+    }
+
+    #[test]
+    fn expire_typing_users() {
+        let mut typing = TypingUsers::default();
+
+        assert!(typing.signal("john", Duration::ZERO));
+        assert!(typing.signal("jane", 2 * SECOND));
+        assert!(!typing.signal("john", SECOND));
+
+        assert_eq!(typing.expire(6 * SECOND), vec!["john"]);
+        assert!(!typing.contains("john"));
+        assert!(typing.contains("jane"));
+        assert_eq!(typing.expire(7 * SECOND), vec!["jane"]);
+    }
+}
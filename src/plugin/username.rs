@@ -0,0 +1,114 @@
+use bevy::prelude::{EventReader, EventWriter, Res, ResMut};
+
+use super::{
+    events::{ChatError, SetUsername},
+    messages::Resubscribe,
+    pubnub_client,
+    resources::{ChatConfig, PubNubClientResource, PubNubSubscribeResource, RawTransportResource},
+};
+
+pub fn username_handler(
+    mut requests: EventReader<SetUsername>,
+    mut config: ResMut<ChatConfig>,
+    mut pubnub: ResMut<PubNubClientResource>,
+    mut subscription_info: ResMut<PubNubSubscribeResource>,
+    transport: Res<RawTransportResource>,
+    mut resubscribe: EventWriter<Resubscribe>,
+    mut errors: EventWriter<ChatError>,
+) {
+    let latest = requests
+        .iter()
+        .filter(|SetUsername(username)| {
+            let valid = !username.is_empty();
+            if !valid {
+                log::warn!("Ignoring empty username");
+            }
+            valid
+        })
+        .last();
+
+    let Some(SetUsername(username)) = latest else {
+        return;
+    };
+
+    match pubnub_client(&config, &transport, username) {
+        Ok(client) => pubnub.0 = client,
+        Err(err) => {
+            log::error!("Username not changed: {}", err);
+            errors.send(ChatError::new("username", &err));
+            return;
+        }
+    }
+
+    config.0.username = username.clone();
+    subscription_info.user_id = username.clone();
+
+    // The subscribe request in flight still uses the previous user id.
+    resubscribe.send(Resubscribe);
+}
+
+#[cfg(test)]
+mod should {
+    use bevy::{ecs::event::Events, prelude::App};
+
+    use crate::{plugin::transport::ChatTransport, ChatPlugin, Keyset};
+
+    use super::*;
+
+    fn app() -> App {
+        let chat = ChatPlugin::builder()
+            .keyset(Keyset {
+                publish_key: "pub-c-test",
+                subscribe_key: "sub-c-test",
+            })
+            .username("john")
+            .build()
+            .unwrap();
+
+        let mut app = App::new();
+        app.insert_resource(PubNubClientResource(chat.pubnub.clone()))
+            .insert_resource(RawTransportResource(
+                ChatTransport::new(&chat.config).unwrap(),
+            ))
+            .insert_resource(PubNubSubscribeResource {
+                tt: "16806024912893745".into(),
+                tr: "1".into(),
+                ..PubNubSubscribeResource::for_tests()
+            })
+            .insert_resource(ChatConfig(chat.config))
+            .add_event::<SetUsername>()
+            .add_event::<Resubscribe>()
+            .add_event::<ChatError>()
+            .add_system(username_handler);
+        app
+    }
+
+    #[test]
+    fn update_stored_user_id() {
+        let mut app = app();
+
+        app.world.send_event(SetUsername("jane".into()));
+        app.update();
+
+        assert_eq!(
+            app.world.resource::<PubNubSubscribeResource>().user_id,
+            "jane"
+        );
+        assert_eq!(app.world.resource::<ChatConfig>().username, "jane");
+        assert_eq!(app.world.resource::<Events<Resubscribe>>().len(), 1);
+    }
+
+    #[test]
+    fn ignore_empty_username() {
+        let mut app = app();
+
+        app.world.send_event(SetUsername("".into()));
+        app.update();
+
+        assert_eq!(
+            app.world.resource::<PubNubSubscribeResource>().user_id,
+            "john"
+        );
+        assert!(app.world.resource::<Events<Resubscribe>>().is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{error::Result, BevyPNError, FilterMode};
+
+/// Applies the word filter to an outgoing message.
+///
+/// The message is masked or, in [`FilterMode::Block`], rejected if it contains a filtered word.
+pub fn filter_outgoing(message: String, words: &[String], mode: FilterMode) -> Result<String> {
+    if words.is_empty() {
+        return Ok(message);
+    }
+
+    match mode {
+        FilterMode::Mask => Ok(mask_words(&message, words)),
+        FilterMode::Block => match find_filtered(&message, words) {
+            Some(word) => Err(BevyPNError::BlockedWord { word }),
+            None => Ok(message),
+        },
+    }
+}
+
+/// Replaces every filtered word with asterisks.
+///
+/// Words are matched whole and ignoring the case, so `class` is not masked by `ass`.
+pub fn mask_words(text: &str, words: &[String]) -> String {
+    let mut masked = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some((start, end)) = next_word(rest) {
+        masked.push_str(&rest[..start]);
+
+        let word = &rest[start..end];
+        if is_filtered(word, words) {
+            masked.extend(word.chars().map(|_| '*'));
+        } else {
+            masked.push_str(word);
+        }
+
+        rest = &rest[end..];
+    }
+
+    masked.push_str(rest);
+    masked
+}
+
+/// Returns the first filtered word of the text.
+pub fn find_filtered(text: &str, words: &[String]) -> Option<String> {
+    text.split(|character: char| !character.is_alphanumeric())
+        .find(|word| is_filtered(word, words))
+        .map(Into::into)
+}
+
+fn is_filtered(word: &str, words: &[String]) -> bool {
+    !word.is_empty()
+        && words
+            .iter()
+            .any(|filtered| filtered.to_lowercase() == word.to_lowercase())
+}
+
+/// Byte range of the next alphanumeric word.
+fn next_word(text: &str) -> Option<(usize, usize)> {
+    let start = text.find(char::is_alphanumeric)?;
+    let end = text[start..]
+        .find(|character: char| !character.is_alphanumeric())
+        .map_or(text.len(), |end| start + end);
+
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+
+    use test_case::test_case;
+
+    fn words() -> Vec<String> {
+        vec!["darn".into(), "heck".into()]
+    }
+
+    #[test_case("what the heck" => "what the ****" ; "filtered word")]
+    #[test_case("HECK, darn!" => "****, ****!" ; "case insensitive")]
+    #[test_case("checking the deck" => "checking the deck" ; "only whole words")]
+    #[test_case("zażółć heck" => "zażółć ****" ; "after multibyte characters")]
+    #[test_case("" => "" ; "empty message")]
+    fn mask_filtered_words(text: &str) -> String {
+        mask_words(text, &words())
+    }
+
+    #[test]
+    fn mask_outgoing_message() {
+        assert_eq!(
+            filter_outgoing("oh heck".into(), &words(), FilterMode::Mask).unwrap(),
+            "oh ****"
+        );
+    }
+
+    #[test]
+    fn block_outgoing_message() {
+        assert!(matches!(
+            filter_outgoing("oh Heck".into(), &words(), FilterMode::Block),
+            Err(BevyPNError::BlockedWord { word }) if word == "Heck"
+        ));
+    }
+
+    #[test]
+    fn publish_clean_message_in_block_mode() {
+        assert_eq!(
+            filter_outgoing("checking in".into(), &words(), FilterMode::Block).unwrap(),
+            "checking in"
+        );
+    }
+}